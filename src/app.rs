@@ -1,4 +1,4 @@
-use crate::WindowState;
+use crate::{Session, WindowState};
 use dioxus::desktop::tao::{self, event::StartCause};
 use dioxus::desktop::UserWindowEvent;
 use egui_wgpu::Renderer;
@@ -7,6 +7,7 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
+use stream::desktop::stream::PaintEvent;
 use tao::{
     event::{Event, WindowEvent},
     event_loop::ControlFlow,
@@ -21,27 +22,50 @@ pub const DOUBLE_CLICK_THRESHOLD: Duration = Duration::from_millis(500);
 pub const DOUBLE_CLICK_DISTANCE: f64 = 5.0;
 
 impl Application {
-    pub fn set_window(&mut self, window: Arc<Window>, uri: String) {
-        self.window_state =
-            Some(pollster::block_on(WindowState::new(window, uri)).expect("unable to create window"));
+    pub fn set_window(&mut self, window: Arc<Window>, uri: String, session: Session) {
+        self.window_state = Some(
+            pollster::block_on(WindowState::new(window, uri, session))
+                .expect("unable to create window"),
+        );
+    }
+
+    pub fn set_room(&mut self, room: String) {
+        if let Some(state) = &mut self.window_state {
+            state.set_room(room);
+        }
+    }
+
+    pub fn join(&mut self, session: Session) {
+        if let Some(state) = &mut self.window_state {
+            state.join(session);
+        }
     }
 
     pub fn run_app(
         &mut self,
         event: &Event<'_, UserWindowEvent>,
         control_flow: &mut ControlFlow,
-        _is_socket_event: bool,
-        _socket_event: Option<()>,
+        is_socket_event: bool,
+        socket_event: Option<PaintEvent>,
     ) {
         let Some(state) = &mut self.window_state else {
             return;
         };
 
+        if is_socket_event {
+            if let Some(paint_event) = socket_event {
+                state.apply_paint_event(paint_event);
+            }
+            return;
+        }
+
         const CURSOR_BLINK_INTERVAL: f32 = 0.5;
         *control_flow = ControlFlow::Poll;
 
         match event {
             Event::MainEventsCleared => {
+                state.apply_incoming_events();
+                state.answer_sync_requests();
                 state.window.request_redraw();
 
                 if state.start_typing
@@ -52,7 +76,15 @@ impl Application {
                     state.window.request_redraw();
                 }
 
-                if state.show_modal_fonts || state.show_modal_colors {
+                if state.show_modal_fonts
+                    || state.show_modal_colors
+                    || state.show_modal_widths
+                    || state.show_modal_export
+                    || state.show_modal_shapes
+                    || state.show_modal_view
+                    || state.show_modal_paper
+                    || state.show_modal_slides
+                {
                     state.window.request_redraw();
                 }
             }
@@ -80,17 +112,15 @@ impl Application {
 
                 state.window.request_redraw();
             }
-            Event::NewEvents(event) => {
-                if event == &StartCause::Init {
-                    state
-                        .surface
-                        .configure(&state.device, &state.surface_config);
+            Event::NewEvents(event) if event == &StartCause::Init => {
+                state
+                    .surface
+                    .configure(&state.device, &state.surface_config);
 
-                    state.egui_renderer =
-                        Renderer::new(&state.device, state.surface_config.format, None, 1, true);
+                state.egui_renderer =
+                    Renderer::new(&state.device, state.surface_config.format, None, 1, true);
 
-                    state.window.request_redraw();
-                }
+                state.window.request_redraw();
             }
             Event::RedrawRequested(window_id) => {
                 if state.window.id() != *window_id {
@@ -99,8 +129,8 @@ impl Application {
                 state.viewport.update(
                     &state.queue,
                     Resolution {
-                        width: state.size.width,
-                        height: state.size.height,
+                        width: state.surface_config.width,
+                        height: state.surface_config.height,
                     },
                 );
                 let _ = state.update();
@@ -112,6 +142,7 @@ impl Application {
                     }
                     Err(e) => eprintln!("{:?}", e),
                 }
+                state.run_pending_dialogs();
             }
             Event::LoopDestroyed => *control_flow = ControlFlow::Exit,
             Event::Opened { .. } => {
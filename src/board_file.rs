@@ -0,0 +1,429 @@
+//! Native `.painter` board files.
+//!
+//! A board file is UTF-8 JSON:
+//!
+//! ```text
+//! {
+//!   "version": 2,
+//!   "metadata": { "title": "Lesson 3", "created": 1730000000 },
+//!   "pages": [
+//!     { "id": "<uuid>", "paper": { "kind": "Ruled", "color": [r, g, b, a] },
+//!       "slide": { "center": [x, y], "size": [w, h], "rotation": 0, "hash": "<sha256>" }, "actions": [
+//!       { "id": "<uuid>", "action_type": { "Stroke": { "color": [r, g, b, a], "width": 3,
+//!         "points": [[x, y], [x, y], [x, y], [x, y]], "pressure": [0.8, 0.6], "join": "Round" } } },
+//!       { "id": "<uuid>", "action_type": { "Text": { "position": [x, y], "color": [r, g, b, a],
+//!         "text": "...", "bounds": { "x": 0, "y": 0, "width": 0, "height": 0 }, "font_size": 16 } } },
+//!       { "id": "<uuid>", "action_type": { "Shapes": { "first": [x, y], "last": [x, y], "color": [r, g, b, a],
+//!         "fill": [r, g, b, a], "outline_width": 1, "corner_radius": 0 } } },
+//!       { "id": "<uuid>", "action_type": { "Ellipse": { "first": [x, y], "last": [x, y], "color": [r, g, b, a],
+//!         "fill": [r, g, b, a], "outline_width": 1 } } },
+//!       { "id": "<uuid>", "action_type": { "Line": { "first": [x, y], "last": [x, y], "color": [r, g, b, a],
+//!         "width": 2, "heads": "End" } } },
+//!       { "id": "<uuid>", "action_type": { "Polygon": { "points": [[x, y], [x, y], [x, y]],
+//!         "color": [r, g, b, a], "fill": [r, g, b, a], "outline_width": 1 } } },
+//!       { "id": "<uuid>", "action_type": { "Image": { "center": [x, y], "size": [w, h],
+//!         "rotation": 0, "hash": "<sha256>" } } }
+//!     ] }
+//!   ],
+//!   "images": { "<sha256>": "<base64>" }
+//! }
+//! ```
+//!
+//! Pages are stored in board order and the actions of each page in paint
+//! order; a page without a slide has `null` for it. Strokes are cubic Bézier
+//! control points with one pressure per end point. Stroke, shape and image
+//! positions are NDC of the 1920 x 1080 board, text positions, font sizes,
+//! image sizes, stroke and outline widths and corner radii are units of that
+//! board and `created` is in seconds since the Unix epoch. Paper colors are
+//! normalized RGBA and image rotations radians. Each image file is stored
+//! once, base64 encoded under its SHA-256, for slides and images alike. Files
+//! written by an older version are upgraded step by step through `MIGRATIONS`
+//! before they are deserialized.
+
+use crate::{
+    pages::Page,
+    paper::Paper,
+    structs::{Action, Image, LineJoin},
+    sync, utils, ActionType, WindowState,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+pub const BOARD_FILE_VERSION: u64 = 2;
+pub const BOARD_FILE_EXTENSION: &str = "painter";
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` file to version `n + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[pages_and_styles];
+
+/// Version 1 boards were a single list of polyline strokes, hollow
+/// rectangles and text, measured on the window they were saved from. They
+/// become one plain white page with the nil id every board starts with:
+/// strokes turn into 1 px wide straight Bézier segments with round joins,
+/// colored like their first vertex, rectangles get a 1 px outline, no fill
+/// and square corners, and pixel values are kept as board pixels, which is
+/// exact for a full HD window.
+fn pages_and_styles(value: &mut Value) {
+    let mut actions = value
+        .as_object_mut()
+        .and_then(|object| object.remove("actions"))
+        .unwrap_or_else(|| Value::Array(Vec::new()));
+    for action in actions.as_array_mut().into_iter().flatten() {
+        let Some(action_type) = action.get_mut("action_type") else {
+            continue;
+        };
+        if let Some(stroke) = action_type.get_mut("Stroke") {
+            *stroke = stroke_from_vertices(stroke);
+        }
+        if let Some(rectangle) = action_type.get_mut("Shapes") {
+            rectangle["fill"] = serde_json::json!([0.0, 0.0, 0.0, 0.0]);
+            rectangle["outline_width"] = 1.0.into();
+            rectangle["corner_radius"] = 0.0.into();
+        }
+    }
+
+    value["pages"] = serde_json::json!([{
+        "id": Uuid::nil(),
+        "paper": Paper::default(),
+        "slide": null,
+        "actions": actions,
+    }]);
+    value["images"] = serde_json::json!({});
+}
+
+/// Joins the vertices of a version 1 stroke with straight Bézier segments.
+fn stroke_from_vertices(vertices: &Value) -> Value {
+    let vertices = vertices.as_array().map(Vec::as_slice).unwrap_or_default();
+    let color = vertices.first().map_or_else(
+        || serde_json::json!([0.0, 0.0, 0.0, 1.0]),
+        |vertex| vertex["color"].clone(),
+    );
+
+    let positions: Vec<[f64; 2]> = vertices
+        .iter()
+        .filter_map(|vertex| {
+            let position = vertex["position"].as_array()?;
+            Some([position.first()?.as_f64()?, position.get(1)?.as_f64()?])
+        })
+        .collect();
+    let mut points = Vec::with_capacity(positions.len() * 3);
+    for (index, point) in positions.iter().enumerate() {
+        if let Some(previous) = index.checked_sub(1).map(|index| positions[index]) {
+            let third = [
+                (point[0] - previous[0]) / 3.0,
+                (point[1] - previous[1]) / 3.0,
+            ];
+            points.push([previous[0] + third[0], previous[1] + third[1]]);
+            points.push([point[0] - third[0], point[1] - third[1]]);
+        }
+        points.push(*point);
+    }
+
+    serde_json::json!({
+        "color": color,
+        "width": 1.0,
+        "points": points,
+        "pressure": [],
+        "join": LineJoin::Round,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardFileCommand {
+    Open,
+    Save,
+}
+
+#[derive(Debug)]
+pub enum BoardFileError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoardMetadata {
+    pub title: String,
+    pub created: u64,
+}
+
+impl BoardMetadata {
+    pub fn new(title: String) -> Self {
+        BoardMetadata {
+            title,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardPage {
+    pub id: Uuid,
+    pub paper: Paper,
+    pub slide: Option<Image>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BoardFile {
+    pub version: u64,
+    pub metadata: BoardMetadata,
+    pub pages: Vec<BoardPage>,
+    /// Base64 of the image files the pages show, by SHA-256.
+    pub images: BTreeMap<String, String>,
+}
+
+impl BoardFile {
+    pub fn new(
+        metadata: BoardMetadata,
+        pages: Vec<BoardPage>,
+        images: BTreeMap<String, String>,
+    ) -> Self {
+        BoardFile {
+            version: BOARD_FILE_VERSION,
+            metadata,
+            pages,
+            images,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, BoardFileError> {
+        serde_json::to_string_pretty(self).map_err(BoardFileError::Json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, BoardFileError> {
+        let value = serde_json::from_str(json).map_err(BoardFileError::Json)?;
+        serde_json::from_value(migrate(value)?).map_err(BoardFileError::Json)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BoardFileError> {
+        std::fs::write(path, self.to_json()?).map_err(BoardFileError::Io)
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BoardFileError> {
+        Self::from_json(&std::fs::read_to_string(path).map_err(BoardFileError::Io)?)
+    }
+}
+
+fn migrate(mut value: Value) -> Result<Value, BoardFileError> {
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    if version == 0 || version > BOARD_FILE_VERSION {
+        return Err(BoardFileError::UnsupportedVersion(version));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(&mut value);
+    }
+    value["version"] = BOARD_FILE_VERSION.into();
+    Ok(value)
+}
+
+impl WindowState {
+    pub fn save_board(&self, path: impl AsRef<Path>) -> Result<(), BoardFileError> {
+        let pages = self
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(index, page)| BoardPage {
+                id: page.id,
+                paper: page.paper,
+                slide: page.slide.clone(),
+                actions: self.page_scene(index).committed().cloned().collect(),
+            })
+            .collect::<Vec<BoardPage>>();
+        let images = pages
+            .iter()
+            .flat_map(|page| {
+                let actions = page
+                    .actions
+                    .iter()
+                    .filter_map(|action| match &action.action_type {
+                        ActionType::Image(image) => Some(image),
+                        _ => None,
+                    });
+                page.slide.iter().chain(actions)
+            })
+            .filter_map(|image| {
+                let stored = self.images.get(&image.hash)?;
+                Some((image.hash.clone(), utils::base64_encode(&stored.bytes)))
+            })
+            .collect();
+        BoardFile::new(self.metadata.clone(), pages, images).save(path)
+    }
+
+    /// Replaces the board with the content of a board file, opened on its
+    /// first page, and shares it with the room in place of the old one.
+    pub fn open_board(&mut self, path: impl AsRef<Path>) -> Result<(), BoardFileError> {
+        let board_file = BoardFile::open(path)?;
+
+        self.send_board_reset();
+        self.clear_board();
+        self.metadata = board_file.metadata;
+        for encoded in board_file.images.values() {
+            let Some(bytes) = utils::base64_decode(encoded) else {
+                continue;
+            };
+            if let Err(e) = self.images.insert(bytes) {
+                eprintln!("{:?}", e);
+            }
+        }
+        if !board_file.pages.is_empty() {
+            self.pages = board_file
+                .pages
+                .into_iter()
+                .map(|board_page| {
+                    let mut page = Page::new(board_page.id);
+                    page.paper = board_page.paper;
+                    page.slide = board_page.slide;
+                    for action in board_page.actions {
+                        page.scene.insert(action);
+                    }
+                    page
+                })
+                .collect();
+            std::mem::swap(&mut self.scene, &mut self.pages[0].scene);
+        }
+        self.send_pages(true);
+        self.actions_changed = true;
+        self.window.request_redraw();
+        Ok(())
+    }
+
+    /// Deletes the shared actions and the pages of this board for the room,
+    /// so that what the others have doesn't outlive it.
+    fn send_board_reset(&self) {
+        let pages = self.pages.iter().enumerate().map(|(index, page)| {
+            // The actions of the current page are in `scene` rather than on
+            // the page.
+            let scene = if index == self.current_page {
+                &self.scene
+            } else {
+                &page.scene
+            };
+            let shared = scene
+                .committed()
+                .filter(|action| self.events_id.contains(&action.id))
+                .collect();
+            (page.id, shared)
+        });
+        self.send_paint_events(sync::board_reset_events(&self.room, pages));
+    }
+
+    /// Asks for a path with the native file dialog and opens or saves the board.
+    pub(crate) fn run_board_file_command(&mut self, command: BoardFileCommand) {
+        let dialog = rfd::FileDialog::new().add_filter("Painter board", &[BOARD_FILE_EXTENSION]);
+        let result = match command {
+            BoardFileCommand::Open => match dialog.pick_file() {
+                Some(path) => self.open_board(path),
+                None => return,
+            },
+            BoardFileCommand::Save => {
+                let file_name = format!("{}.{}", self.metadata.title, BOARD_FILE_EXTENSION);
+                match dialog.set_file_name(file_name).save_file() {
+                    Some(path) => self.save_board(path),
+                    None => return,
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            eprintln!("{:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION_1: &str = r#"{
+        "version": 1,
+        "metadata": { "title": "Lesson 3", "created": 1730000000 },
+        "actions": [
+            { "id": "5c0f2d6e-8a43-4a55-9a0e-6c1e0f7b1d01", "action_type": { "Stroke": [
+                { "position": [0.0, 0.0], "color": [1.0, 0.0, 0.0, 1.0] },
+                { "position": [0.3, 0.6], "color": [0.0, 1.0, 0.0, 1.0] }
+            ] } },
+            { "id": "5c0f2d6e-8a43-4a55-9a0e-6c1e0f7b1d02", "action_type": { "Text": {
+                "position": [100.0, 200.0], "color": [0, 0, 0, 255], "text": "hello",
+                "bounds": { "x": 0.0, "y": 0.0, "width": 40.0, "height": 20.0 }, "font_size": 16
+            } } },
+            { "id": "5c0f2d6e-8a43-4a55-9a0e-6c1e0f7b1d03", "action_type": { "Shapes": {
+                "first": [-0.5, -0.5], "last": [0.5, 0.5], "color": [0.0, 0.0, 1.0, 1.0]
+            } } }
+        ]
+    }"#;
+
+    #[test]
+    fn opens_version_1_files() {
+        let board_file = BoardFile::from_json(VERSION_1).unwrap();
+        assert_eq!(board_file.version, BOARD_FILE_VERSION);
+        assert_eq!(board_file.metadata.title, "Lesson 3");
+        assert!(board_file.images.is_empty());
+
+        let [page] = board_file.pages.as_slice() else {
+            panic!("expected a single page");
+        };
+        assert_eq!(page.id, Uuid::nil());
+        assert_eq!(page.paper, Paper::default());
+        assert!(page.slide.is_none());
+        assert_eq!(page.actions.len(), 3);
+
+        let ActionType::Stroke(stroke) = &page.actions[0].action_type else {
+            panic!("expected a stroke");
+        };
+        assert_eq!(stroke.color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(stroke.width, 1.0);
+        assert!(stroke.pressure.is_empty());
+        assert_eq!(stroke.join, LineJoin::Round);
+        assert_eq!(stroke.points.len(), 4);
+        assert_eq!(stroke.points[0], [0.0, 0.0]);
+        assert_eq!(stroke.points[3], [0.3, 0.6]);
+        assert!((stroke.points[1][0] - 0.1).abs() < 1e-6);
+        assert!((stroke.points[2][1] - 0.4).abs() < 1e-6);
+
+        let ActionType::Text(text) = &page.actions[1].action_type else {
+            panic!("expected text");
+        };
+        assert_eq!(text.text, "hello");
+        assert_eq!(text.position, [100.0, 200.0]);
+
+        let ActionType::Shapes(rectangle) = &page.actions[2].action_type else {
+            panic!("expected a rectangle");
+        };
+        assert_eq!(rectangle.fill, [0.0; 4]);
+        assert_eq!(rectangle.outline_width, 1.0);
+        assert_eq!(rectangle.corner_radius, 0.0);
+    }
+
+    #[test]
+    fn round_trips_the_current_version() {
+        let board_file = BoardFile::from_json(VERSION_1).unwrap();
+        let reopened = BoardFile::from_json(&board_file.to_json().unwrap()).unwrap();
+        assert_eq!(reopened.pages.len(), 1);
+        assert_eq!(reopened.pages[0].actions.len(), 3);
+        assert_eq!(reopened.metadata, board_file.metadata);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        for version in [0, BOARD_FILE_VERSION + 1] {
+            let json = format!(r#"{{ "version": {version} }}"#);
+            assert!(matches!(
+                BoardFile::from_json(&json),
+                Err(BoardFileError::UnsupportedVersion(found)) if found == version
+            ));
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use crate::{
+    export, geometry,
+    structs::{Rect, Vertex},
+    WindowState,
+};
+use dioxus::desktop::tao::{
+    dpi::PhysicalPosition,
+    event::{MouseButton, MouseScrollDelta},
+    keyboard::Key,
+};
+use std::f32::consts::TAU;
+
+/// The board every client draws on, in board pixels: logical units that
+/// don't depend on the window or its DPI. Windows of any size show it scaled
+/// to fit, so positions and sizes mean the same everywhere.
+pub const BOARD_SIZE: [f32; 2] = [1920.0, 1080.0];
+/// Color of the bars around the board where the window is wider or taller.
+pub(crate) const LETTERBOX_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.85,
+    g: 0.85,
+    b: 0.85,
+    a: 1.0,
+};
+
+/// Zoom factor of the zoom in and out buttons.
+pub(crate) const ZOOM_STEP: f32 = 1.25;
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10.0;
+/// Zoom factor of one wheel line with Ctrl held.
+const WHEEL_ZOOM_STEP: f32 = 1.1;
+/// Window pixels one wheel line scrolls the board by.
+const PIXELS_PER_LINE: f32 = 40.0;
+/// Space left around the board content by zoom to fit, in screen pixels.
+const FIT_MARGIN: f32 = 32.0;
+
+/// Where the board is looked at from. Actions are stored in world
+/// coordinates, the board pixels of an unmoved view; the camera turns them by
+/// `rotation` radians and scales them by `zoom` around the world origin, then
+/// moves them by `offset` board pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub offset: [f32; 2],
+    pub zoom: f32,
+    pub rotation: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            offset: [0.0, 0.0],
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn to_screen(&self, point: [f32; 2]) -> [f32; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            self.zoom * (cos * point[0] - sin * point[1]) + self.offset[0],
+            self.zoom * (sin * point[0] + cos * point[1]) + self.offset[1],
+        ]
+    }
+
+    pub fn to_world(&self, point: [f32; 2]) -> [f32; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let x = (point[0] - self.offset[0]) / self.zoom;
+        let y = (point[1] - self.offset[1]) / self.zoom;
+        [cos * x + sin * y, cos * y - sin * x]
+    }
+
+    pub fn pan(&mut self, delta: [f32; 2]) {
+        self.offset[0] += delta[0];
+        self.offset[1] += delta[1];
+    }
+
+    /// Multiplies the zoom by `factor`, keeping what is under the screen
+    /// point `anchor` in place.
+    pub fn zoom_about(&mut self, anchor: [f32; 2], factor: f32) {
+        let world = self.to_world(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.keep(world, anchor);
+    }
+
+    /// Turns the view by `angle` radians around the screen point `anchor`.
+    pub fn rotate_about(&mut self, anchor: [f32; 2], angle: f32) {
+        let world = self.to_world(anchor);
+        self.rotation = (self.rotation + angle).rem_euclid(TAU);
+        self.keep(world, anchor);
+    }
+
+    /// An unrotated view that shows all of `bounds`, in world coordinates,
+    /// centered on a view of `screen_size` board pixels.
+    pub fn fit(bounds: &Rect, screen_size: [f32; 2]) -> Self {
+        let zoom = ((screen_size[0] - 2.0 * FIT_MARGIN) / bounds.width.max(1.0))
+            .min((screen_size[1] - 2.0 * FIT_MARGIN) / bounds.height.max(1.0))
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        let center = [
+            bounds.x + bounds.width / 2.0,
+            bounds.y + bounds.height / 2.0,
+        ];
+        Camera {
+            offset: [
+                screen_size[0] / 2.0 - zoom * center[0],
+                screen_size[1] / 2.0 - zoom * center[1],
+            ],
+            zoom,
+            rotation: 0.0,
+        }
+    }
+
+    /// Moves the view so the world point `world` is at the screen point `screen`.
+    fn keep(&mut self, world: [f32; 2], screen: [f32; 2]) {
+        let moved = self.to_screen(world);
+        self.pan([screen[0] - moved[0], screen[1] - moved[1]]);
+    }
+
+    /// Two triangles in world NDC covering the whole view of a board of
+    /// `board_size`, whichever way the camera looks at it.
+    pub(crate) fn view_quad(&self, board_size: [f32; 2], color: [f32; 4]) -> Vec<Vertex> {
+        let [width, height] = board_size;
+        let corners = [[0.0, 0.0], [width, 0.0], [width, height], [0.0, height]]
+            .map(|corner| export::board_to_ndc(self.to_world(corner), board_size));
+        [0, 1, 2, 0, 2, 3]
+            .into_iter()
+            .map(|index| Vertex {
+                position: corners[index],
+                color,
+            })
+            .collect()
+    }
+
+    /// The column-major matrix the shaders take vertices from world NDC to
+    /// clip space with, on a board of `board_size` filling the viewport.
+    pub(crate) fn matrix(&self, board_size: [f32; 2]) -> [[f32; 4]; 4] {
+        let clip = |ndc: [f32; 2]| {
+            let screen = self.to_screen(export::ndc_to_board(ndc, board_size));
+            export::board_to_ndc(screen, board_size)
+        };
+        let origin = clip([0.0, 0.0]);
+        let x = clip([1.0, 0.0]);
+        let y = clip([0.0, 1.0]);
+        [
+            [x[0] - origin[0], x[1] - origin[1], 0.0, 0.0],
+            [y[0] - origin[0], y[1] - origin[1], 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [origin[0], origin[1], 0.0, 1.0],
+        ]
+    }
+}
+
+/// Where the board sits in a window: scaled to fit and centered, leaving
+/// bars on the sides that don't match its aspect ratio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Letterbox {
+    /// Top left corner of the board in window pixels.
+    pub origin: [f32; 2],
+    /// Window pixels per board pixel.
+    pub scale: f32,
+}
+
+impl Letterbox {
+    pub(crate) fn new(window_size: [f32; 2]) -> Self {
+        let scale = (window_size[0] / BOARD_SIZE[0])
+            .min(window_size[1] / BOARD_SIZE[1])
+            .max(f32::EPSILON);
+        Letterbox {
+            origin: [
+                (window_size[0] - BOARD_SIZE[0] * scale) / 2.0,
+                (window_size[1] - BOARD_SIZE[1] * scale) / 2.0,
+            ],
+            scale,
+        }
+    }
+
+    pub(crate) fn to_window(self, point: [f32; 2]) -> [f32; 2] {
+        [
+            self.origin[0] + point[0] * self.scale,
+            self.origin[1] + point[1] * self.scale,
+        ]
+    }
+
+    pub(crate) fn to_board(self, point: [f32; 2]) -> [f32; 2] {
+        [
+            (point[0] - self.origin[0]) / self.scale,
+            (point[1] - self.origin[1]) / self.scale,
+        ]
+    }
+
+    /// The board area of a render pass on a target of `target_size`: drawing
+    /// is mapped into it and cut off at its edges. The bars are left as they
+    /// were cleared to.
+    pub(crate) fn clip_pass(self, render_pass: &mut wgpu::RenderPass, target_size: [u32; 2]) {
+        let Some([left, top, width, height]) = self.board_area(target_size) else {
+            return;
+        };
+        render_pass.set_viewport(left, top, width, height, 0.0, 1.0);
+        render_pass.set_scissor_rect(
+            left as u32,
+            top as u32,
+            (width as u32).max(1),
+            (height as u32).max(1),
+        );
+    }
+
+    /// The part of a target of `target_size` the board shows in, as left,
+    /// top, width and height in pixels. `None` when none of it is visible.
+    pub(crate) fn board_area(self, target_size: [u32; 2]) -> Option<[f32; 4]> {
+        let [left, top] = [self.origin[0].max(0.0), self.origin[1].max(0.0)];
+        let width = (BOARD_SIZE[0] * self.scale).min(target_size[0] as f32 - left);
+        let height = (BOARD_SIZE[1] * self.scale).min(target_size[1] as f32 - top);
+        (width > 0.0 && height > 0.0).then_some([left, top, width, height])
+    }
+}
+
+impl WindowState {
+    /// Where the board sits on the surface. Pointer positions, text and
+    /// everything drawn are all placed through it.
+    pub(crate) fn letterbox(&self) -> Letterbox {
+        Letterbox::new([
+            self.surface_config.width as f32,
+            self.surface_config.height as f32,
+        ])
+    }
+
+    /// The world position under a pointer at the window `position`.
+    pub(crate) fn world_position(&self, position: PhysicalPosition<f64>) -> PhysicalPosition<f64> {
+        let view = self
+            .letterbox()
+            .to_board([position.x as f32, position.y as f32]);
+        let [x, y] = self.camera.to_world(view);
+        PhysicalPosition::new(x as f64, y as f64)
+    }
+
+    /// The middle of the board view, which the view buttons zoom and turn
+    /// around.
+    pub(crate) fn screen_center(&self) -> [f32; 2] {
+        let [width, height] = self.board_size();
+        [width / 2.0, height / 2.0]
+    }
+
+    /// Starts or ends a pan on a middle button drag, or a left button drag
+    /// with Space held. Returns whether the button was taken by the pan.
+    pub(crate) fn pan_button(&mut self, button: MouseButton, pressed: bool) -> bool {
+        let space = self.editing_text.is_none() && self.pressed_keys.contains(&Key::Space);
+        match button {
+            MouseButton::Middle | MouseButton::Left if !pressed && self.pan_anchor.is_some() => {
+                self.pan_anchor = None;
+                true
+            }
+            MouseButton::Middle => {
+                self.pan_anchor = pressed.then_some(self.last_cursor_position);
+                true
+            }
+            MouseButton::Left if pressed && space => {
+                self.pan_anchor = Some(self.last_cursor_position);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drags the board along with the pointer while panning. Returns whether
+    /// a pan is in progress.
+    pub(crate) fn pan_to(&mut self, position: PhysicalPosition<f64>) -> bool {
+        let Some(anchor) = self.pan_anchor.replace(position) else {
+            return false;
+        };
+        let scale = self.letterbox().scale;
+        self.camera.pan([
+            (position.x - anchor.x) as f32 / scale,
+            (position.y - anchor.y) as f32 / scale,
+        ]);
+        true
+    }
+
+    /// Zooms around the pointer with Ctrl held, and scrolls the board otherwise.
+    pub(crate) fn scroll(&mut self, delta: MouseScrollDelta) {
+        let [x, y] = match delta {
+            MouseScrollDelta::LineDelta(x, y) => [x * PIXELS_PER_LINE, y * PIXELS_PER_LINE],
+            MouseScrollDelta::PixelDelta(position) => [position.x as f32, position.y as f32],
+            _ => return,
+        };
+
+        let letterbox = self.letterbox();
+        if self.raw_input.modifiers.ctrl {
+            let cursor = self.last_cursor_position;
+            self.camera.zoom_about(
+                letterbox.to_board([cursor.x as f32, cursor.y as f32]),
+                WHEEL_ZOOM_STEP.powf(y / PIXELS_PER_LINE),
+            );
+        } else {
+            self.camera.pan([x / letterbox.scale, y / letterbox.scale]);
+        }
+    }
+
+    /// Frames everything on the board. An empty board resets the view.
+    pub(crate) fn zoom_to_fit(&mut self) {
+        let board_size = self.board_size();
+        let mut points = Vec::new();
+        for action in self.scene.committed() {
+            points.extend(geometry::outline_points(
+                action,
+                board_size,
+                &self.camera,
+                &mut self.font_system,
+            ));
+        }
+        self.camera = match geometry::bounds(points) {
+            Some(bounds) => Camera::fit(&bounds, board_size),
+            None => Camera::default(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-2 && (a[1] - b[1]).abs() < 1e-2
+    }
+
+    fn turned() -> Camera {
+        Camera {
+            offset: [120.0, -40.0],
+            zoom: 2.5,
+            rotation: 0.7,
+        }
+    }
+
+    #[test]
+    fn maps_screen_and_world_both_ways() {
+        let camera = turned();
+        for point in [[0.0, 0.0], [300.0, 200.0], [-50.0, 900.0]] {
+            assert!(close(camera.to_world(camera.to_screen(point)), point));
+        }
+
+        let quarter = Camera {
+            rotation: FRAC_PI_2,
+            ..Camera::default()
+        };
+        assert!(close(quarter.to_screen([10.0, 0.0]), [0.0, 10.0]));
+    }
+
+    #[test]
+    fn zooms_about_the_pointer() {
+        let mut camera = turned();
+        let anchor = [640.0, 360.0];
+        let under = camera.to_world(anchor);
+        camera.zoom_about(anchor, ZOOM_STEP);
+        assert!((camera.zoom - 2.5 * ZOOM_STEP).abs() < 1e-5);
+        assert!(close(camera.to_world(anchor), under));
+
+        camera.zoom_about(anchor, 1000.0);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+        camera.zoom_about(anchor, 0.0);
+        assert_eq!(camera.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn rotates_about_the_pointer() {
+        let mut camera = turned();
+        let anchor = [200.0, 500.0];
+        let under = camera.to_world(anchor);
+        camera.rotate_about(anchor, -1.0);
+        assert!((camera.rotation - (0.7 - 1.0 + TAU)).abs() < 1e-5);
+        assert!(close(camera.to_world(anchor), under));
+    }
+
+    #[test]
+    fn fits_bounds_in_the_middle_of_the_view() {
+        let bounds = Rect {
+            x: 100.0,
+            y: 100.0,
+            width: 400.0,
+            height: 100.0,
+        };
+        let camera = Camera::fit(&bounds, BOARD_SIZE);
+        assert_eq!(camera.rotation, 0.0);
+        assert!(close(
+            camera.to_screen([300.0, 150.0]),
+            [BOARD_SIZE[0] / 2.0, BOARD_SIZE[1] / 2.0]
+        ));
+        assert!((camera.to_screen([100.0, 0.0])[0] - FIT_MARGIN).abs() < 1e-2);
+    }
+
+    #[test]
+    fn matrix_places_vertices_where_the_camera_shows_them() {
+        let camera = turned();
+        let matrix = camera.matrix(BOARD_SIZE);
+        let world = [300.0, 200.0];
+        let [x, y] = export::board_to_ndc(world, BOARD_SIZE);
+        let clip = [
+            matrix[0][0] * x + matrix[1][0] * y + matrix[3][0],
+            matrix[0][1] * x + matrix[1][1] * y + matrix[3][1],
+        ];
+        assert!(close(
+            export::ndc_to_board(clip, BOARD_SIZE),
+            camera.to_screen(world)
+        ));
+    }
+
+    #[test]
+    fn letterboxes_wide_and_tall_windows() {
+        let wide = Letterbox::new([2000.0, 540.0]);
+        assert_eq!(wide.scale, 0.5);
+        assert!(close(wide.origin, [520.0, 0.0]));
+        assert!(close(wide.to_window([0.0, 0.0]), [520.0, 0.0]));
+        assert!(close(wide.to_window(BOARD_SIZE), [1480.0, 540.0]));
+
+        let tall = Letterbox::new([960.0, 1000.0]);
+        assert!(close(tall.origin, [0.0, 230.0]));
+        for point in [[0.0, 0.0], [960.0, 540.0], [1900.0, 20.0]] {
+            assert!(close(tall.to_board(tall.to_window(point)), point));
+        }
+    }
+
+    #[test]
+    fn clips_drawing_to_the_board_area() {
+        let letterbox = Letterbox::new([2000.0, 540.0]);
+        assert_eq!(
+            letterbox.board_area([2000, 540]),
+            Some([520.0, 0.0, 960.0, 540.0])
+        );
+        // A target smaller than the window cuts the board off.
+        assert_eq!(
+            letterbox.board_area([1000, 300]),
+            Some([520.0, 0.0, 480.0, 300.0])
+        );
+        assert_eq!(letterbox.board_area([500, 540]), None);
+    }
+}
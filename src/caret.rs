@@ -0,0 +1,493 @@
+use crate::{
+    export, render,
+    structs::{TextEntries, Vertex},
+    utils, WindowState,
+};
+use dioxus::desktop::tao::keyboard::Key;
+use glyphon::Buffer;
+use std::{ops::Range, time::Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Color of the boxes around selected text.
+const SELECTION_COLOR: [f32; 4] = [0.2, 0.5, 1.0, 1.0];
+/// How far around its glyphs a click still lands on the text being edited,
+/// as a share of the font size.
+const CLICK_MARGIN: f32 = 0.5;
+
+/// Where typing goes in the text being edited, as byte offsets into its
+/// text. `anchor` is the other end of the selection and equals `position`
+/// when nothing is selected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextCaret {
+    pub position: usize,
+    pub anchor: usize,
+    /// Shows the caret before the character after `position` rather than
+    /// after the one before it. The two are apart where the text changes
+    /// direction.
+    pub leading: bool,
+}
+
+/// A place the caret can stand in laid out text, in board pixels from the
+/// text position.
+#[derive(Clone, Copy, Debug)]
+struct Stop {
+    line: usize,
+    line_y: f32,
+    x: f32,
+    index: usize,
+    leading: bool,
+    rtl: bool,
+}
+
+impl TextCaret {
+    pub fn at(index: usize) -> Self {
+        TextCaret {
+            position: index,
+            anchor: index,
+            leading: false,
+        }
+    }
+
+    pub fn selection(&self) -> Range<usize> {
+        self.position.min(self.anchor)..self.position.max(self.anchor)
+    }
+
+    /// Keeps both ends on character boundaries of `text`, which may have
+    /// changed under the caret.
+    fn clamp(&mut self, text: &str) {
+        let clamp = |mut index: usize| {
+            index = index.min(text.len());
+            while !text.is_char_boundary(index) {
+                index -= 1;
+            }
+            index
+        };
+        self.position = clamp(self.position);
+        self.anchor = clamp(self.anchor);
+    }
+
+    /// Replaces the selection in `text` with `inserted` and puts the caret
+    /// after it.
+    pub(crate) fn insert(&mut self, text: &mut String, inserted: &str) {
+        self.clamp(text);
+        let selection = self.selection();
+        text.replace_range(selection.clone(), inserted);
+        *self = TextCaret::at(selection.start + inserted.len());
+    }
+
+    /// Removes the selection from `text`, or the character or word before
+    /// the caret, or after it when `forward`.
+    pub(crate) fn delete(&mut self, text: &mut String, forward: bool, word: bool) {
+        self.clamp(text);
+        let mut range = self.selection();
+        if range.is_empty() {
+            range = match (forward, word) {
+                (false, false) => previous_grapheme(text, self.position)..self.position,
+                (false, true) => previous_word(text, self.position)..self.position,
+                (true, false) => self.position..next_grapheme(text, self.position),
+                (true, true) => self.position..next_word(text, self.position),
+            };
+        }
+        text.replace_range(range.clone(), "");
+        *self = TextCaret::at(range.start);
+    }
+
+    fn select_all(&mut self, text: &str) {
+        *self = TextCaret {
+            position: text.len(),
+            anchor: 0,
+            leading: false,
+        };
+    }
+
+    /// Moves to `index`, keeping the anchor when `extend`ing the selection.
+    fn move_to(&mut self, index: usize, leading: bool, extend: bool) {
+        self.position = index;
+        self.leading = leading;
+        if !extend {
+            self.anchor = index;
+        }
+    }
+}
+
+fn previous_grapheme(text: &str, index: usize) -> usize {
+    text[..index]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(start, _)| start)
+}
+
+fn next_grapheme(text: &str, index: usize) -> usize {
+    text[index..]
+        .graphemes(true)
+        .next()
+        .map_or(text.len(), |grapheme| index + grapheme.len())
+}
+
+/// The start of the word before `index`.
+fn previous_word(text: &str, index: usize) -> usize {
+    text.unicode_word_indices()
+        .map(|(start, _)| start)
+        .rfind(|&start| start < index)
+        .unwrap_or(0)
+}
+
+/// The end of the word after `index`.
+fn next_word(text: &str, index: usize) -> usize {
+    text.unicode_word_indices()
+        .map(|(start, word)| start + word.len())
+        .find(|&end| end > index)
+        .unwrap_or(text.len())
+}
+
+/// The edges of the glyphs in `buffer`, in visual order line by line. Each
+/// glyph gives the offset before it in reading order on one side and the
+/// offset after it on the other, so right-to-left glyphs have them swapped.
+fn caret_stops(buffer: &Buffer) -> Vec<Stop> {
+    let mut stops = Vec::new();
+    for (line, run) in buffer.layout_runs().enumerate() {
+        let first = stops.len();
+        for glyph in run.glyphs {
+            if glyph.end <= render::TEXT_PREFIX_LEN {
+                continue;
+            }
+            let rtl = glyph.level.is_rtl();
+            let [start, end] =
+                [glyph.start, glyph.end].map(|index| index.saturating_sub(render::TEXT_PREFIX_LEN));
+            let stop = |x, index, leading| Stop {
+                line,
+                line_y: run.line_y,
+                x,
+                index,
+                leading,
+                rtl,
+            };
+            let (left, right) = if rtl {
+                (
+                    stop(glyph.x, end, false),
+                    stop(glyph.x + glyph.w, start, true),
+                )
+            } else {
+                (
+                    stop(glyph.x, start, true),
+                    stop(glyph.x + glyph.w, end, false),
+                )
+            };
+            stops.push(left);
+            stops.push(right);
+        }
+        stops[first..].sort_by(|a, b| a.x.total_cmp(&b.x));
+        if stops.len() == first && line == 0 {
+            // Empty text still has a caret, at its position.
+            stops.push(Stop {
+                line,
+                line_y: run.line_y,
+                x: 0.0,
+                index: 0,
+                leading: false,
+                rtl: false,
+            });
+        }
+    }
+    stops
+}
+
+/// The stop `caret` shows at.
+fn caret_stop(stops: &[Stop], caret: &TextCaret) -> Option<usize> {
+    stops
+        .iter()
+        .position(|stop| stop.index == caret.position && stop.leading == caret.leading)
+        .or_else(|| stops.iter().position(|stop| stop.index == caret.position))
+        .or_else(|| (0..stops.len()).min_by_key(|&stop| stops[stop].index.abs_diff(caret.position)))
+}
+
+/// The stop next to `from` on screen, to the right or left. Where two stops
+/// share a place, the one on the side the caret comes from is taken.
+fn visual_step(stops: &[Stop], from: usize, right: bool) -> Option<Stop> {
+    let current = stops[from];
+    let apart = |stop: &&Stop| stop.line != current.line || (stop.x - current.x).abs() > 0.01;
+    if right {
+        stops[from + 1..].iter().find(apart).copied()
+    } else {
+        stops[..from].iter().rev().find(apart).copied()
+    }
+}
+
+impl WindowState {
+    /// Where the caret can stand in the text being edited, with its layout
+    /// and the text itself.
+    fn editing_layout(&mut self) -> Option<(Vec<Stop>, Buffer, TextEntries)> {
+        let board_size = self.board_size();
+        let text_entry = self.scene.text(&self.editing_text?)?.clone();
+        let buffer = render::text_buffer(
+            &mut self.font_system,
+            &text_entry,
+            board_size[0],
+            board_size[1],
+        );
+        Some((caret_stops(&buffer), buffer, text_entry))
+    }
+
+    /// The world point at `offset` board pixels from the corner of upright
+    /// text at `position`, which is drawn at the zoom of the camera but not
+    /// turned with it.
+    fn text_to_world(&self, position: [f32; 2], offset: [f32; 2]) -> [f32; 2] {
+        let [x, y] = self.camera.to_screen(position);
+        self.camera.to_world([
+            x + offset[0] * self.camera.zoom,
+            y + offset[1] * self.camera.zoom,
+        ])
+    }
+
+    fn world_to_text(&self, position: [f32; 2], world: [f32; 2]) -> [f32; 2] {
+        let corner = self.camera.to_screen(position);
+        let point = self.camera.to_screen(world);
+        [
+            (point[0] - corner[0]) / self.camera.zoom,
+            (point[1] - corner[1]) / self.camera.zoom,
+        ]
+    }
+
+    /// Handles a key pressed while typing. Returns whether it was used.
+    pub(crate) fn text_key(&mut self, key: &Key) -> bool {
+        let Some(id) = self.editing_text else {
+            return false;
+        };
+        let extend = self.pressed_keys.contains(&Key::Shift);
+        let word = self.pressed_keys.contains(&Key::Control);
+        match key {
+            Key::Enter | Key::GoBack => {
+                self.commit_text();
+                return true;
+            }
+            Key::ArrowLeft | Key::ArrowRight | Key::Home | Key::End => {
+                self.move_caret(key, word, extend);
+            }
+            _ => {
+                let mut caret = self.text_caret;
+                let Some(text_entry) = self.scene.text_mut(&id) else {
+                    return false;
+                };
+                let text = &mut text_entry.text;
+                match key {
+                    Key::Character(char) if word && char.eq_ignore_ascii_case("a") => {
+                        caret.select_all(text)
+                    }
+                    // Other shortcuts are left to the board rather than typed.
+                    Key::Character(_) if word => return false,
+                    Key::Character(char) => caret.insert(text, char),
+                    Key::Space => caret.insert(text, " "),
+                    Key::Backspace => caret.delete(text, false, word),
+                    Key::Delete => caret.delete(text, true, word),
+                    _ => return false,
+                }
+                self.text_caret = caret;
+            }
+        }
+        // The caret shows right away after a key rather than mid-blink.
+        self.cursor_visible = true;
+        self.cursor_timer = Instant::now();
+        true
+    }
+
+    /// Moves the caret by a character, a word or to the ends of its line, as
+    /// the text shows on screen: Left goes left in Persian as in English.
+    fn move_caret(&mut self, key: &Key, word: bool, extend: bool) {
+        let Some((stops, _, text_entry)) = self.editing_layout() else {
+            return;
+        };
+        let caret = &mut self.text_caret;
+        caret.clamp(&text_entry.text);
+        let Some(from) = caret_stop(&stops, caret) else {
+            return;
+        };
+        let right = matches!(key, Key::ArrowRight);
+        match key {
+            Key::ArrowLeft | Key::ArrowRight if word => {
+                // Words are read in the direction of their script, so the
+                // next word on the right is the previous one in Persian.
+                let text = &text_entry.text;
+                let (index, leading) = if right != stops[from].rtl {
+                    (next_word(text, caret.position), false)
+                } else {
+                    (previous_word(text, caret.position), true)
+                };
+                caret.move_to(index, leading, extend);
+            }
+            Key::ArrowLeft | Key::ArrowRight => {
+                let selection = caret.selection();
+                if !extend && !selection.is_empty() {
+                    let index = if right {
+                        selection.end
+                    } else {
+                        selection.start
+                    };
+                    caret.move_to(index, false, false);
+                } else if let Some(stop) = visual_step(&stops, from, right) {
+                    caret.move_to(stop.index, stop.leading, extend);
+                }
+            }
+            _ => {
+                // Home goes to where the line is read from.
+                let line = stops[from].line;
+                let mut on_line = stops.iter().filter(|stop| stop.line == line);
+                let first = if utils::is_rtl(&text_entry.text) == matches!(key, Key::Home) {
+                    on_line.next_back()
+                } else {
+                    on_line.next()
+                };
+                if let Some(stop) = first {
+                    caret.move_to(stop.index, stop.leading, extend);
+                }
+            }
+        }
+    }
+
+    /// Puts the caret where the text being edited was clicked, extending the
+    /// selection to there when `extend`. Returns false when `world` is not on
+    /// that text.
+    pub(crate) fn click_text(&mut self, world: [f32; 2], extend: bool) -> bool {
+        let Some((stops, _, text_entry)) = self.editing_layout() else {
+            return false;
+        };
+        let font_size = text_entry.font_size as f32;
+        let [x, y] = self.world_to_text(text_entry.position, world);
+
+        let near_line = |stop: &&Stop| (y - (stop.line_y - font_size * 0.375)).abs();
+        let Some(line) = stops
+            .iter()
+            .min_by(|a, b| near_line(a).total_cmp(&near_line(b)))
+            .map(|stop| stop.line)
+        else {
+            return false;
+        };
+        let on_line = || stops.iter().filter(|stop| stop.line == line);
+        let (left, right) = on_line().fold((f32::MAX, f32::MIN), |(left, right), stop| {
+            (left.min(stop.x), right.max(stop.x))
+        });
+        let margin = font_size * CLICK_MARGIN;
+        let line_y = on_line().next().map_or(0.0, |stop| stop.line_y);
+        if x < left - margin
+            || x > right + margin
+            || y < line_y - font_size - margin
+            || y > line_y + font_size * 0.25 + margin
+        {
+            return false;
+        }
+
+        if let Some(stop) = on_line().min_by(|a, b| (a.x - x).abs().total_cmp(&(b.x - x).abs())) {
+            self.text_caret.move_to(stop.index, stop.leading, extend);
+        }
+        self.cursor_visible = true;
+        self.cursor_timer = Instant::now();
+        true
+    }
+
+    /// Lines drawn over the text being edited: the caret while it blinks on,
+    /// and boxes around the selected glyphs.
+    pub(crate) fn caret_overlay(&mut self) -> Vec<Vertex> {
+        let Some((stops, buffer, text_entry)) = self.editing_layout() else {
+            return Vec::new();
+        };
+        let board_size = self.board_size();
+        let font_size = text_entry.font_size as f32;
+        let mut caret = self.text_caret;
+        caret.clamp(&text_entry.text);
+        let to_world = |point| self.text_to_world(text_entry.position, point);
+        let mut lines: Vec<([f32; 2], [f32; 4])> = Vec::new();
+
+        let selection = caret.selection();
+        if !selection.is_empty() {
+            for run in buffer.layout_runs() {
+                let [top, bottom] = [run.line_y - font_size, run.line_y + font_size * 0.25];
+                let mut boxes: Vec<[f32; 2]> = Vec::new();
+                let mut last_selected = false;
+                for glyph in run.glyphs {
+                    let [start, end] = [glyph.start, glyph.end]
+                        .map(|index| index.saturating_sub(render::TEXT_PREFIX_LEN));
+                    let selected = glyph.end > render::TEXT_PREFIX_LEN
+                        && start >= selection.start
+                        && end <= selection.end;
+                    match boxes.last_mut() {
+                        Some(last) if selected && last_selected => last[1] = glyph.x + glyph.w,
+                        _ if selected => boxes.push([glyph.x, glyph.x + glyph.w]),
+                        _ => {}
+                    }
+                    last_selected = selected;
+                }
+                for [left, right] in boxes {
+                    let corners = [[left, top], [right, top], [right, bottom], [left, bottom]];
+                    for index in 0..4 {
+                        lines.push((to_world(corners[index]), SELECTION_COLOR));
+                        lines.push((to_world(corners[(index + 1) % 4]), SELECTION_COLOR));
+                    }
+                }
+            }
+        }
+
+        if self.cursor_visible {
+            if let Some(stop) = caret_stop(&stops, &caret).map(|stop| stops[stop]) {
+                let color = text_entry.color.map(|channel| channel as f32 / 255.0);
+                lines.push((to_world([stop.x, stop.line_y - font_size]), color));
+                lines.push((to_world([stop.x, stop.line_y + font_size * 0.25]), color));
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(point, color)| Vertex {
+                position: export::board_to_ndc(point, board_size),
+                color,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typing_replaces_the_selection() {
+        let mut text = "hello world".to_owned();
+        let mut caret = TextCaret {
+            position: 0,
+            anchor: 5,
+            leading: false,
+        };
+        caret.insert(&mut text, "سلام");
+        assert_eq!(text, "سلام world");
+        assert_eq!(caret, TextCaret::at("سلام".len()));
+    }
+
+    #[test]
+    fn deletes_whole_graphemes_and_words() {
+        let mut text = "cafe\u{301} au lait".to_owned();
+        let mut caret = TextCaret::at("cafe\u{301}".len());
+        caret.delete(&mut text, false, false);
+        assert_eq!(text, "caf au lait");
+
+        let mut caret = TextCaret::at(text.len());
+        caret.delete(&mut text, false, true);
+        assert_eq!(text, "caf au ");
+
+        let mut caret = TextCaret::at(0);
+        caret.delete(&mut text, true, true);
+        assert_eq!(text, " au ");
+    }
+
+    #[test]
+    fn stays_on_character_boundaries() {
+        let mut text = "نو".to_owned();
+        let mut caret = TextCaret::at(1);
+        caret.insert(&mut text, "ا");
+        assert!(text.is_char_boundary(caret.position));
+        assert_eq!(text, "انو");
+
+        let mut caret = TextCaret::at(100);
+        caret.select_all(&text);
+        assert_eq!(caret.selection(), 0..text.len());
+        caret.delete(&mut text, false, false);
+        assert!(text.is_empty());
+    }
+}
@@ -0,0 +1,503 @@
+use crate::{
+    export,
+    structs::{LineJoin, Polyline, Stroke},
+    vector::{add, direction, dot, length, normalize, scale, sub},
+};
+
+/// Largest distance, in board pixels, between a drawn sample and the fitted
+/// curve. Pressure counts by how far it moves the edge of the line.
+const FIT_TOLERANCE: f32 = 0.75;
+/// Reparameterization rounds tried before a segment is split.
+const MAX_REPARAMETERIZATIONS: usize = 4;
+/// Samples closer than this, in board pixels, are merged before fitting.
+const MIN_SAMPLE_DISTANCE: f32 = 0.01;
+/// Length in board pixels of the straight pieces a curve is drawn with.
+const FLATTEN_STEP: f32 = 2.0;
+/// Most pieces a single Bézier segment is drawn with.
+const MAX_FLATTEN_STEPS: usize = 128;
+
+/// Fits piecewise cubic Béziers through the samples of `polyline`, following
+/// "An Algorithm for Automatically Fitting Digitized Curves" (Schneider,
+/// Graphics Gems, 1990). The fit runs in pixels of a board of `board_size`
+/// so the tolerance does not depend on the aspect ratio.
+pub(crate) fn fit_stroke(
+    polyline: &Polyline,
+    color: [f32; 4],
+    width: f32,
+    join: LineJoin,
+    board_size: [f32; 2],
+) -> Stroke {
+    let has_pressure = polyline.pressure.len() == polyline.points.len();
+    let mut samples: Vec<Sample> = Vec::with_capacity(polyline.points.len());
+    for (index, point) in polyline.points.iter().enumerate() {
+        let point = export::ndc_to_board(*point, board_size);
+        if samples
+            .last()
+            .is_some_and(|last| length(sub(point, last.point)) < MIN_SAMPLE_DISTANCE)
+        {
+            continue;
+        }
+        samples.push(Sample {
+            point,
+            pressure: if has_pressure {
+                polyline.pressure[index]
+            } else {
+                1.0
+            },
+        });
+    }
+
+    let mut fit = Fit {
+        samples: &samples,
+        half_width: width / 2.0,
+        points: Vec::new(),
+        pressure: Vec::new(),
+    };
+    match samples.as_slice() {
+        [] => {}
+        [sample] => {
+            fit.points.push(sample.point);
+            fit.pressure.push(sample.pressure);
+        }
+        [first, .., last] => {
+            let last_index = samples.len() - 1;
+            fit.points.push(first.point);
+            fit.pressure.push(first.pressure);
+            fit.fit_cubic(
+                0,
+                last_index,
+                direction(first.point, samples[1].point),
+                direction(last.point, samples[last_index - 1].point),
+            );
+        }
+    }
+
+    Stroke {
+        color,
+        width,
+        points: fit
+            .points
+            .into_iter()
+            .map(|point| export::board_to_ndc(point, board_size))
+            .collect(),
+        pressure: if has_pressure {
+            fit.pressure
+        } else {
+            Vec::new()
+        },
+        join,
+    }
+}
+
+/// Walks the curves of `stroke` in short straight steps, returning points in
+/// board pixels with the line width there.
+pub(crate) fn flatten(stroke: &Stroke, board_size: [f32; 2]) -> Vec<([f32; 2], f32)> {
+    let Some(first) = stroke.points.first() else {
+        return Vec::new();
+    };
+
+    let mut points = vec![(export::ndc_to_board(*first, board_size), stroke.width_at(0))];
+    for (index, segment) in stroke.segments().enumerate() {
+        let segment = segment.map(|point| export::ndc_to_board(point, board_size));
+        let (start_width, end_width) = (stroke.width_at(index), stroke.width_at(index + 1));
+        let polygon_length = segment
+            .windows(2)
+            .map(|pair| length(sub(pair[1], pair[0])))
+            .sum::<f32>();
+        let steps = ((polygon_length / FLATTEN_STEP).ceil() as usize).clamp(1, MAX_FLATTEN_STEPS);
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            points.push((
+                bezier(&segment, t),
+                start_width + (end_width - start_width) * t,
+            ));
+        }
+    }
+    points
+}
+
+/// The part of `stroke` between `start` and `end`, counted in whole Bézier
+/// segments plus the position within one, so `1.5` is halfway along the
+/// second segment.
+pub(crate) fn slice(stroke: &Stroke, start: f32, end: f32) -> Stroke {
+    let segments: Vec<[[f32; 2]; 4]> = stroke.segments().collect();
+    let Some(last) = segments.len().checked_sub(1) else {
+        return stroke.clone();
+    };
+    let position = |value: f32| {
+        let index = (value.max(0.0).floor() as usize).min(last);
+        (index, (value - index as f32).clamp(0.0, 1.0))
+    };
+    let pressure_at = |index: usize, t: f32| {
+        let (start, end) = (stroke.pressure[index], stroke.pressure[index + 1]);
+        start + (end - start) * t
+    };
+    let has_pressure = stroke.pressure.len() == segments.len() + 1;
+
+    let (first_index, first_t) = position(start);
+    let (last_index, last_t) = position(end);
+    let mut points = Vec::new();
+    let mut pressure = Vec::new();
+    for (index, segment) in segments
+        .iter()
+        .enumerate()
+        .take(last_index + 1)
+        .skip(first_index)
+    {
+        let t0 = if index == first_index { first_t } else { 0.0 };
+        let t1 = if index == last_index { last_t } else { 1.0 };
+        let piece = sub_segment(segment, t0, t1);
+        if points.is_empty() {
+            points.push(piece[0]);
+            if has_pressure {
+                pressure.push(pressure_at(index, t0));
+            }
+        }
+        points.extend_from_slice(&piece[1..]);
+        if has_pressure {
+            pressure.push(pressure_at(index, t1));
+        }
+    }
+
+    Stroke {
+        color: stroke.color,
+        width: stroke.width,
+        points,
+        pressure,
+        join: stroke.join,
+    }
+}
+
+/// The part of `segment` between `t0` and `t1`, by de Casteljau subdivision.
+fn sub_segment(segment: &[[f32; 2]; 4], t0: f32, t1: f32) -> [[f32; 2]; 4] {
+    let (left, _) = split(segment, t1);
+    if t1 <= f32::EPSILON {
+        return left;
+    }
+    let (_, right) = split(&left, t0 / t1);
+    right
+}
+
+fn split(segment: &[[f32; 2]; 4], t: f32) -> ([[f32; 2]; 4], [[f32; 2]; 4]) {
+    let lerp = |a: [f32; 2], b: [f32; 2]| add(a, scale(sub(b, a), t));
+    let [p0, p1, p2, p3] = *segment;
+    let (a, b, c) = (lerp(p0, p1), lerp(p1, p2), lerp(p2, p3));
+    let (d, e) = (lerp(a, b), lerp(b, c));
+    let middle = lerp(d, e);
+    ([p0, a, d, middle], [middle, e, c, p3])
+}
+
+struct Sample {
+    point: [f32; 2],
+    pressure: f32,
+}
+
+struct Fit<'a> {
+    samples: &'a [Sample],
+    half_width: f32,
+    /// Output control points after the first end point, in board pixels.
+    points: Vec<[f32; 2]>,
+    pressure: Vec<f32>,
+}
+
+impl Fit<'_> {
+    /// Fits `samples[first..=last]` with tangents pointing into the curve at
+    /// both ends, splitting where the error is largest until it is small enough.
+    fn fit_cubic(&mut self, first: usize, last: usize, start: [f32; 2], end: [f32; 2]) {
+        let tolerance = FIT_TOLERANCE * FIT_TOLERANCE;
+
+        if last - first == 1 {
+            let distance = length(sub(self.samples[last].point, self.samples[first].point)) / 3.0;
+            let segment = [
+                self.samples[first].point,
+                add(self.samples[first].point, scale(start, distance)),
+                add(self.samples[last].point, scale(end, distance)),
+                self.samples[last].point,
+            ];
+            self.push(segment, last);
+            return;
+        }
+
+        let mut parameters = self.chord_length_parameters(first, last);
+        let mut segment = self.generate_bezier(first, last, &parameters, start, end);
+        let (mut error, mut split) = self.max_error(first, last, &segment, &parameters);
+        if error < tolerance {
+            self.push(segment, last);
+            return;
+        }
+
+        if error < tolerance * 4.0 {
+            for _ in 0..MAX_REPARAMETERIZATIONS {
+                parameters = self.reparameterize(first, &segment, &parameters);
+                segment = self.generate_bezier(first, last, &parameters, start, end);
+                (error, split) = self.max_error(first, last, &segment, &parameters);
+                if error < tolerance {
+                    self.push(segment, last);
+                    return;
+                }
+            }
+        }
+
+        let mut center = sub(self.samples[split - 1].point, self.samples[split + 1].point);
+        if length(center) < MIN_SAMPLE_DISTANCE {
+            center = sub(self.samples[split - 1].point, self.samples[split].point);
+        }
+        let center = normalize(center);
+        self.fit_cubic(first, split, start, center);
+        self.fit_cubic(split, last, scale(center, -1.0), end);
+    }
+
+    fn push(&mut self, segment: [[f32; 2]; 4], last: usize) {
+        self.points.extend_from_slice(&segment[1..]);
+        self.pressure.push(self.samples[last].pressure);
+    }
+
+    fn chord_length_parameters(&self, first: usize, last: usize) -> Vec<f32> {
+        let mut parameters = vec![0.0];
+        for index in first + 1..=last {
+            let step = length(sub(
+                self.samples[index].point,
+                self.samples[index - 1].point,
+            ));
+            parameters.push(parameters[parameters.len() - 1] + step);
+        }
+        let total = parameters[parameters.len() - 1];
+        parameters.iter().map(|length| length / total).collect()
+    }
+
+    /// Least-squares control points for the given end tangents and sample
+    /// parameters, falling back to a third of the chord when that degenerates.
+    fn generate_bezier(
+        &self,
+        first: usize,
+        last: usize,
+        parameters: &[f32],
+        start: [f32; 2],
+        end: [f32; 2],
+    ) -> [[f32; 2]; 4] {
+        let first_point = self.samples[first].point;
+        let last_point = self.samples[last].point;
+
+        let mut c = [[0.0; 2]; 2];
+        let mut x = [0.0; 2];
+        for (offset, &t) in parameters.iter().enumerate() {
+            let [b0, b1, b2, b3] = bernstein(t);
+            let a = [scale(start, b1), scale(end, b2)];
+            let rest = sub(
+                self.samples[first + offset].point,
+                add(scale(first_point, b0 + b1), scale(last_point, b2 + b3)),
+            );
+            c[0][0] += dot(a[0], a[0]);
+            c[0][1] += dot(a[0], a[1]);
+            c[1][1] += dot(a[1], a[1]);
+            x[0] += dot(a[0], rest);
+            x[1] += dot(a[1], rest);
+        }
+        c[1][0] = c[0][1];
+
+        let determinant = c[0][0] * c[1][1] - c[1][0] * c[0][1];
+        let (mut alpha_start, mut alpha_end) = if determinant.abs() > f32::EPSILON {
+            (
+                (x[0] * c[1][1] - x[1] * c[0][1]) / determinant,
+                (c[0][0] * x[1] - c[1][0] * x[0]) / determinant,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        let chord = length(sub(last_point, first_point));
+        let epsilon = 1e-6 * chord;
+        if alpha_start < epsilon || alpha_end < epsilon {
+            alpha_start = chord / 3.0;
+            alpha_end = chord / 3.0;
+        }
+
+        [
+            first_point,
+            add(first_point, scale(start, alpha_start)),
+            add(last_point, scale(end, alpha_end)),
+            last_point,
+        ]
+    }
+
+    /// Largest squared error over the inner samples and where it happens.
+    /// Pressure is interpolated linearly along the segment, as `flatten` does.
+    fn max_error(
+        &self,
+        first: usize,
+        last: usize,
+        segment: &[[f32; 2]; 4],
+        parameters: &[f32],
+    ) -> (f32, usize) {
+        let (start_pressure, end_pressure) =
+            (self.samples[first].pressure, self.samples[last].pressure);
+        let mut max = (0.0, (first + last) / 2);
+        for index in first + 1..last {
+            let t = parameters[index - first];
+            let sample = &self.samples[index];
+            let position = length(sub(bezier(segment, t), sample.point));
+            let pressure = start_pressure + (end_pressure - start_pressure) * t;
+            let edge = (pressure - sample.pressure).abs() * self.half_width;
+            let error = position.max(edge).powi(2);
+            if error >= max.0 {
+                max = (error, index);
+            }
+        }
+        max
+    }
+
+    /// Moves each parameter one Newton-Raphson step closer to the point of
+    /// the curve nearest to its sample.
+    fn reparameterize(
+        &self,
+        first: usize,
+        segment: &[[f32; 2]; 4],
+        parameters: &[f32],
+    ) -> Vec<f32> {
+        let first_derivative = [
+            scale(sub(segment[1], segment[0]), 3.0),
+            scale(sub(segment[2], segment[1]), 3.0),
+            scale(sub(segment[3], segment[2]), 3.0),
+        ];
+        let second_derivative = [
+            scale(sub(first_derivative[1], first_derivative[0]), 2.0),
+            scale(sub(first_derivative[2], first_derivative[1]), 2.0),
+        ];
+
+        parameters
+            .iter()
+            .enumerate()
+            .map(|(offset, &t)| {
+                let difference = sub(bezier(segment, t), self.samples[first + offset].point);
+                let slope = quadratic(&first_derivative, t);
+                let curvature = linear(&second_derivative, t);
+                let numerator = dot(difference, slope);
+                let denominator = dot(slope, slope) + dot(difference, curvature);
+                if denominator.abs() < f32::EPSILON {
+                    t
+                } else {
+                    (t - numerator / denominator).clamp(0.0, 1.0)
+                }
+            })
+            .collect()
+    }
+}
+
+fn bernstein(t: f32) -> [f32; 4] {
+    let u = 1.0 - t;
+    [u * u * u, 3.0 * t * u * u, 3.0 * t * t * u, t * t * t]
+}
+
+pub(crate) fn bezier(segment: &[[f32; 2]; 4], t: f32) -> [f32; 2] {
+    let weights = bernstein(t);
+    segment
+        .iter()
+        .zip(weights)
+        .fold([0.0, 0.0], |sum, (point, weight)| {
+            add(sum, scale(*point, weight))
+        })
+}
+
+fn quadratic(points: &[[f32; 2]; 3], t: f32) -> [f32; 2] {
+    let u = 1.0 - t;
+    add(
+        add(scale(points[0], u * u), scale(points[1], 2.0 * t * u)),
+        scale(points[2], t * t),
+    )
+}
+
+fn linear(points: &[[f32; 2]; 2], t: f32) -> [f32; 2] {
+    add(scale(points[0], 1.0 - t), scale(points[1], t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+
+    fn polyline(points: impl IntoIterator<Item = [f32; 2]>) -> Polyline {
+        let mut polyline = Polyline::default();
+        for point in points {
+            polyline.push(export::board_to_ndc(point, BOARD_SIZE), 1.0);
+        }
+        polyline
+    }
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        length(sub(a, b)) < 1e-3
+    }
+
+    #[test]
+    fn fits_a_straight_line_with_one_segment() {
+        let line = polyline((0..20).map(|step| [100.0 + step as f32 * 10.0, 300.0]));
+        let stroke = fit_stroke(&line, [0.0; 4], 2.0, LineJoin::Bevel, BOARD_SIZE);
+        assert_eq!(stroke.points.len(), 4);
+        assert_eq!(stroke.pressure, [1.0, 1.0]);
+        assert_eq!(stroke.join, LineJoin::Bevel);
+        assert!(close(stroke.points[0], line.points[0]));
+        assert!(close(stroke.points[3], line.points[19]));
+    }
+
+    #[test]
+    fn fits_a_curve_within_tolerance() {
+        let arc = polyline((0..=90).map(|degree| {
+            let angle = (degree as f32).to_radians() * 3.0;
+            [400.0 + 150.0 * angle.cos(), 300.0 + 150.0 * angle.sin()]
+        }));
+        let stroke = fit_stroke(&arc, [0.0; 4], 4.0, LineJoin::Round, BOARD_SIZE);
+        assert!(stroke.points.len() > 4);
+        assert_eq!(stroke.points.len() % 3, 1);
+
+        let drawn: Vec<[f32; 2]> = flatten(&stroke, BOARD_SIZE)
+            .into_iter()
+            .map(|(point, _)| point)
+            .collect();
+        for sample in &arc.points {
+            let sample = export::ndc_to_board(*sample, BOARD_SIZE);
+            let nearest = drawn
+                .iter()
+                .map(|point| length(sub(*point, sample)))
+                .fold(f32::INFINITY, f32::min);
+            assert!(nearest <= FIT_TOLERANCE + FLATTEN_STEP, "{nearest}");
+        }
+    }
+
+    #[test]
+    fn keeps_a_single_sample_as_a_dot() {
+        let dot = polyline([[10.0, 10.0], [10.0, 10.0]]);
+        let stroke = fit_stroke(&dot, [0.0; 4], 2.0, LineJoin::Round, BOARD_SIZE);
+        assert_eq!(stroke.points.len(), 1);
+        assert_eq!(flatten(&stroke, BOARD_SIZE).len(), 1);
+    }
+
+    #[test]
+    fn slices_within_a_segment() {
+        let stroke = Stroke {
+            color: [0.0; 4],
+            width: 2.0,
+            points: vec![[0.0, 0.0], [0.2, 0.4], [0.6, 0.4], [0.8, 0.0]],
+            pressure: vec![0.2, 1.0],
+            join: LineJoin::Miter,
+        };
+        let segment = [
+            stroke.points[0],
+            stroke.points[1],
+            stroke.points[2],
+            stroke.points[3],
+        ];
+
+        let half = slice(&stroke, 0.25, 0.75);
+        assert_eq!(half.points.len(), 4);
+        assert!(close(half.points[0], bezier(&segment, 0.25)));
+        assert!(close(half.points[3], bezier(&segment, 0.75)));
+        assert!((half.pressure[0] - 0.4).abs() < 1e-6);
+        assert!((half.pressure[1] - 0.8).abs() < 1e-6);
+        assert_eq!(half.join, LineJoin::Miter);
+
+        let whole = slice(&stroke, 0.0, 1.0);
+        for (sliced, point) in whole.points.iter().zip(&stroke.points) {
+            assert!(close(*sliced, *point));
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{
+    curve, export, geometry,
+    structs::{Action, ActionType, Stroke, Vertex},
+    sync,
+    vector::distance,
+    WindowState,
+};
+use dioxus::desktop::tao::dpi::PhysicalPosition;
+use std::f32::consts::PI;
+use stream::desktop::stream::paint_event::ActionType as StreamActionType;
+use uuid::Uuid;
+
+/// Radius of the eraser in board pixels.
+pub(crate) const ERASER_RADIUS: f32 = 12.0;
+/// Distance in board pixels between the points a stroke is tested at.
+const ERASE_STEP: f32 = 1.0;
+/// Halvings used to find where a stroke enters or leaves the eraser.
+const BISECTION_STEPS: usize = 8;
+/// Fragments shorter than this, in board pixels, are dropped.
+const MIN_FRAGMENT: f32 = 0.5;
+/// Segments of the eraser outline drawn under the pointer.
+const OUTLINE_SEGMENTS: usize = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EraserMode {
+    /// Removes every stroke, rectangle and text the eraser touches.
+    #[default]
+    Object,
+    /// Cuts away the parts of strokes under the eraser. Rectangles and text
+    /// can't be cut and are removed whole.
+    Partial,
+}
+
+/// Cuts the parts of `stroke` within reach of an eraser at `center` away.
+/// Returns `None` when the eraser misses, otherwise what is left, which may be
+/// nothing.
+fn cut_stroke(stroke: &Stroke, center: [f32; 2], board_size: [f32; 2]) -> Option<Vec<Stroke>> {
+    let reach = ERASER_RADIUS + stroke.width / 2.0;
+    let inside =
+        |point: [f32; 2]| distance(center, export::ndc_to_board(point, board_size)) <= reach;
+
+    let segments: Vec<[[f32; 2]; 4]> = stroke.segments().collect();
+    if segments.is_empty() {
+        return stroke
+            .points
+            .first()
+            .is_some_and(|point| inside(*point))
+            .then(Vec::new);
+    }
+
+    // Kept parts, as positions along the stroke in the units of `curve::slice`.
+    let mut kept: Vec<(f32, f32)> = Vec::new();
+    let mut start = (!inside(stroke.points[0])).then_some(0.0);
+    let mut erased = start.is_none();
+    for (index, segment) in segments.iter().enumerate() {
+        let polygon_length = segment
+            .windows(2)
+            .map(|pair| {
+                distance(
+                    export::ndc_to_board(pair[0], board_size),
+                    export::ndc_to_board(pair[1], board_size),
+                )
+            })
+            .sum::<f32>();
+        let steps = ((polygon_length / ERASE_STEP).ceil() as usize).max(1);
+
+        let mut previous = (0.0, inside(segment[0]));
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let is_inside = inside(curve::bezier(segment, t));
+            if is_inside != previous.1 {
+                let (mut outside_t, mut inside_t) = if is_inside {
+                    (previous.0, t)
+                } else {
+                    (t, previous.0)
+                };
+                for _ in 0..BISECTION_STEPS {
+                    let middle = (outside_t + inside_t) / 2.0;
+                    if inside(curve::bezier(segment, middle)) {
+                        inside_t = middle;
+                    } else {
+                        outside_t = middle;
+                    }
+                }
+
+                let boundary = index as f32 + outside_t;
+                if is_inside {
+                    erased = true;
+                    if let Some(start) = start.take() {
+                        kept.push((start, boundary));
+                    }
+                } else {
+                    start = Some(boundary);
+                }
+            }
+            previous = (t, is_inside);
+        }
+    }
+
+    if !erased {
+        return None;
+    }
+    if let Some(start) = start {
+        kept.push((start, segments.len() as f32));
+    }
+
+    Some(
+        kept.into_iter()
+            .map(|(start, end)| curve::slice(stroke, start, end))
+            .filter(|fragment| {
+                let points = curve::flatten(fragment, board_size);
+                let length = points
+                    .windows(2)
+                    .map(|pair| distance(pair[0].0, pair[1].0))
+                    .sum::<f32>();
+                length >= MIN_FRAGMENT
+            })
+            .collect(),
+    )
+}
+
+/// The eraser outline around `center`, in board pixels, as a line list.
+pub(crate) fn outline(center: [f32; 2], board_size: [f32; 2]) -> Vec<Vertex> {
+    let point = |step: usize| {
+        let angle = step as f32 / OUTLINE_SEGMENTS as f32 * 2.0 * PI;
+        Vertex {
+            position: [
+                (center[0] + ERASER_RADIUS * angle.cos()) / board_size[0] * 2.0 - 1.0,
+                1.0 - (center[1] + ERASER_RADIUS * angle.sin()) / board_size[1] * 2.0,
+            ],
+            color: [0.5, 0.5, 0.5, 1.0],
+        }
+    };
+    (0..OUTLINE_SEGMENTS)
+        .flat_map(|step| [point(step), point(step + 1)])
+        .collect()
+}
+
+impl WindowState {
+    pub(crate) fn begin_erase(&mut self, position: PhysicalPosition<f64>) {
+        self.last_erase_position = None;
+        self.erase_to(position);
+    }
+
+    /// Moves the eraser to `position`, erasing everything it passes over on
+    /// the way. Removed actions are deleted from the room, and what is left
+    /// of cut strokes is shared as new actions on the next update.
+    pub(crate) fn erase_to(&mut self, position: PhysicalPosition<f64>) {
+        let Some(mode) = self.eraser else {
+            return;
+        };
+        let point = [position.x as f32, position.y as f32];
+        let from = self.last_erase_position.replace(point).unwrap_or(point);
+        let steps = (distance(from, point) / (ERASER_RADIUS / 2.0))
+            .ceil()
+            .max(1.0) as usize;
+
+        let mut removed = Vec::new();
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let center = [
+                from[0] + (point[0] - from[0]) * t,
+                from[1] + (point[1] - from[1]) * t,
+            ];
+            removed.extend(self.erase_at(center, mode));
+        }
+
+        // Fragments cut again before they were ever sent have nothing to delete.
+        removed.retain(|action| self.events_id.contains(&action.id));
+        if !removed.is_empty() {
+            self.send_paint_event(sync::paint_event(
+                &self.room,
+                self.current_page_id(),
+                &removed,
+                StreamActionType::IsDeleted(true),
+            ));
+        }
+    }
+
+    fn erase_at(&mut self, center: [f32; 2], mode: EraserMode) -> Vec<Action> {
+        let board_size = self.board_size();
+        let mut hits: Vec<(Uuid, Option<Vec<Stroke>>)> = Vec::new();
+        for action in self.scene.committed() {
+            match (&action.action_type, mode) {
+                (ActionType::Stroke(stroke), EraserMode::Partial) => {
+                    if let Some(fragments) = cut_stroke(stroke, center, board_size) {
+                        hits.push((action.id, Some(fragments)));
+                    }
+                }
+                _ => {
+                    if geometry::touches(
+                        action,
+                        center,
+                        ERASER_RADIUS,
+                        board_size,
+                        &self.camera,
+                        &mut self.font_system,
+                    ) {
+                        hits.push((action.id, None));
+                    }
+                }
+            }
+        }
+
+        let mut removed = Vec::new();
+        for (id, fragments) in hits {
+            removed.extend(self.scene.remove(&id));
+            for fragment in fragments.into_iter().flatten() {
+                self.add_action(ActionType::Stroke(fragment));
+            }
+        }
+        if !removed.is_empty() {
+            self.redo_stack.clear();
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::LineJoin;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+
+    /// A straight stroke 2 pixels wide between two board points.
+    fn stroke(from: [f32; 2], to: [f32; 2]) -> Stroke {
+        let point = |t: f32| {
+            export::board_to_ndc(
+                [
+                    from[0] + (to[0] - from[0]) * t,
+                    from[1] + (to[1] - from[1]) * t,
+                ],
+                BOARD_SIZE,
+            )
+        };
+        Stroke {
+            color: [0.0, 0.0, 0.0, 1.0],
+            width: 2.0,
+            points: vec![point(0.0), point(1.0 / 3.0), point(2.0 / 3.0), point(1.0)],
+            pressure: Vec::new(),
+            join: LineJoin::Round,
+        }
+    }
+
+    /// First and last point of `stroke` in board pixels.
+    fn ends(stroke: &Stroke) -> [[f32; 2]; 2] {
+        [stroke.points[0], stroke.points[stroke.points.len() - 1]]
+            .map(|point| export::ndc_to_board(point, BOARD_SIZE))
+    }
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        distance(a, b) < 0.5
+    }
+
+    #[test]
+    fn leaves_strokes_it_misses_alone() {
+        let line = stroke([100.0, 300.0], [300.0, 300.0]);
+        assert!(cut_stroke(&line, [200.0, 320.0], BOARD_SIZE).is_none());
+    }
+
+    #[test]
+    fn cuts_a_stroke_in_two() {
+        let line = stroke([100.0, 300.0], [300.0, 300.0]);
+        let fragments = cut_stroke(&line, [200.0, 300.0], BOARD_SIZE).unwrap();
+        assert_eq!(fragments.len(), 2);
+
+        // The eraser reaches half the stroke width past its radius.
+        let reach = ERASER_RADIUS + 1.0;
+        let [first, second] = [ends(&fragments[0]), ends(&fragments[1])];
+        assert!(close(first[0], [100.0, 300.0]));
+        assert!(close(first[1], [200.0 - reach, 300.0]), "{:?}", first);
+        assert!(close(second[0], [200.0 + reach, 300.0]), "{:?}", second);
+        assert!(close(second[1], [300.0, 300.0]));
+        assert!(fragments
+            .iter()
+            .all(|fragment| fragment.width == line.width));
+    }
+
+    #[test]
+    fn shortens_a_stroke_from_its_end() {
+        let line = stroke([100.0, 300.0], [300.0, 300.0]);
+        let fragments = cut_stroke(&line, [300.0, 300.0], BOARD_SIZE).unwrap();
+        assert_eq!(fragments.len(), 1);
+        let [start, end] = ends(&fragments[0]);
+        assert!(close(start, [100.0, 300.0]));
+        assert!(
+            close(end, [300.0 - ERASER_RADIUS - 1.0, 300.0]),
+            "{:?}",
+            end
+        );
+    }
+
+    #[test]
+    fn erases_strokes_it_covers_entirely() {
+        let short = stroke([195.0, 300.0], [205.0, 300.0]);
+        assert_eq!(
+            cut_stroke(&short, [200.0, 300.0], BOARD_SIZE)
+                .unwrap()
+                .len(),
+            0
+        );
+
+        let dot = Stroke {
+            points: vec![export::board_to_ndc([200.0, 300.0], BOARD_SIZE)],
+            ..short
+        };
+        assert_eq!(
+            cut_stroke(&dot, [205.0, 300.0], BOARD_SIZE).unwrap().len(),
+            0
+        );
+        assert!(cut_stroke(&dot, [230.0, 300.0], BOARD_SIZE).is_none());
+    }
+
+    #[test]
+    fn outlines_the_reach_of_the_eraser() {
+        let center = [200.0, 100.0];
+        let vertices = outline(center, BOARD_SIZE);
+        assert_eq!(vertices.len(), OUTLINE_SEGMENTS * 2);
+        for vertex in vertices {
+            let point = export::ndc_to_board(vertex.position, BOARD_SIZE);
+            assert!((distance(point, center) - ERASER_RADIUS).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    camera,
+    images::{ImageStore, StoredImage},
+    offscreen::{self, OffscreenError},
+    paper::Paper,
+    pdf::PdfExportOptions,
+    structs::{Action, ActionType, Background, Ellipse, Image, Line, Polygon, Rectangle, Stroke},
+    svg::SvgExportOptions,
+    TextEntries, WindowState,
+};
+use image::{imageops, RgbaImage};
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+const CROP_PADDING: u32 = 16;
+
+#[derive(Debug)]
+pub enum ExportError {
+    Render(OffscreenError),
+    Image(image::ImageError),
+    Io(std::io::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Svg,
+    Pdf,
+}
+
+/// What is exported of a page: its paper, the slide on it, its actions in
+/// paint order and the image files both show.
+#[derive(Clone, Copy, Debug)]
+pub struct PageContent<'a> {
+    pub paper: Paper,
+    pub slide: Option<&'a Image>,
+    pub actions: &'a [Action],
+    pub images: &'a ImageStore,
+}
+
+/// Receives the drawable content of a board in the order it is painted on
+/// screen: the paper first, then the slide, then every action in scene order.
+pub(crate) trait BoardPainter {
+    fn paper(&mut self, paper: &Paper);
+    /// Images whose file isn't in the store are left out.
+    fn image(&mut self, image: &Image, stored: &StoredImage);
+    fn rectangle(&mut self, rectangle: &Rectangle);
+    fn ellipse(&mut self, ellipse: &Ellipse);
+    fn line(&mut self, line: &Line);
+    fn polygon(&mut self, polygon: &Polygon);
+    fn stroke(&mut self, stroke: &Stroke);
+    fn text(&mut self, text_entry: &TextEntries);
+}
+
+pub(crate) fn paint_actions<'a>(
+    paper: Option<&Paper>,
+    slide: Option<&Image>,
+    actions: impl Iterator<Item = &'a Action>,
+    images: &ImageStore,
+    painter: &mut impl BoardPainter,
+) {
+    if let Some(paper) = paper {
+        painter.paper(paper);
+    }
+    if let Some(slide) = slide {
+        if let Some(stored) = images.get(&slide.hash) {
+            painter.image(slide, stored);
+        }
+    }
+    for action in actions {
+        match &action.action_type {
+            ActionType::Image(image) => {
+                if let Some(stored) = images.get(&image.hash) {
+                    painter.image(image, stored);
+                }
+            }
+            ActionType::Shapes(rectangle) => painter.rectangle(rectangle),
+            ActionType::Ellipse(ellipse) => painter.ellipse(ellipse),
+            ActionType::Line(line) => painter.line(line),
+            ActionType::Polygon(polygon) => painter.polygon(polygon),
+            ActionType::Stroke(stroke) => painter.stroke(stroke),
+            ActionType::Text(text_entry) => painter.text(text_entry),
+        }
+    }
+}
+
+/// Maps a position in NDC onto a board of `board_size` pixels, origin top left.
+pub(crate) fn ndc_to_board(position: [f32; 2], board_size: [f32; 2]) -> [f32; 2] {
+    [
+        (position[0] + 1.0) / 2.0 * board_size[0],
+        (1.0 - position[1]) / 2.0 * board_size[1],
+    ]
+}
+
+/// Maps a position in pixels of a board of `board_size` back to NDC.
+pub(crate) fn board_to_ndc(point: [f32; 2], board_size: [f32; 2]) -> [f32; 2] {
+    [
+        point[0] / board_size[0] * 2.0 - 1.0,
+        1.0 - point[1] / board_size[1] * 2.0,
+    ]
+}
+
+/// Splits a stroke into runs of Bézier segments that can be drawn as one path
+/// of a single width, as ranges of `stroke.points`. Widths are rounded to a
+/// quarter pixel so pressure strokes don't turn into one path per segment.
+pub(crate) fn stroke_runs(stroke: &Stroke) -> Vec<(RangeInclusive<usize>, f32)> {
+    let mut runs: Vec<(RangeInclusive<usize>, f32)> = Vec::new();
+    for segment in 0..stroke.segments().count() {
+        let (start, end) = (segment * 3, segment * 3 + 3);
+        let width = ((stroke.width_at(segment) + stroke.width_at(segment + 1)) * 2.0).round() / 4.0;
+
+        match runs.last_mut() {
+            Some((range, run_width)) if *run_width == width => {
+                *range = *range.start()..=end;
+            }
+            _ => runs.push((start..=end, width)),
+        }
+    }
+    runs
+}
+
+/// Center and width, in board pixels, of the round dot a stroke without a
+/// single segment leaves, as a tap does. `stroke_runs` has nothing for it.
+pub(crate) fn stroke_dot(stroke: &Stroke, board_size: [f32; 2]) -> Option<([f32; 2], f32)> {
+    if stroke.segments().next().is_some() {
+        return None;
+    }
+    let point = stroke.points.first()?;
+    Some((ndc_to_board(*point, board_size), stroke.width_at(0)))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PngExportOptions {
+    /// Output width in pixels; the height follows the board's aspect ratio.
+    pub width: u32,
+    pub background: Background,
+    pub crop_to_content: bool,
+}
+
+impl Default for PngExportOptions {
+    fn default() -> Self {
+        PngExportOptions {
+            width: 1920,
+            background: Background::White,
+            crop_to_content: false,
+        }
+    }
+}
+
+/// Rasterizes a page to a PNG file. `board_size` is the size in pixels of
+/// the board the page was drawn on, which text positions are relative to.
+/// Cropping keeps what differs from the empty page, paper included.
+pub fn export_png(
+    page: &PageContent,
+    board_size: [f32; 2],
+    options: &PngExportOptions,
+    path: impl AsRef<Path>,
+) -> Result<(), ExportError> {
+    let scale = options.width as f32 / board_size[0];
+    let height = (board_size[1] * scale).round().max(1.0) as u32;
+
+    let mut image =
+        offscreen::render_actions(page, options.width, height, scale, options.background)
+            .map_err(ExportError::Render)?;
+
+    if options.crop_to_content {
+        let empty = PageContent {
+            slide: None,
+            actions: &[],
+            ..*page
+        };
+        let empty =
+            offscreen::render_actions(&empty, options.width, height, scale, options.background)
+                .map_err(ExportError::Render)?;
+        if let Some((x, y, width, height)) = content_bounds(&image, &empty) {
+            image = imageops::crop_imm(&image, x, y, width, height).to_image();
+        }
+    }
+
+    image.save(path).map_err(ExportError::Image)
+}
+
+/// The region of `image` where it differs from `empty`, the same page
+/// rendered without content, with some padding.
+fn content_bounds(image: &RgbaImage, empty: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for ((x, y, pixel), blank) in image.enumerate_pixels().zip(empty.pixels()) {
+        if pixel != blank {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+
+    if min_x > max_x {
+        return None;
+    }
+
+    let x = min_x.saturating_sub(CROP_PADDING);
+    let y = min_y.saturating_sub(CROP_PADDING);
+    let right = (max_x + CROP_PADDING + 1).min(image.width());
+    let bottom = (max_y + CROP_PADDING + 1).min(image.height());
+    Some((x, y, right - x, bottom - y))
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+impl WindowState {
+    /// The size of the board in board pixels, the same on every client
+    /// whatever the window size.
+    pub fn board_size(&self) -> [f32; 2] {
+        camera::BOARD_SIZE
+    }
+
+    /// The committed actions of the current page in paint order.
+    pub fn board_actions(&self) -> Vec<Action> {
+        self.scene.committed().cloned().collect()
+    }
+
+    /// Exports the current page with its paper and slide as a PNG,
+    /// independent of the window size.
+    pub fn export_png(
+        &self,
+        path: impl AsRef<Path>,
+        options: &PngExportOptions,
+    ) -> Result<(), ExportError> {
+        let page = PageContent {
+            paper: self.current_paper(),
+            slide: self.current_slide(),
+            actions: &self.board_actions(),
+            images: &self.images,
+        };
+        export_png(&page, self.board_size(), options, path)
+    }
+
+    /// Asks where to export with the native file dialog and exports the board
+    /// there in `format`.
+    pub(crate) fn run_export(&mut self, format: ExportFormat) {
+        let extension = format.extension();
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(extension.to_uppercase(), &[extension])
+            .set_file_name(format!("{}.{}", self.metadata.title, extension))
+            .save_file()
+        else {
+            return;
+        };
+
+        let result = match format {
+            ExportFormat::Png => {
+                self.spawn_png_export(path);
+                Ok(())
+            }
+            ExportFormat::Svg => {
+                let options = SvgExportOptions {
+                    background: self.png_export_options.background,
+                    ..Default::default()
+                };
+                self.export_svg(path, &options)
+            }
+            ExportFormat::Pdf => self.export_pdf(path, &PdfExportOptions::default()),
+        };
+        if let Err(e) = result {
+            eprintln!("{:?}", e);
+        }
+    }
+
+    /// Same as `export_png` but runs on a background thread so the UI keeps
+    /// drawing while the board is rasterized.
+    pub(crate) fn spawn_png_export(&self, path: PathBuf) {
+        let paper = self.current_paper();
+        let slide = self.current_slide().cloned();
+        let actions = self.board_actions();
+        let images = self.images.clone();
+        let board_size = self.board_size();
+        let options = self.png_export_options;
+        std::thread::spawn(move || {
+            let page = PageContent {
+                paper,
+                slide: slide.as_ref(),
+                actions: &actions,
+                images: &images,
+            };
+            if let Err(e) = export_png(&page, board_size, &options, &path) {
+                eprintln!("{:?}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,390 @@
+use crate::{
+    camera::Camera,
+    curve, export, images, render, shapes,
+    structs::{Action, ActionType, Polygon, Rect},
+    tessellate,
+    vector::distance,
+};
+use glyphon::FontSystem;
+
+/// Points an ellipse is outlined with for hit testing and bounds.
+const ELLIPSE_STEPS: usize = 32;
+/// Points an ellipse is outlined with once it is turned into a polygon.
+const TURNED_ELLIPSE_STEPS: usize = 64;
+
+/// An affine transform of board pixels, as the first two rows of a 3x3 matrix.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Transform([[f32; 3]; 2]);
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]])
+    }
+}
+
+impl Transform {
+    pub(crate) fn translate(offset: [f32; 2]) -> Self {
+        Transform([[1.0, 0.0, offset[0]], [0.0, 1.0, offset[1]]])
+    }
+
+    /// Scales by `factor` on each axis, keeping `anchor` in place.
+    pub(crate) fn scale_about(anchor: [f32; 2], factor: [f32; 2]) -> Self {
+        Transform([
+            [factor[0], 0.0, anchor[0] * (1.0 - factor[0])],
+            [0.0, factor[1], anchor[1] * (1.0 - factor[1])],
+        ])
+    }
+
+    /// Turns by `angle` radians around `pivot`, clockwise on screen.
+    pub(crate) fn rotate_about(pivot: [f32; 2], angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Transform([
+            [cos, -sin, pivot[0] - cos * pivot[0] + sin * pivot[1]],
+            [sin, cos, pivot[1] - sin * pivot[0] - cos * pivot[1]],
+        ])
+    }
+
+    pub(crate) fn apply(&self, point: [f32; 2]) -> [f32; 2] {
+        let [row_x, row_y] = self.0;
+        [
+            row_x[0] * point[0] + row_x[1] * point[1] + row_x[2],
+            row_y[0] * point[0] + row_y[1] * point[1] + row_y[2],
+        ]
+    }
+
+    /// Applies the transform to a direction, leaving out the translation.
+    fn apply_vector(&self, vector: [f32; 2]) -> [f32; 2] {
+        let [row_x, row_y] = self.0;
+        [
+            row_x[0] * vector[0] + row_x[1] * vector[1],
+            row_y[0] * vector[0] + row_y[1] * vector[1],
+        ]
+    }
+
+    /// How much the transform stretches each axis, ignoring rotation.
+    fn axis_scale(&self) -> [f32; 2] {
+        let [row_x, row_y] = self.0;
+        [row_x[0].hypot(row_y[0]), row_x[1].hypot(row_y[1])]
+    }
+
+    /// Whether the transform turns what it is applied to.
+    fn rotates(&self) -> bool {
+        let [row_x, row_y] = self.0;
+        row_x[1].abs() > f32::EPSILON || row_y[0].abs() > f32::EPSILON
+    }
+
+    /// The factor line widths and font sizes scale by.
+    fn uniform_scale(&self) -> f32 {
+        let [row_x, row_y] = self.0;
+        (row_x[0] * row_y[1] - row_x[1] * row_y[0]).abs().sqrt()
+    }
+}
+
+/// Applies `transform` to an action on a board of `board_size`. Stroke and
+/// outline widths, corner radii and font sizes follow the scale. Rectangles
+/// and ellipses are stored by two corners and can't be turned, so a
+/// transform that turns them makes them polygons of their outline. Images
+/// turn with the transform; text stays upright and only its corner moves.
+pub(crate) fn transform_action(
+    action_type: &mut ActionType,
+    transform: &Transform,
+    board_size: [f32; 2],
+) {
+    let apply = |point: [f32; 2]| {
+        export::board_to_ndc(
+            transform.apply(export::ndc_to_board(point, board_size)),
+            board_size,
+        )
+    };
+    // Moves the center of the box between two corners and scales its sides.
+    let apply_box = |first: &mut [f32; 2], last: &mut [f32; 2]| {
+        let [x1, y1] = export::ndc_to_board(*first, board_size);
+        let [x2, y2] = export::ndc_to_board(*last, board_size);
+        let center = transform.apply([(x1 + x2) / 2.0, (y1 + y2) / 2.0]);
+        let scale = transform.axis_scale();
+        let half = [(x2 - x1) / 2.0 * scale[0], (y2 - y1) / 2.0 * scale[1]];
+        *first = export::board_to_ndc([center[0] - half[0], center[1] - half[1]], board_size);
+        *last = export::board_to_ndc([center[0] + half[0], center[1] + half[1]], board_size);
+    };
+
+    if let Some(mut polygon) = transform
+        .rotates()
+        .then(|| turnable_outline(action_type, board_size))
+        .flatten()
+    {
+        for point in &mut polygon.points {
+            *point = apply(*point);
+        }
+        polygon.outline_width *= transform.uniform_scale();
+        *action_type = ActionType::Polygon(polygon);
+        return;
+    }
+
+    match action_type {
+        ActionType::Stroke(stroke) => {
+            for point in &mut stroke.points {
+                *point = apply(*point);
+            }
+            stroke.width *= transform.uniform_scale();
+        }
+        ActionType::Shapes(rectangle) => {
+            apply_box(&mut rectangle.first, &mut rectangle.last);
+            rectangle.outline_width *= transform.uniform_scale();
+            rectangle.corner_radius *= transform.uniform_scale();
+        }
+        ActionType::Ellipse(ellipse) => {
+            apply_box(&mut ellipse.first, &mut ellipse.last);
+            ellipse.outline_width *= transform.uniform_scale();
+        }
+        ActionType::Line(line) => {
+            line.first = apply(line.first);
+            line.last = apply(line.last);
+            line.width *= transform.uniform_scale();
+        }
+        ActionType::Polygon(polygon) => {
+            for point in &mut polygon.points {
+                *point = apply(*point);
+            }
+            polygon.outline_width *= transform.uniform_scale();
+        }
+        ActionType::Text(text_entry) => {
+            let position = transform.apply(text_entry.position);
+            text_entry.bounds.x += position[0] - text_entry.position[0];
+            text_entry.bounds.y += position[1] - text_entry.position[1];
+            text_entry.position = position;
+            text_entry.font_size =
+                ((text_entry.font_size as f32 * transform.uniform_scale()).round() as i32).max(1);
+        }
+        ActionType::Image(image) => {
+            // The sides of the image as they are turned on the board.
+            let (sin, cos) = image.rotation.sin_cos();
+            let across = transform.apply_vector([cos, sin]);
+            let down = transform.apply_vector([-sin, cos]);
+            image.center = apply(image.center);
+            image.size[0] *= across[0].hypot(across[1]);
+            image.size[1] *= down[0].hypot(down[1]);
+            image.rotation = across[1].atan2(across[0]);
+        }
+    }
+}
+
+/// A rectangle or ellipse as a polygon with the same style, its points in
+/// NDC. Square corners stay single points; the outline joins are rounded.
+fn turnable_outline(action_type: &ActionType, board_size: [f32; 2]) -> Option<Polygon> {
+    let (points, color, fill, outline_width) = match action_type {
+        ActionType::Shapes(rectangle) => {
+            let first = export::ndc_to_board(rectangle.first, board_size);
+            let last = export::ndc_to_board(rectangle.last, board_size);
+            let center = [(first[0] + last[0]) / 2.0, (first[1] + last[1]) / 2.0];
+            let half = [
+                (last[0] - first[0]).abs() / 2.0,
+                (last[1] - first[1]).abs() / 2.0,
+            ];
+            let radius = rectangle.corner_radius.clamp(0.0, half[0].min(half[1]));
+            let mut points = tessellate::rounded_outline(center, half, radius);
+            points.dedup();
+            (
+                points,
+                rectangle.color,
+                rectangle.fill,
+                rectangle.outline_width,
+            )
+        }
+        ActionType::Ellipse(ellipse) => {
+            let (center, radii) = shapes::ellipse_box(ellipse, board_size);
+            (
+                shapes::ellipse_points(center, radii, TURNED_ELLIPSE_STEPS),
+                ellipse.color,
+                ellipse.fill,
+                ellipse.outline_width,
+            )
+        }
+        _ => return None,
+    };
+
+    Some(Polygon {
+        points: points
+            .into_iter()
+            .map(|point| export::board_to_ndc(point, board_size))
+            .collect(),
+        color,
+        fill,
+        outline_width,
+    })
+}
+
+/// Points in board pixels that outline what `action` covers: the flattened
+/// line of a stroke, the ends of a line, points around an ellipse, or the
+/// corners of a rectangle, a polygon, an image or laid out text. Text is
+/// outlined where `camera` shows it, upright on screen.
+pub(crate) fn outline_points(
+    action: &Action,
+    board_size: [f32; 2],
+    camera: &Camera,
+    font_system: &mut FontSystem,
+) -> Vec<[f32; 2]> {
+    match &action.action_type {
+        ActionType::Stroke(stroke) => curve::flatten(stroke, board_size)
+            .into_iter()
+            .map(|(point, _)| point)
+            .collect(),
+        ActionType::Shapes(rectangle) => {
+            let [x1, y1] = export::ndc_to_board(rectangle.first, board_size);
+            let [x2, y2] = export::ndc_to_board(rectangle.last, board_size);
+            vec![[x1, y1], [x2, y1], [x2, y2], [x1, y2]]
+        }
+        ActionType::Ellipse(ellipse) => {
+            let (center, radii) = shapes::ellipse_box(ellipse, board_size);
+            shapes::ellipse_points(center, radii, ELLIPSE_STEPS)
+        }
+        ActionType::Line(line) => vec![
+            export::ndc_to_board(line.first, board_size),
+            export::ndc_to_board(line.last, board_size),
+        ],
+        ActionType::Polygon(polygon) => polygon
+            .points
+            .iter()
+            .map(|point| export::ndc_to_board(*point, board_size))
+            .collect(),
+        ActionType::Text(text_entry) => render::text_extent(font_system, text_entry, board_size)
+            .map(|extent| upright_corners(&extent, text_entry.position, camera))
+            .unwrap_or_default(),
+        ActionType::Image(image) => images::corners(image, board_size).to_vec(),
+    }
+}
+
+/// The world corners of `extent`, laid out text at `position`, as the text
+/// is drawn: turned with the screen rather than the board, at the zoom of
+/// `camera`, which its size follows anyway.
+pub(crate) fn upright_corners(extent: &Rect, position: [f32; 2], camera: &Camera) -> Vec<[f32; 2]> {
+    let corner = camera.to_screen(position);
+    let (x1, y1) = (extent.x, extent.y);
+    let (x2, y2) = (extent.x + extent.width, extent.y + extent.height);
+    [[x1, y1], [x2, y1], [x2, y2], [x1, y2]]
+        .into_iter()
+        .map(|[x, y]| {
+            camera.to_world([
+                corner[0] + (x - position[0]) * camera.zoom,
+                corner[1] + (y - position[1]) * camera.zoom,
+            ])
+        })
+        .collect()
+}
+
+/// The smallest axis-aligned box around `points`.
+pub(crate) fn bounds(points: impl IntoIterator<Item = [f32; 2]>) -> Option<Rect> {
+    let (mut left, mut top, mut right, mut bottom) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for [x, y] in points {
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    (left <= right).then_some(Rect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Whether a disc of `radius` around `point`, in board pixels, touches
+/// `action` as `camera` shows it.
+pub(crate) fn touches(
+    action: &Action,
+    point: [f32; 2],
+    radius: f32,
+    board_size: [f32; 2],
+    camera: &Camera,
+    font_system: &mut FontSystem,
+) -> bool {
+    match &action.action_type {
+        ActionType::Stroke(stroke) => {
+            let reach = radius + stroke.width / 2.0;
+            let points = curve::flatten(stroke, board_size);
+            match points.as_slice() {
+                [(only, _)] => distance(point, *only) <= reach,
+                _ => points
+                    .windows(2)
+                    .any(|pair| segment_distance(point, pair[0].0, pair[1].0) <= reach),
+            }
+        }
+        ActionType::Shapes(rectangle) => outline_touches(
+            &outline_points(action, board_size, camera, font_system),
+            rectangle.fill,
+            rectangle.outline_width,
+            point,
+            radius,
+        ),
+        ActionType::Ellipse(ellipse) => outline_touches(
+            &outline_points(action, board_size, camera, font_system),
+            ellipse.fill,
+            ellipse.outline_width,
+            point,
+            radius,
+        ),
+        ActionType::Polygon(polygon) => outline_touches(
+            &outline_points(action, board_size, camera, font_system),
+            polygon.fill,
+            polygon.outline_width,
+            point,
+            radius,
+        ),
+        ActionType::Line(line) => {
+            let first = export::ndc_to_board(line.first, board_size);
+            let last = export::ndc_to_board(line.last, board_size);
+            segment_distance(point, first, last) <= radius + line.width / 2.0
+        }
+        // Text and images are solid, so touching them anywhere counts.
+        ActionType::Text(_) | ActionType::Image(_) => outline_touches(
+            &outline_points(action, board_size, camera, font_system),
+            [0.0, 0.0, 0.0, 1.0],
+            0.0,
+            point,
+            radius,
+        ),
+    }
+}
+
+/// Whether a disc of `radius` around `point` touches a closed shape with the
+/// corners `outline`: its outline of `outline_width`, or its inside if filled.
+fn outline_touches(
+    outline: &[[f32; 2]],
+    fill: [f32; 4],
+    outline_width: f32,
+    point: [f32; 2],
+    radius: f32,
+) -> bool {
+    let reach = radius + outline_width / 2.0;
+    (fill[3] > 0.0 && polygon_contains(outline, point))
+        || (0..outline.len()).any(|index| {
+            let next = outline[(index + 1) % outline.len()];
+            segment_distance(point, outline[index], next) <= reach
+        })
+}
+
+/// Even-odd test of `point` against the closed `polygon`.
+pub(crate) fn polygon_contains(polygon: &[[f32; 2]], point: [f32; 2]) -> bool {
+    let mut inside = false;
+    for index in 0..polygon.len() {
+        let a = polygon[index];
+        let b = polygon[(index + 1) % polygon.len()];
+        if (a[1] > point[1]) != (b[1] > point[1])
+            && point[0] < a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+pub(crate) fn segment_distance(point: [f32; 2], start: [f32; 2], end: [f32; 2]) -> f32 {
+    let [dx, dy] = [end[0] - start[0], end[1] - start[1]];
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((point[0] - start[0]) * dx + (point[1] - start[1]) * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    distance(point, [start[0] + dx * t, start[1] + dy * t])
+}
@@ -0,0 +1,355 @@
+use crate::{
+    export, render,
+    structs::{ActionType, Image, ImageVertex},
+    sync, WindowState,
+};
+use image::RgbaImage;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use stream::desktop::stream::PaintEvent;
+
+/// Bytes of an encoded image sent in one message. Base64 makes them a third
+/// larger on the wire.
+pub(crate) const CHUNK_SIZE: usize = 48 * 1024;
+/// Largest image file that can be inserted or received from the room.
+pub(crate) const MAX_FILE_SIZE: usize = 32 * 1024 * 1024;
+/// Most chunks an image file can arrive in.
+pub(crate) const MAX_CHUNKS: usize = MAX_FILE_SIZE.div_ceil(CHUNK_SIZE);
+/// Most images arriving at once. Starting another drops the one that got a
+/// chunk least recently.
+const MAX_INCOMING: usize = 4;
+/// How long an image can go without a chunk before what arrived is dropped.
+const INCOMING_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest side images are kept at for drawing; larger ones are scaled down
+/// to stay within the texture limits of every GPU.
+const MAX_TEXTURE_SIDE: u32 = 4096;
+/// Share of the board a new image fills at most, so it fits on screen.
+const MAX_BOARD_SHARE: f32 = 0.6;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    Decode(image::ImageError),
+    Clipboard(arboard::Error),
+    /// The file is larger than `MAX_FILE_SIZE`, in bytes.
+    TooLarge(usize),
+}
+
+/// SHA-256 of `bytes` in lowercase hex, the name images are shared under.
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// An image file and its decoded pixels, no larger than `MAX_TEXTURE_SIDE`.
+#[derive(Clone, Debug)]
+pub struct StoredImage {
+    pub bytes: Arc<Vec<u8>>,
+    pub pixels: Arc<RgbaImage>,
+}
+
+/// Decodes an image file, scaled down to `MAX_TEXTURE_SIDE` if it is larger.
+pub(crate) fn decode(bytes: Vec<u8>) -> Result<StoredImage, image::ImageError> {
+    let mut decoded = image::load_from_memory(&bytes)?;
+    if decoded.width().max(decoded.height()) > MAX_TEXTURE_SIDE {
+        decoded = decoded.resize(
+            MAX_TEXTURE_SIDE,
+            MAX_TEXTURE_SIDE,
+            image::imageops::FilterType::Triangle,
+        );
+    }
+    Ok(StoredImage {
+        bytes: Arc::new(bytes),
+        pixels: Arc::new(decoded.to_rgba8()),
+    })
+}
+
+/// Pixels of the images on a board by content hash, and the images still
+/// arriving from the room chunk by chunk.
+#[derive(Clone, Debug, Default)]
+pub struct ImageStore {
+    images: HashMap<String, StoredImage>,
+    incoming: HashMap<String, IncomingImage>,
+}
+
+/// The chunks of an image file received so far.
+#[derive(Clone, Debug)]
+struct IncomingImage {
+    chunks: Vec<Option<Vec<u8>>>,
+    last_chunk: Instant,
+}
+
+impl ImageStore {
+    pub fn get(&self, hash: &str) -> Option<&StoredImage> {
+        self.images.get(hash)
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.images.contains_key(hash)
+    }
+
+    /// Decodes an image file and keeps it under its hash, which is returned.
+    pub fn insert(&mut self, bytes: Vec<u8>) -> Result<String, image::ImageError> {
+        let hash = content_hash(&bytes);
+        if !self.images.contains_key(&hash) {
+            self.images.insert(hash.clone(), decode(bytes)?);
+        }
+        Ok(hash)
+    }
+
+    /// Keeps an image decoded with `decode` under its hash.
+    pub(crate) fn insert_decoded(&mut self, hash: String, image: StoredImage) {
+        self.images.entry(hash).or_insert(image);
+    }
+
+    /// Keeps chunk `index` of `count` of the image `hash`. Once every chunk
+    /// is in, the file is checked against the hash and decoded. Returns
+    /// whether that completed the image. Chunks of files larger than
+    /// `MAX_FILE_SIZE` are ignored.
+    pub fn receive_chunk(&mut self, hash: &str, index: usize, count: usize, data: Vec<u8>) -> bool {
+        if self.images.contains_key(hash)
+            || index >= count
+            || count > MAX_CHUNKS
+            || data.len() > CHUNK_SIZE
+        {
+            return false;
+        }
+
+        let now = Instant::now();
+        self.incoming
+            .retain(|_, incoming| now.duration_since(incoming.last_chunk) < INCOMING_TIMEOUT);
+        if !self.incoming.contains_key(hash) && self.incoming.len() >= MAX_INCOMING {
+            let stalest = self
+                .incoming
+                .iter()
+                .min_by_key(|(_, incoming)| incoming.last_chunk)
+                .map(|(hash, _)| hash.clone());
+            if let Some(stalest) = stalest {
+                self.incoming.remove(&stalest);
+            }
+        }
+
+        let incoming = self
+            .incoming
+            .entry(hash.to_owned())
+            .or_insert_with(|| IncomingImage {
+                chunks: vec![None; count],
+                last_chunk: now,
+            });
+        if incoming.chunks.len() != count {
+            incoming.chunks = vec![None; count];
+        }
+        incoming.chunks[index] = Some(data);
+        incoming.last_chunk = now;
+        if incoming.chunks.iter().any(Option::is_none) {
+            return false;
+        }
+
+        let bytes: Vec<u8> = self
+            .incoming
+            .remove(hash)
+            .into_iter()
+            .flat_map(|incoming| incoming.chunks)
+            .flatten()
+            .flatten()
+            .collect();
+        if content_hash(&bytes) != hash {
+            return false;
+        }
+        match self.insert(bytes) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("{:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// The corners of `image` in board pixels, clockwise from the top left one
+/// as it is shown.
+pub(crate) fn corners(image: &Image, board_size: [f32; 2]) -> [[f32; 2]; 4] {
+    let [x, y] = export::ndc_to_board(image.center, board_size);
+    let [half_width, half_height] = [image.size[0] / 2.0, image.size[1] / 2.0];
+    let (sin, cos) = image.rotation.sin_cos();
+    [
+        [-half_width, -half_height],
+        [half_width, -half_height],
+        [half_width, half_height],
+        [-half_width, half_height],
+    ]
+    .map(|[dx, dy]| [x + cos * dx - sin * dy, y + sin * dx + cos * dy])
+}
+
+/// Two triangles in NDC covering `image`, textured from its top left corner.
+pub(crate) fn image_vertices(image: &Image, board_size: [f32; 2]) -> Vec<ImageVertex> {
+    let corners = corners(image, board_size).map(|corner| export::board_to_ndc(corner, board_size));
+    let uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+    [0, 1, 2, 0, 2, 3]
+        .into_iter()
+        .map(|index| ImageVertex {
+            position: corners[index],
+            uv: uvs[index],
+        })
+        .collect()
+}
+
+/// The messages that carry the file of `image` to the room.
+pub(crate) fn chunk_events(room: &str, hash: &str, image: &StoredImage) -> Vec<PaintEvent> {
+    let count = image.bytes.len().div_ceil(CHUNK_SIZE).max(1);
+    (0..count)
+        .map(|index| {
+            let start = index * CHUNK_SIZE;
+            let end = (start + CHUNK_SIZE).min(image.bytes.len());
+            sync::image_chunk_event(room, hash, index, count, &image.bytes[start..end])
+        })
+        .collect()
+}
+
+impl WindowState {
+    /// Places an image file in the middle of the view, scaled down to fit
+    /// the board if it is larger.
+    pub fn insert_image(&mut self, bytes: Vec<u8>) -> Result<(), ImageError> {
+        if bytes.len() > MAX_FILE_SIZE {
+            return Err(ImageError::TooLarge(bytes.len()));
+        }
+        let hash = self.images.insert(bytes).map_err(ImageError::Decode)?;
+        let Some(stored) = self.images.get(&hash) else {
+            return Ok(());
+        };
+
+        let board_size = self.board_size();
+        let [width, height] = [
+            stored.pixels.width().max(1) as f32,
+            stored.pixels.height().max(1) as f32,
+        ];
+        let scale = (board_size[0] * MAX_BOARD_SHARE / width)
+            .min(board_size[1] * MAX_BOARD_SHARE / height)
+            .min(1.0);
+        let center = self.camera.to_world(self.screen_center());
+        self.commit_text();
+        self.add_action(ActionType::Image(Image {
+            center: export::board_to_ndc(center, board_size),
+            size: [width * scale, height * scale],
+            rotation: -self.camera.rotation,
+            hash,
+        }));
+        self.window.request_redraw();
+        Ok(())
+    }
+
+    pub fn insert_image_file(&mut self, path: impl AsRef<Path>) -> Result<(), ImageError> {
+        self.insert_image(std::fs::read(path).map_err(ImageError::Io)?)
+    }
+
+    /// Inserts the image on the clipboard, stored as PNG.
+    pub fn paste_image(&mut self) -> Result<(), ImageError> {
+        let clipboard_image = arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_image())
+            .map_err(ImageError::Clipboard)?;
+        let pixels = RgbaImage::from_raw(
+            clipboard_image.width as u32,
+            clipboard_image.height as u32,
+            clipboard_image.bytes.into_owned(),
+        );
+        let Some(pixels) = pixels else {
+            return Ok(());
+        };
+
+        let mut bytes = Vec::new();
+        pixels
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .map_err(ImageError::Decode)?;
+        self.insert_image(bytes)
+    }
+
+    /// Asks for an image file with the native file dialog and inserts it.
+    pub(crate) fn open_image(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Image", &["png", "jpg", "jpeg"])
+            .pick_file()
+        else {
+            return;
+        };
+        if let Err(e) = self.insert_image_file(path) {
+            eprintln!("{:?}", e);
+        }
+    }
+
+    /// The messages that carry the files of the images among `hashes`, each
+    /// once, whether the room was sent them before or not. A full resync
+    /// sends them all, since who joined since has none.
+    pub(crate) fn image_events<'a>(
+        &mut self,
+        hashes: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<PaintEvent> {
+        let mut events = Vec::new();
+        let mut sent = HashSet::new();
+        for hash in hashes {
+            let Some(stored) = self.images.get(hash) else {
+                continue;
+            };
+            if sent.insert(hash) {
+                self.shared_images.insert(hash.clone());
+                events.extend(chunk_events(&self.room, hash, stored));
+            }
+        }
+        events
+    }
+
+    /// The messages that carry the files of the images among `hashes` the
+    /// room hasn't been sent yet, for changes the whole room has seen.
+    pub(crate) fn unshared_image_events<'a>(
+        &mut self,
+        hashes: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<PaintEvent> {
+        let mut events = Vec::new();
+        for hash in hashes {
+            let Some(stored) = self.images.get(hash) else {
+                continue;
+            };
+            if self.shared_images.insert(hash.clone()) {
+                events.extend(chunk_events(&self.room, hash, stored));
+            }
+        }
+        events
+    }
+
+    /// The slide and images of the current page, in paint order.
+    pub(crate) fn page_images(&self) -> impl Iterator<Item = &Image> {
+        let actions = self
+            .scene
+            .iter()
+            .filter_map(|action| match &action.action_type {
+                ActionType::Image(image) => Some(image),
+                _ => None,
+            });
+        self.current_slide().into_iter().chain(actions)
+    }
+
+    /// Uploads the pixels of the slide and images on the current page that
+    /// have none on the GPU yet.
+    pub(crate) fn prepare_image_textures(&mut self) {
+        let missing: HashSet<String> = self
+            .page_images()
+            .filter(|image| !self.image_textures.contains_key(&image.hash))
+            .map(|image| image.hash.clone())
+            .collect();
+        for hash in missing {
+            if let Some(stored) = self.images.get(&hash) {
+                let bind_group = render::image_binding(
+                    &self.device,
+                    &self.queue,
+                    &self.image_layout,
+                    &stored.pixels,
+                );
+                self.image_textures.insert(hash, bind_group);
+            }
+        }
+    }
+}
@@ -1,9 +1,65 @@
 mod app;
+mod board_file;
+mod camera;
+mod caret;
+mod curve;
+mod eraser;
+mod export;
+mod geometry;
+mod images;
+mod offscreen;
+mod pages;
+mod paper;
+mod pdf;
+mod recognition;
+mod render;
+mod scene;
+mod selection;
+mod session;
+mod shapes;
+mod slides;
+mod smoothing;
 mod structs;
+mod svg;
+mod sync;
+mod tessellate;
 mod utils;
+mod vector;
 mod window;
 
 pub use crate::app::Application;
+pub use crate::board_file::{
+    BoardFile, BoardFileError, BoardMetadata, BOARD_FILE_EXTENSION, BOARD_FILE_VERSION,
+};
+pub use crate::camera::{Camera, BOARD_SIZE};
+pub use crate::caret::TextCaret;
+pub use crate::eraser::EraserMode;
+pub use crate::export::{export_png, ExportError, ExportFormat, PageContent, PngExportOptions};
+pub use crate::images::{ImageError, ImageStore};
+pub use crate::offscreen::{render_actions, OffscreenError, OffscreenRenderer};
+pub use crate::pages::{Page, PageCommand};
+pub use crate::paper::{Paper, PaperKind};
+pub use crate::pdf::{export_pdf, PageSize, PdfExportOptions};
+pub use crate::recognition::RecognitionMode;
+pub use crate::scene::Scene;
+pub use crate::selection::{Selection, SelectionMode};
+pub use crate::session::Session;
+pub use crate::shapes::ShapeKind;
+pub use crate::slides::{slide_files, SlideImport};
+pub use crate::smoothing::{Stabilizer, StabilizerMode, StabilizerOptions};
+pub use crate::structs::Action;
+pub use crate::structs::ActionType;
+pub use crate::structs::ArrowHeads;
+pub use crate::structs::Background;
+pub use crate::structs::Ellipse;
+pub use crate::structs::Image;
+pub use crate::structs::Line;
+pub use crate::structs::LineJoin;
+pub use crate::structs::Polygon;
+pub use crate::structs::Polyline;
+pub use crate::structs::Rectangle;
+pub use crate::structs::Stroke;
 pub use crate::structs::TextEntries;
 pub use crate::structs::Vertex;
 pub use crate::structs::WindowState;
+pub use crate::svg::{export_svg, SvgExportOptions};
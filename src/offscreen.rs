@@ -0,0 +1,573 @@
+use crate::{
+    camera::Camera,
+    export::PageContent,
+    paper::PaperUniform,
+    render::{self, BoardBatches, BoardPipelines},
+    structs::{ActionType, Background},
+};
+use glyphon::{
+    Buffer, Cache, Color, FontSystem, Resolution, SwashCache, TextArea, TextAtlas, TextBounds,
+    TextRenderer, Viewport,
+};
+use image::RgbaImage;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+use wgpu::{
+    util::DeviceExt, BindGroup, BindGroupLayout, Device, DeviceDescriptor, Extent3d, Instance,
+    InstanceDescriptor, Queue, RenderPipeline, RequestAdapterOptions, TextureDescriptor,
+    TextureFormat, TextureUsages,
+};
+
+const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Debug)]
+pub enum OffscreenError {
+    NoAdapter,
+    /// The requested width and height: empty, or larger than the device
+    /// can render.
+    Size(u32, u32),
+    RequestDevice(wgpu::RequestDeviceError),
+    Prepare(glyphon::PrepareError),
+    Render(glyphon::RenderError),
+    Map(wgpu::BufferAsyncError),
+}
+
+/// Renders boards into an offscreen texture, without a window or surface.
+/// Uses the same pipelines as `WindowState` so the output matches the screen.
+pub struct OffscreenRenderer {
+    device: Device,
+    queue: Queue,
+    render_pipeline: RenderPipeline,
+    shape_shader: RenderPipeline,
+    image_pipeline: RenderPipeline,
+    image_layout: BindGroupLayout,
+    paper_pipeline: RenderPipeline,
+    paper_layout: BindGroupLayout,
+    /// An unmoved camera: boards are rendered as stored, whatever the view.
+    camera_bind_group: BindGroup,
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    viewport: Viewport,
+    atlas: TextAtlas,
+    /// One per run of text, so text is layered between the other content.
+    text_renderers: Vec<TextRenderer>,
+}
+
+impl OffscreenRenderer {
+    /// Picks the default adapter and falls back to a software adapter when no
+    /// hardware one is available.
+    pub async fn new() -> Result<Self, OffscreenError> {
+        let instance = Instance::new(InstanceDescriptor::default());
+
+        let adapter = match instance
+            .request_adapter(&RequestAdapterOptions::default())
+            .await
+        {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&RequestAdapterOptions {
+                    force_fallback_adapter: true,
+                    ..Default::default()
+                })
+                .await
+                .ok_or(OffscreenError::NoAdapter)?,
+        };
+
+        let (device, queue) = adapter
+            .request_device(&DeviceDescriptor::default(), None)
+            .await
+            .map_err(OffscreenError::RequestDevice)?;
+
+        let cache = Cache::new(&device);
+        let viewport = Viewport::new(&device, &cache);
+        let atlas = TextAtlas::new(&device, &queue, &cache, OFFSCREEN_FORMAT);
+
+        let camera_layout = render::camera_bind_group_layout(&device);
+        // The bind group keeps the buffer alive, and it is never written again.
+        let (_, camera_bind_group) = render::camera_binding(
+            &device,
+            &camera_layout,
+            Camera::default().matrix([1.0, 1.0]),
+        );
+
+        let image_layout = render::image_bind_group_layout(&device);
+        let paper_layout = render::paper_bind_group_layout(&device);
+
+        Ok(Self {
+            image_pipeline: render::create_image_pipeline(
+                &device,
+                OFFSCREEN_FORMAT,
+                &camera_layout,
+                &image_layout,
+            ),
+            image_layout,
+            paper_pipeline: render::create_paper_pipeline(
+                &device,
+                OFFSCREEN_FORMAT,
+                &camera_layout,
+                &paper_layout,
+            ),
+            paper_layout,
+            render_pipeline: render::create_stroke_pipeline(
+                &device,
+                OFFSCREEN_FORMAT,
+                &camera_layout,
+            ),
+            shape_shader: render::create_shape_pipeline(&device, OFFSCREEN_FORMAT, &camera_layout),
+            camera_bind_group,
+            device,
+            queue,
+            font_system: render::font_system(),
+            swash_cache: SwashCache::new(),
+            viewport,
+            atlas,
+            text_renderers: Vec::new(),
+        })
+    }
+
+    /// Renders a page into a `width` x `height` image. Strokes, shapes and
+    /// images are stored in NDC and fill the image; text positions and font
+    /// sizes are multiplied by `scale`. The page is drawn on its paper over a
+    /// white background, and without it over a transparent one. Sizes the
+    /// device can't render are refused with `OffscreenError::Size`.
+    pub fn render(
+        &mut self,
+        page: &PageContent,
+        width: u32,
+        height: u32,
+        scale: f32,
+        background: Background,
+    ) -> Result<RgbaImage, OffscreenError> {
+        let limits = self.device.limits();
+        let padded_bytes_per_row = (width as u64 * 4)
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64;
+        if width == 0
+            || height == 0
+            || width > limits.max_texture_dimension_2d
+            || height > limits.max_texture_dimension_2d
+            || padded_bytes_per_row * height as u64 > limits.max_buffer_size
+        {
+            return Err(OffscreenError::Size(width, height));
+        }
+
+        let extent = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OFFSCREEN_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let board_size = [width as f32 / scale, height as f32 / scale];
+        let textures: HashMap<&str, BindGroup> = page
+            .slide
+            .into_iter()
+            .chain(
+                page.actions
+                    .iter()
+                    .filter_map(|action| match &action.action_type {
+                        ActionType::Image(image) => Some(image),
+                        _ => None,
+                    }),
+            )
+            .filter_map(|image| {
+                let stored = page.images.get(&image.hash)?;
+                let texture = render::image_binding(
+                    &self.device,
+                    &self.queue,
+                    &self.image_layout,
+                    &stored.pixels,
+                );
+                Some((image.hash.as_str(), texture))
+            })
+            .collect();
+        let paper = (background == Background::White).then(|| {
+            let (_, bind_group) = render::paper_binding(
+                &self.device,
+                &self.paper_layout,
+                PaperUniform::new(&page.paper, board_size),
+            );
+            let vertices = Camera::default().view_quad(board_size, page.paper.color);
+            let vertex_buffer = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Offscreen Paper Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+            (bind_group, vertex_buffer, vertices.len() as u32)
+        });
+        let board = BoardBatches::new(
+            page.slide,
+            page.actions.iter().map(|action| &action.action_type),
+            |hash| textures.get(hash),
+            board_size,
+        );
+
+        self.viewport
+            .update(&self.queue, Resolution { width, height });
+        render::reserve_text_renderers(
+            &mut self.text_renderers,
+            &board,
+            &mut self.atlas,
+            &self.device,
+        );
+        for (texts, text_renderer) in board.texts().zip(&mut self.text_renderers) {
+            let buffers: Vec<Buffer> = texts
+                .iter()
+                .map(|text_entry| {
+                    render::text_buffer(
+                        &mut self.font_system,
+                        text_entry,
+                        board_size[0],
+                        board_size[1],
+                    )
+                })
+                .collect();
+            let text_areas = texts
+                .iter()
+                .zip(&buffers)
+                .map(|(text_entry, buffer)| TextArea {
+                    buffer,
+                    left: text_entry.position[0] * scale,
+                    top: text_entry.position[1] * scale,
+                    scale,
+                    bounds: TextBounds {
+                        left: 0,
+                        top: 0,
+                        right: width as i32,
+                        bottom: height as i32,
+                    },
+                    default_color: Color::rgba(
+                        text_entry.color[0],
+                        text_entry.color[1],
+                        text_entry.color[2],
+                        text_entry.color[3],
+                    ),
+                    custom_glyphs: &[],
+                });
+            text_renderer
+                .prepare(
+                    &self.device,
+                    &self.queue,
+                    &mut self.font_system,
+                    &mut self.atlas,
+                    &self.viewport,
+                    text_areas,
+                    &mut self.swash_cache,
+                )
+                .map_err(OffscreenError::Prepare)?;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen Encoder"),
+            });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Offscreen Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(match background {
+                            Background::Transparent => wgpu::Color::TRANSPARENT,
+                            Background::White => wgpu::Color::WHITE,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            if let Some((bind_group, vertex_buffer, count)) = &paper {
+                render_pass.set_pipeline(&self.paper_pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(1, bind_group, &[]);
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.draw(0..*count, 0..1);
+            }
+
+            let pipelines = BoardPipelines {
+                images: &self.image_pipeline,
+                shapes: &self.shape_shader,
+                strokes: &self.render_pipeline,
+                camera: &self.camera_bind_group,
+                letterbox: None,
+            };
+            board
+                .draw(
+                    &self.device,
+                    &mut render_pass,
+                    &pipelines,
+                    &self.text_renderers,
+                    &self.atlas,
+                    &self.viewport,
+                )
+                .map_err(OffscreenError::Render)?;
+        }
+
+        let unpadded_bytes_per_row = width * 4;
+        // Fits, as the buffer size was checked against the device limits.
+        let padded_bytes_per_row = padded_bytes_per_row as u32;
+        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Output Buffer"),
+            size: padded_bytes_per_row as wgpu::BufferAddress * height as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            extent,
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = output_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .unwrap_or(Err(wgpu::BufferAsyncError))
+            .map_err(OffscreenError::Map)?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        output_buffer.unmap();
+        self.atlas.trim();
+
+        Ok(RgbaImage::from_raw(width, height, pixels).expect("pixel buffer matches image size"))
+    }
+}
+
+/// The renderer `render_actions` shares between calls, created on first use.
+static SHARED_RENDERER: Mutex<Option<OffscreenRenderer>> = Mutex::new(None);
+
+/// Renders a page with one `OffscreenRenderer` shared by every call, so the
+/// adapter, pipelines and glyph atlas are only set up once.
+pub fn render_actions(
+    page: &PageContent,
+    width: u32,
+    height: u32,
+    scale: f32,
+    background: Background,
+) -> Result<RgbaImage, OffscreenError> {
+    let mut shared = SHARED_RENDERER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let renderer = match &mut *shared {
+        Some(renderer) => renderer,
+        None => shared.insert(pollster::block_on(OffscreenRenderer::new())?),
+    };
+    renderer.render(page, width, height, scale, background)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        images::ImageStore,
+        paper::{Paper, PaperKind, PAPER_COLORS},
+        structs::{Action, ArrowHeads, Ellipse, Image, Line, LineJoin, Polygon, Rectangle, Stroke},
+        TextEntries,
+    };
+    use image::{ImageFormat, Rgba};
+    use std::io::Cursor;
+    use uuid::Uuid;
+
+    /// Rendered with llvmpipe. Set `UPDATE_GOLDEN` to write it again after an
+    /// intended change in how boards look.
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/board.png");
+    /// Largest difference of any channel for a pixel to still match, since
+    /// adapters antialias edges and glyphs a little differently.
+    const CHANNEL_TOLERANCE: u8 = 32;
+    /// Share of pixels that may differ by more than the channel tolerance.
+    const PIXEL_TOLERANCE: f32 = 0.005;
+
+    fn renderer() -> OffscreenRenderer {
+        pollster::block_on(OffscreenRenderer::new())
+            .expect("an adapter, software if need be, to render with")
+    }
+
+    fn action(action_type: ActionType) -> Action {
+        Action {
+            id: Uuid::nil(),
+            action_type,
+        }
+    }
+
+    /// A slide on ruled paper under every kind of shape, a stroke and
+    /// right-to-left text in the bundled font, overlapping so the order
+    /// shows.
+    fn scene(images: &mut ImageStore) -> (Image, Vec<Action>) {
+        let checker = RgbaImage::from_fn(4, 4, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([230, 230, 250, 255])
+            } else {
+                Rgba([200, 220, 200, 255])
+            }
+        });
+        let mut png = Vec::new();
+        checker
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let hash = images.insert(png).unwrap();
+        let slide = Image {
+            center: [0.0, 0.0],
+            size: [240.0, 120.0],
+            rotation: 0.0,
+            hash,
+        };
+
+        let actions = vec![
+            action(ActionType::Shapes(Rectangle {
+                first: [-0.9, 0.8],
+                last: [-0.1, 0.1],
+                color: [0.1, 0.2, 0.8, 1.0],
+                fill: [0.6, 0.7, 1.0, 1.0],
+                outline_width: 4.0,
+                corner_radius: 12.0,
+            })),
+            action(ActionType::Ellipse(Ellipse {
+                first: [0.1, 0.8],
+                last: [0.9, 0.1],
+                color: [0.8, 0.1, 0.1, 1.0],
+                fill: [1.0, 0.8, 0.6, 0.5],
+                outline_width: 3.0,
+            })),
+            action(ActionType::Polygon(Polygon {
+                points: vec![[-0.8, -0.8], [-0.5, -0.1], [-0.2, -0.8]],
+                color: [0.0, 0.5, 0.0, 1.0],
+                fill: [0.5, 0.9, 0.5, 1.0],
+                outline_width: 2.0,
+            })),
+            action(ActionType::Stroke(Stroke {
+                color: [0.0, 0.0, 0.0, 1.0],
+                width: 8.0,
+                points: vec![
+                    [-0.95, 0.0],
+                    [-0.5, 0.6],
+                    [0.5, -0.6],
+                    [0.3, 0.0],
+                    [0.3, 0.3],
+                    [0.6, 0.3],
+                    [0.95, -0.2],
+                ],
+                pressure: vec![0.5, 1.0, 0.7],
+                join: LineJoin::Miter,
+            })),
+            action(ActionType::Line(Line {
+                first: [0.1, -0.8],
+                last: [0.9, -0.3],
+                color: [0.5, 0.0, 0.5, 1.0],
+                width: 3.0,
+                heads: ArrowHeads::Both,
+            })),
+            action(ActionType::Text(TextEntries {
+                position: [120.0, 128.0],
+                text: "Board سلام".to_owned(),
+                pending: false,
+                ..TextEntries::null([20, 20, 120, 255], 24)
+            })),
+        ];
+        (slide, actions)
+    }
+
+    #[test]
+    fn refuses_sizes_the_device_cannot_render() {
+        let mut renderer = renderer();
+        let images = ImageStore::default();
+        let page = PageContent {
+            paper: Paper::default(),
+            slide: None,
+            actions: &[],
+            images: &images,
+        };
+        let too_large = renderer.device.limits().max_texture_dimension_2d + 1;
+        for (width, height) in [(0, 180), (320, 0), (too_large, 180), (320, too_large)] {
+            assert!(matches!(
+                renderer.render(&page, width, height, 1.0, Background::White),
+                Err(OffscreenError::Size(w, h)) if (w, h) == (width, height)
+            ));
+        }
+    }
+
+    #[test]
+    fn renders_the_golden_board() {
+        let mut renderer = renderer();
+        let mut images = ImageStore::default();
+        let (slide, actions) = scene(&mut images);
+        let page = PageContent {
+            paper: Paper {
+                kind: PaperKind::Ruled,
+                color: PAPER_COLORS[1],
+            },
+            slide: Some(&slide),
+            actions: &actions,
+            images: &images,
+        };
+        let rendered = renderer
+            .render(&page, 320, 180, 1.0, Background::White)
+            .unwrap();
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            rendered.save(GOLDEN).unwrap();
+        }
+        let golden = image::open(GOLDEN).unwrap().to_rgba8();
+        assert_eq!(golden.dimensions(), rendered.dimensions());
+        let differing = golden
+            .pixels()
+            .zip(rendered.pixels())
+            .filter(|(expected, actual)| {
+                expected
+                    .0
+                    .iter()
+                    .zip(actual.0)
+                    .any(|(expected, actual)| expected.abs_diff(actual) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        let allowed = (PIXEL_TOLERANCE * (golden.width() * golden.height()) as f32) as usize;
+        assert!(
+            differing <= allowed,
+            "{differing} pixels differ from {GOLDEN}, at most {allowed} may"
+        );
+    }
+}
@@ -0,0 +1,587 @@
+use crate::{
+    camera::BOARD_SIZE,
+    curve,
+    export::{self, BoardPainter},
+    images::{self, ImageStore, StoredImage},
+    paper::Paper,
+    scene::Scene,
+    selection::Selection,
+    shapes,
+    structs::{Action, ActionType, Ellipse, Image, Line, Polygon, Polyline, Rectangle, Stroke},
+    sync, utils, TextEntries, WindowState,
+};
+use egui::{Color32, Painter, Pos2, TextureHandle};
+use std::collections::HashMap;
+use stream::desktop::stream::{paint_event::ActionType as StreamActionType, PaintEvent};
+use uuid::Uuid;
+
+/// Width of a page thumbnail in the page strip, in points.
+pub(crate) const THUMBNAIL_WIDTH: f32 = 96.0;
+/// Id of the egui area the page strip is shown in.
+pub(crate) const PAGE_STRIP: &str = "Pages";
+/// Points an ellipse is outlined with in a thumbnail.
+const THUMBNAIL_ELLIPSE_STEPS: usize = 24;
+/// Longest side images are scaled down to for thumbnails, in pixels.
+const THUMBNAIL_TEXTURE_SIDE: u32 = 256;
+
+fn image_of(action: &Action) -> Option<&Image> {
+    match &action.action_type {
+        ActionType::Image(image) => Some(image),
+        _ => None,
+    }
+}
+
+/// One page of a board, with its own actions in paint order.
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub id: Uuid,
+    /// Empty while the page is the current one; its actions are in
+    /// `WindowState::scene` then.
+    pub scene: Scene,
+    pub paper: Paper,
+    /// An imported slide shown over the paper. It is part of the page rather
+    /// than an action, so it can't be erased, selected or undone.
+    pub slide: Option<Image>,
+}
+
+impl Page {
+    pub fn new(id: Uuid) -> Self {
+        Page {
+            id,
+            scene: Scene::new(),
+            paper: Paper::default(),
+            slide: None,
+        }
+    }
+
+    /// The page every board starts with. All participants give it the nil id,
+    /// so they agree on it before any page is shared.
+    pub fn first() -> Self {
+        Page::new(Uuid::nil())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageCommand {
+    Previous,
+    Next,
+    Add,
+    Delete,
+    Show(usize),
+}
+
+/// Draws a page scaled down into an egui painter, `scale` points per board
+/// pixel from `origin`.
+struct ThumbnailPainter<'a> {
+    painter: &'a Painter,
+    origin: Pos2,
+    scale: f32,
+    /// Small copies of the images on the pages, by content hash.
+    textures: &'a mut HashMap<String, TextureHandle>,
+}
+
+impl ThumbnailPainter<'_> {
+    fn point(&self, point: [f32; 2]) -> Pos2 {
+        Pos2::new(
+            self.origin.x + point[0] * self.scale,
+            self.origin.y + point[1] * self.scale,
+        )
+    }
+
+    fn ndc(&self, position: [f32; 2]) -> Pos2 {
+        self.point(export::ndc_to_board(position, BOARD_SIZE))
+    }
+
+    fn pen(&self, color: [f32; 4], width: f32) -> egui::Stroke {
+        egui::Stroke::new((width * self.scale).max(0.5), color32(color))
+    }
+
+    /// A closed outline with an optional fill. egui fills as if the shape
+    /// were convex, which is close enough at thumbnail size.
+    fn closed(&self, points: Vec<Pos2>, color: [f32; 4], fill: [f32; 4], outline_width: f32) {
+        let outline = if outline_width > 0.0 {
+            self.pen(color, outline_width)
+        } else {
+            egui::Stroke::NONE
+        };
+        let fill = if fill[3] > 0.0 {
+            color32(fill)
+        } else {
+            Color32::TRANSPARENT
+        };
+        self.painter
+            .add(egui::Shape::convex_polygon(points, fill, outline));
+    }
+}
+
+impl BoardPainter for ThumbnailPainter<'_> {
+    /// Only the color; the lines would be too fine to see.
+    fn paper(&mut self, paper: &Paper) {
+        let board = egui::Rect::from_min_max(self.point([0.0, 0.0]), self.point(BOARD_SIZE));
+        self.painter.rect_filled(board, 0.0, color32(paper.color));
+    }
+
+    fn image(&mut self, image: &Image, stored: &StoredImage) {
+        let texture = self.textures.entry(image.hash.clone()).or_insert_with(|| {
+            let pixels = image::imageops::thumbnail(
+                &*stored.pixels,
+                THUMBNAIL_TEXTURE_SIDE.min(stored.pixels.width()),
+                THUMBNAIL_TEXTURE_SIDE.min(stored.pixels.height()),
+            );
+            self.painter.ctx().load_texture(
+                &image.hash,
+                egui::ColorImage::from_rgba_unmultiplied(
+                    [pixels.width() as usize, pixels.height() as usize],
+                    pixels.as_raw(),
+                ),
+                egui::TextureOptions::LINEAR,
+            )
+        });
+
+        let mut mesh = egui::Mesh::with_texture(texture.id());
+        let uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        for (corner, [u, v]) in images::corners(image, BOARD_SIZE).into_iter().zip(uvs) {
+            mesh.vertices.push(egui::epaint::Vertex {
+                pos: self.point(corner),
+                uv: Pos2::new(u, v),
+                color: Color32::WHITE,
+            });
+        }
+        mesh.add_triangle(0, 1, 2);
+        mesh.add_triangle(0, 2, 3);
+        self.painter.add(egui::Shape::mesh(mesh));
+    }
+
+    fn rectangle(&mut self, rectangle: &Rectangle) {
+        let rect = egui::Rect::from_two_pos(self.ndc(rectangle.first), self.ndc(rectangle.last));
+        let corners = vec![
+            rect.left_top(),
+            rect.right_top(),
+            rect.right_bottom(),
+            rect.left_bottom(),
+        ];
+        self.closed(
+            corners,
+            rectangle.color,
+            rectangle.fill,
+            rectangle.outline_width,
+        );
+    }
+
+    fn ellipse(&mut self, ellipse: &Ellipse) {
+        let (center, radii) = shapes::ellipse_box(ellipse, BOARD_SIZE);
+        let points = shapes::ellipse_points(center, radii, THUMBNAIL_ELLIPSE_STEPS)
+            .into_iter()
+            .map(|point| self.point(point))
+            .collect();
+        self.closed(points, ellipse.color, ellipse.fill, ellipse.outline_width);
+    }
+
+    fn line(&mut self, line: &Line) {
+        let stroke = self.pen(line.color, line.width);
+        self.painter
+            .line_segment([self.ndc(line.first), self.ndc(line.last)], stroke);
+    }
+
+    fn polygon(&mut self, polygon: &Polygon) {
+        let points = polygon
+            .points
+            .iter()
+            .map(|point| self.ndc(*point))
+            .collect();
+        self.closed(points, polygon.color, polygon.fill, polygon.outline_width);
+    }
+
+    fn stroke(&mut self, stroke: &Stroke) {
+        let points = curve::flatten(stroke, BOARD_SIZE)
+            .into_iter()
+            .map(|(point, _)| self.point(point))
+            .collect();
+        let outline = self.pen(stroke.color, stroke.width);
+        self.painter.add(egui::Shape::line(points, outline));
+    }
+
+    fn text(&mut self, text_entry: &TextEntries) {
+        let [red, green, blue, alpha] = text_entry.color;
+        self.painter.text(
+            self.point(text_entry.position),
+            egui::Align2::LEFT_TOP,
+            &text_entry.text,
+            egui::FontId::proportional((text_entry.font_size as f32 * self.scale).max(1.0)),
+            Color32::from_rgba_unmultiplied(red, green, blue, alpha),
+        );
+    }
+}
+
+fn color32(color: [f32; 4]) -> Color32 {
+    let [red, green, blue, alpha] = utils::normalized_to_rgba(color);
+    Color32::from_rgba_unmultiplied(red, green, blue, alpha)
+}
+
+/// Paints `page` with the actions in `scene` as a thumbnail filling `rect`.
+pub(crate) fn paint_thumbnail(
+    painter: &Painter,
+    rect: egui::Rect,
+    page: &Page,
+    scene: &Scene,
+    images: &ImageStore,
+    textures: &mut HashMap<String, TextureHandle>,
+) {
+    let actions: Vec<&Action> = scene.committed().collect();
+    let mut thumbnail = ThumbnailPainter {
+        painter: &painter.with_clip_rect(rect),
+        origin: rect.min,
+        scale: rect.width() / BOARD_SIZE[0],
+        textures,
+    };
+    export::paint_actions(
+        Some(&page.paper),
+        page.slide.as_ref(),
+        actions.iter().copied(),
+        images,
+        &mut thumbnail,
+    );
+}
+
+impl WindowState {
+    /// Whether the pointer is over the page strip, as laid out last frame.
+    pub(crate) fn pointer_over_pages(&self) -> bool {
+        let position = self.last_cursor_position;
+        self.egui_context
+            .layer_id_at(Pos2::new(position.x as f32, position.y as f32))
+            .is_some_and(|layer| layer.id == egui::Id::new(PAGE_STRIP))
+    }
+
+    pub fn current_page_id(&self) -> Uuid {
+        self.pages[self.current_page].id
+    }
+
+    /// The slide under the current page, if one was imported.
+    pub fn current_slide(&self) -> Option<&Image> {
+        self.pages[self.current_page].slide.as_ref()
+    }
+
+    /// The actions of the page at `index`.
+    pub fn page_scene(&self, index: usize) -> &Scene {
+        if index == self.current_page {
+            &self.scene
+        } else {
+            &self.pages[index].scene
+        }
+    }
+
+    /// The actions of the page with `id`, when the board has it.
+    pub(crate) fn page_scene_mut(&mut self, id: Uuid) -> Option<&mut Scene> {
+        let index = self.pages.iter().position(|page| page.id == id)?;
+        Some(if index == self.current_page {
+            &mut self.scene
+        } else {
+            &mut self.pages[index].scene
+        })
+    }
+
+    pub(crate) fn run_page_command(&mut self, command: PageCommand) {
+        match command {
+            PageCommand::Previous => self.go_to_page(self.current_page.saturating_sub(1)),
+            PageCommand::Next => self.go_to_page(self.current_page + 1),
+            PageCommand::Add => self.add_page(),
+            PageCommand::Delete => self.delete_page(),
+            PageCommand::Show(index) => self.go_to_page(index),
+        }
+    }
+
+    /// Turns to the page at `index` and tells the room about it.
+    pub fn go_to_page(&mut self, index: usize) {
+        if index >= self.pages.len() || index == self.current_page {
+            return;
+        }
+        self.show_page(index);
+        self.send_paint_event(sync::page_event(
+            &self.room,
+            self.current_page_id(),
+            index,
+            StreamActionType::ActionRequest(true),
+        ));
+    }
+
+    /// Adds an empty page on the same paper after the current one and turns
+    /// to it.
+    pub fn add_page(&mut self) {
+        let paper = self.current_paper();
+        let index = self.insert_page(self.current_page + 1, Uuid::new_v4());
+        self.go_to_page(index);
+        self.set_paper(paper);
+    }
+
+    /// Removes the current page for everyone in the room. The last page can't
+    /// be deleted.
+    pub fn delete_page(&mut self) {
+        if self.pages.len() < 2 {
+            return;
+        }
+        let deleted = sync::page_event(
+            &self.room,
+            self.current_page_id(),
+            self.current_page,
+            StreamActionType::IsDeleted(true),
+        );
+        self.remove_page(self.current_page);
+        self.send_paint_events([
+            deleted,
+            sync::page_event(
+                &self.room,
+                self.current_page_id(),
+                self.current_page,
+                StreamActionType::ActionRequest(true),
+            ),
+        ]);
+    }
+
+    /// Shares every page, its paper, its slide, its actions and the files of
+    /// its images with the room.
+    /// With `turn` the others are asked to turn along, ending on the current
+    /// page; otherwise the pages are only announced, as when answering a
+    /// participant who joined.
+    pub(crate) fn send_pages(&mut self, turn: bool) {
+        let requests = self.page_events(turn);
+        self.send_paint_events(requests);
+    }
+
+    /// The events `send_pages` sends, in order. The actions in them count
+    /// as shared from here on.
+    pub(crate) fn page_events(&mut self, turn: bool) -> Vec<PaintEvent> {
+        let hashes: Vec<String> = self
+            .pages
+            .iter()
+            .flat_map(|page| {
+                page.slide
+                    .iter()
+                    .chain(page.scene.committed().filter_map(image_of))
+            })
+            .chain(self.scene.committed().filter_map(image_of))
+            .map(|image| image.hash.clone())
+            .collect();
+        let mut requests = self.image_events(&hashes);
+        for (index, page) in self.pages.iter().enumerate() {
+            requests.push(if turn {
+                sync::page_event(
+                    &self.room,
+                    page.id,
+                    index,
+                    StreamActionType::ActionRequest(true),
+                )
+            } else {
+                sync::page_announcement(&self.room, page.id, index)
+            });
+            if page.paper != Paper::default() {
+                requests.push(sync::paper_event(&self.room, page.id, &page.paper));
+            }
+            if let Some(slide) = &page.slide {
+                requests.push(sync::slide_event(&self.room, page.id, slide));
+            }
+            // The actions of the current page are in `scene` rather than
+            // on the page.
+            let scene = if index == self.current_page {
+                &self.scene
+            } else {
+                &page.scene
+            };
+            if scene.committed().next().is_some() {
+                requests.push(sync::paint_event(
+                    &self.room,
+                    page.id,
+                    scene.committed(),
+                    StreamActionType::ActionRequest(true),
+                ));
+            }
+        }
+        if turn {
+            requests.push(sync::page_event(
+                &self.room,
+                self.current_page_id(),
+                self.current_page,
+                StreamActionType::ActionRequest(true),
+            ));
+        }
+        let sent_ids: Vec<Uuid> = self
+            .pages
+            .iter()
+            .flat_map(|page| page.scene.committed())
+            .chain(self.scene.committed())
+            .map(|action| action.id)
+            .collect();
+        self.events_id.extend(sent_ids);
+        requests
+    }
+
+    /// Applies a page change received from the room: a page that was turned
+    /// to or announced is added where the sender has it, and followed if it
+    /// was `turned` to and `follow_pages` is set. A deleted page goes away
+    /// with its actions.
+    pub(crate) fn apply_page_event(&mut self, id: Uuid, index: usize, deleted: bool, turned: bool) {
+        let position = self.pages.iter().position(|page| page.id == id);
+        match (position, deleted) {
+            (Some(position), true) => self.remove_page(position),
+            (None, true) => {}
+            (position, false) => {
+                let position = position.unwrap_or_else(|| self.insert_page(index, id));
+                if turned && self.follow_pages {
+                    self.show_page(position);
+                }
+            }
+        }
+    }
+
+    /// Puts an empty page with `id` at `index`, or last when the board is
+    /// shorter, and returns where it went.
+    pub(crate) fn insert_page(&mut self, index: usize, id: Uuid) -> usize {
+        let index = index.min(self.pages.len());
+        self.pages.insert(index, Page::new(id));
+        if index <= self.current_page {
+            self.current_page += 1;
+        }
+        index
+    }
+
+    /// Makes the page at `index` current, swapping its actions into `scene`.
+    /// Nothing is sent to the room.
+    fn show_page(&mut self, index: usize) {
+        if index >= self.pages.len() || index == self.current_page {
+            return;
+        }
+        self.commit_text();
+        self.send_unsent_actions();
+
+        std::mem::swap(&mut self.scene, &mut self.pages[self.current_page].scene);
+        std::mem::swap(&mut self.scene, &mut self.pages[index].scene);
+        self.current_page = index;
+
+        self.redo_stack.clear();
+        self.current_stroke = Polyline::default();
+        self.shape_positions.clear();
+        if let Some(selection) = &mut self.selection {
+            *selection = Selection::new(selection.mode);
+        }
+        self.window.request_redraw();
+    }
+
+    /// Drops the page at `index` and its actions, turning to a neighbour
+    /// first when it is the current one. The last page is emptied instead.
+    fn remove_page(&mut self, index: usize) {
+        if self.pages.len() < 2 {
+            for action in self.scene.iter() {
+                self.events_id.remove(&action.id);
+            }
+            self.scene.clear();
+            self.pages[0].slide = None;
+            self.redo_stack.clear();
+            return;
+        }
+        if index == self.current_page {
+            self.show_page(if index + 1 < self.pages.len() {
+                index + 1
+            } else {
+                index - 1
+            });
+        }
+
+        let page = self.pages.remove(index);
+        if index < self.current_page {
+            self.current_page -= 1;
+        }
+        for action in page.scene.iter() {
+            self.events_id.remove(&action.id);
+        }
+        self.window.request_redraw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::PaperKind;
+    use egui::{epaint::ClippedShape, Shape};
+
+    const THUMBNAIL: egui::Rect = egui::Rect {
+        min: Pos2::new(10.0, 20.0),
+        max: Pos2::new(10.0 + THUMBNAIL_WIDTH, 20.0 + THUMBNAIL_WIDTH * 9.0 / 16.0),
+    };
+
+    /// The shapes a thumbnail of `page` with `scene` is painted with.
+    fn thumbnail(page: &Page, scene: &Scene) -> Vec<ClippedShape> {
+        let context = egui::Context::default();
+        let output = context.run(egui::RawInput::default(), |context| {
+            let painter = context.layer_painter(egui::LayerId::background());
+            paint_thumbnail(
+                &painter,
+                THUMBNAIL,
+                page,
+                scene,
+                &ImageStore::default(),
+                &mut HashMap::new(),
+            );
+        });
+        output.shapes
+    }
+
+    fn close(a: Pos2, b: Pos2) -> bool {
+        a.distance(b) < 1e-3
+    }
+
+    #[test]
+    fn starts_every_board_on_the_same_page() {
+        assert_eq!(Page::first().id, Page::first().id);
+        assert!(Page::first().id.is_nil());
+        assert!(Page::first().slide.is_none());
+    }
+
+    #[test]
+    fn paints_the_paper_over_the_whole_thumbnail() {
+        let mut page = Page::first();
+        page.paper = Paper {
+            kind: PaperKind::Ruled,
+            color: [1.0, 0.9, 0.8, 1.0],
+        };
+        let shapes = thumbnail(&page, &Scene::new());
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(shapes[0].clip_rect, THUMBNAIL);
+        let Shape::Rect(paper) = &shapes[0].shape else {
+            panic!("{:?}", shapes[0].shape);
+        };
+        assert!(close(paper.rect.min, THUMBNAIL.min));
+        assert!(close(paper.rect.max, THUMBNAIL.max));
+        assert_eq!(paper.fill, color32(page.paper.color));
+    }
+
+    #[test]
+    fn scales_committed_actions_into_the_thumbnail() {
+        let mut scene = Scene::new();
+        scene.insert(Action {
+            id: Uuid::new_v4(),
+            action_type: ActionType::Shapes(Rectangle {
+                first: export::board_to_ndc([0.0, 0.0], BOARD_SIZE),
+                last: export::board_to_ndc([BOARD_SIZE[0] / 2.0, BOARD_SIZE[1] / 2.0], BOARD_SIZE),
+                color: [0.0, 0.0, 0.0, 1.0],
+                fill: [0.0; 4],
+                outline_width: 2.0,
+                corner_radius: 0.0,
+            }),
+        });
+        scene.insert(Action {
+            id: Uuid::new_v4(),
+            action_type: ActionType::Text(TextEntries {
+                text: "typing".to_owned(),
+                pending: true,
+                ..TextEntries::null([0, 0, 0, 255], 24)
+            }),
+        });
+
+        let shapes = thumbnail(&Page::first(), &scene);
+        assert_eq!(shapes.len(), 2);
+        let Shape::Path(rectangle) = &shapes[1].shape else {
+            panic!("{:?}", shapes[1].shape);
+        };
+        assert!(close(rectangle.points[0], THUMBNAIL.min));
+        assert!(close(rectangle.points[2], THUMBNAIL.center()));
+    }
+}
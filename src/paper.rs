@@ -0,0 +1,333 @@
+use crate::{sync, WindowState};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Paper colors offered in the paper menu: white, cream, light gray, and
+/// green and black boards for chalk colors.
+pub(crate) const PAPER_COLORS: [[f32; 4]; 5] = [
+    [1.0, 1.0, 1.0, 1.0],
+    [0.99, 0.96, 0.88, 1.0],
+    [0.92, 0.92, 0.92, 1.0],
+    [0.13, 0.27, 0.2, 1.0],
+    [0.1, 0.1, 0.1, 1.0],
+];
+
+/// The lines printed on a page. Spacings are fixed in board pixels, so the
+/// pattern scales with the board like its content.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaperKind {
+    #[default]
+    Plain,
+    SquareGrid,
+    DotGrid,
+    /// Horizontal rules with a margin line on the left.
+    Ruled,
+    /// Fine lines with a heavier line every fifth.
+    Graph,
+    /// Baselines with guides five nuqtas above and three below, for Persian
+    /// calligraphy practice.
+    Calligraphy,
+}
+
+impl PaperKind {
+    pub const ALL: [PaperKind; 6] = [
+        PaperKind::Plain,
+        PaperKind::SquareGrid,
+        PaperKind::DotGrid,
+        PaperKind::Ruled,
+        PaperKind::Graph,
+        PaperKind::Calligraphy,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PaperKind::Plain => "Plain",
+            PaperKind::SquareGrid => "Grid",
+            PaperKind::DotGrid => "Dots",
+            PaperKind::Ruled => "Ruled",
+            PaperKind::Graph => "Graph",
+            PaperKind::Calligraphy => "Calligraphy",
+        }
+    }
+
+    /// The number `paper.wgsl` and the wire know this kind by.
+    pub(crate) fn index(self) -> u32 {
+        match self {
+            PaperKind::Plain => 0,
+            PaperKind::SquareGrid => 1,
+            PaperKind::DotGrid => 2,
+            PaperKind::Ruled => 3,
+            PaperKind::Graph => 4,
+            PaperKind::Calligraphy => 5,
+        }
+    }
+
+    /// Unknown kinds, from newer clients, are drawn plain.
+    pub(crate) fn from_index(index: u32) -> Self {
+        PaperKind::ALL
+            .into_iter()
+            .find(|kind| kind.index() == index)
+            .unwrap_or_default()
+    }
+}
+
+/// The background of a page, drawn under all of its content.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Paper {
+    pub kind: PaperKind,
+    pub color: [f32; 4],
+}
+
+impl Default for Paper {
+    fn default() -> Self {
+        Paper {
+            kind: PaperKind::Plain,
+            color: PAPER_COLORS[0],
+        }
+    }
+}
+
+impl Paper {
+    /// The color of the paper laid on white, as exports show it.
+    pub(crate) fn on_white(&self) -> [f32; 4] {
+        let [red, green, blue, alpha] = self.color;
+        let blend = |value: f32| value * alpha + (1.0 - alpha);
+        [blend(red), blend(green), blend(blue), 1.0]
+    }
+}
+
+// Spacings and widths in board pixels, as in `paper.wgsl`.
+const GRID_SPACING: f32 = 40.0;
+const DOT_RADIUS: f32 = 2.0;
+const RULED_SPACING: f32 = 48.0;
+const RULED_TOP: f32 = 96.0;
+const RULED_MARGIN: f32 = 120.0;
+const GRAPH_MINOR: f32 = 16.0;
+const GRAPH_MAJOR: f32 = 80.0;
+const LINE_WIDTH: f32 = 1.0;
+const NUQTA: f32 = 14.0;
+const CALLIGRAPHY_ROW: f32 = 180.0;
+const CALLIGRAPHY_BASELINE: f32 = 120.0;
+
+/// A line or dot printed on paper, in board pixels, for painters that draw
+/// the pattern `paper.wgsl` shades. Colors are opaque, already mixed with
+/// the paper as the shader mixes them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PaperMark {
+    Line {
+        from: [f32; 2],
+        to: [f32; 2],
+        width: f32,
+        color: [f32; 4],
+    },
+    Dot {
+        center: [f32; 2],
+        radius: f32,
+        color: [f32; 4],
+    },
+}
+
+/// The marks of `paper` over a board of `board_size`, in paint order.
+pub(crate) fn paper_marks(paper: &Paper, board_size: [f32; 2]) -> Vec<PaperMark> {
+    let [width, height] = board_size;
+    let base = paper.on_white();
+    // Guides are a quiet blue on light paper and a quiet white on dark paper.
+    let luminance = 0.299 * base[0] + 0.587 * base[1] + 0.114 * base[2];
+    let guide = if luminance < 0.5 {
+        [0.9, 0.9, 0.9]
+    } else {
+        [0.45, 0.6, 0.85]
+    };
+    let mix = |toward: [f32; 3], amount: f32| {
+        let channel = |index: usize| base[index] + (toward[index] - base[index]) * amount;
+        [channel(0), channel(1), channel(2), 1.0]
+    };
+    let steps = |start: f32, spacing: f32, end: f32| {
+        (0..)
+            .map(move |step| start + step as f32 * spacing)
+            .take_while(move |at| *at <= end)
+    };
+    let row = move |y: f32, line_width: f32, color: [f32; 4]| PaperMark::Line {
+        from: [0.0, y],
+        to: [width, y],
+        width: line_width,
+        color,
+    };
+    let rows = |start: f32, spacing: f32, line_width: f32, color: [f32; 4]| {
+        steps(start, spacing, height).map(move |y| row(y, line_width, color))
+    };
+    let columns = |spacing: f32, line_width: f32, color: [f32; 4]| {
+        steps(0.0, spacing, width).map(move |x| PaperMark::Line {
+            from: [x, 0.0],
+            to: [x, height],
+            width: line_width,
+            color,
+        })
+    };
+
+    let mut marks = Vec::new();
+    match paper.kind {
+        PaperKind::Plain => {}
+        PaperKind::SquareGrid => {
+            let color = mix(guide, 0.5);
+            marks.extend(columns(GRID_SPACING, LINE_WIDTH, color));
+            marks.extend(rows(0.0, GRID_SPACING, LINE_WIDTH, color));
+        }
+        PaperKind::DotGrid => {
+            let color = mix(guide, 0.8);
+            for y in steps(0.0, GRID_SPACING, height) {
+                marks.extend(steps(0.0, GRID_SPACING, width).map(|x| PaperMark::Dot {
+                    center: [x, y],
+                    radius: DOT_RADIUS,
+                    color,
+                }));
+            }
+        }
+        PaperKind::Ruled => {
+            marks.extend(rows(RULED_TOP, RULED_SPACING, LINE_WIDTH, mix(guide, 0.6)));
+            marks.push(PaperMark::Line {
+                from: [RULED_MARGIN, 0.0],
+                to: [RULED_MARGIN, height],
+                width: LINE_WIDTH * 1.5,
+                color: mix([0.9, 0.35, 0.35], 0.7),
+            });
+        }
+        PaperKind::Graph => {
+            let minor = mix(guide, 0.3);
+            marks.extend(columns(GRAPH_MINOR, LINE_WIDTH * 0.75, minor));
+            marks.extend(rows(0.0, GRAPH_MINOR, LINE_WIDTH * 0.75, minor));
+            let major = mix(guide, 0.6);
+            marks.extend(columns(GRAPH_MAJOR, LINE_WIDTH * 1.5, major));
+            marks.extend(rows(0.0, GRAPH_MAJOR, LINE_WIDTH * 1.5, major));
+        }
+        PaperKind::Calligraphy => {
+            // Each row has a baseline, a guide five nuqtas above it for the
+            // height of tall letters and one three nuqtas below for
+            // descenders.
+            let guides = mix(guide, 0.4);
+            for top in steps(0.0, CALLIGRAPHY_ROW, height) {
+                let baseline = top + CALLIGRAPHY_BASELINE;
+                let lines = [
+                    (baseline - 5.0 * NUQTA, LINE_WIDTH, guides),
+                    (baseline + 3.0 * NUQTA, LINE_WIDTH, guides),
+                    (baseline, LINE_WIDTH * 2.0, mix(guide, 0.8)),
+                ];
+                marks.extend(
+                    lines
+                        .into_iter()
+                        .filter(|(y, _, _)| *y <= height)
+                        .map(|(y, line_width, color)| row(y, line_width, color)),
+                );
+            }
+        }
+    }
+    marks
+}
+
+/// The uniform `paper.wgsl` reads at group 1.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct PaperUniform {
+    board_size: [f32; 2],
+    kind: u32,
+    _padding: u32,
+}
+
+impl PaperUniform {
+    pub(crate) fn new(paper: &Paper, board_size: [f32; 2]) -> Self {
+        PaperUniform {
+            board_size,
+            kind: paper.kind.index(),
+            _padding: 0,
+        }
+    }
+}
+
+impl WindowState {
+    pub fn current_paper(&self) -> Paper {
+        self.pages[self.current_page].paper
+    }
+
+    /// Puts `paper` under the current page and tells the room about it.
+    pub fn set_paper(&mut self, paper: Paper) {
+        if self.current_paper() == paper {
+            return;
+        }
+        self.pages[self.current_page].paper = paper;
+        self.send_paint_event(sync::paper_event(
+            &self.room,
+            self.current_page_id(),
+            &paper,
+        ));
+        self.window.request_redraw();
+    }
+
+    /// Applies the paper of a page received from the room. A page the board
+    /// doesn't have yet is added at the end.
+    pub(crate) fn apply_paper_event(&mut self, page: Uuid, paper: Paper) {
+        let index = match self.pages.iter().position(|known| known.id == page) {
+            Some(index) => index,
+            None => self.insert_page(self.pages.len(), page),
+        };
+        self.pages[index].paper = paper;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD_SIZE: [f32; 2] = [400.0, 300.0];
+
+    fn rows(marks: &[PaperMark]) -> Vec<f32> {
+        marks
+            .iter()
+            .filter_map(|mark| match mark {
+                PaperMark::Line { from, to, .. } if from[1] == to[1] => Some(from[1]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plain_paper_has_no_marks() {
+        assert!(paper_marks(&Paper::default(), BOARD_SIZE).is_empty());
+    }
+
+    #[test]
+    fn rules_start_below_the_top_with_a_margin() {
+        let ruled = Paper {
+            kind: PaperKind::Ruled,
+            ..Paper::default()
+        };
+        let marks = paper_marks(&ruled, BOARD_SIZE);
+        assert_eq!(rows(&marks), [96.0, 144.0, 192.0, 240.0, 288.0]);
+        assert!(marks.iter().any(|mark| matches!(
+            mark,
+            PaperMark::Line { from, to, .. }
+                if *from == [RULED_MARGIN, 0.0] && *to == [RULED_MARGIN, BOARD_SIZE[1]]
+        )));
+    }
+
+    #[test]
+    fn guides_are_light_on_dark_paper() {
+        let board = Paper {
+            kind: PaperKind::SquareGrid,
+            color: PAPER_COLORS[3],
+        };
+        let [PaperMark::Line { color, .. }, ..] = paper_marks(&board, BOARD_SIZE)[..] else {
+            panic!("expected grid lines");
+        };
+        assert!(color[0] > board.color[0] && color[3] == 1.0);
+    }
+
+    #[test]
+    fn calligraphy_rows_have_three_lines() {
+        let calligraphy = Paper {
+            kind: PaperKind::Calligraphy,
+            ..Paper::default()
+        };
+        let marks = paper_marks(&calligraphy, BOARD_SIZE);
+        assert_eq!(rows(&marks), [50.0, 162.0, 120.0, 230.0, 300.0]);
+    }
+}
@@ -0,0 +1,1155 @@
+use crate::{
+    export::{self, BoardPainter, ExportError, PageContent},
+    images::{self, StoredImage},
+    paper::{self, Paper, PaperMark},
+    render, shapes,
+    structs::{Action, Ellipse, Image, Line, LineJoin, Polygon, Rectangle, Stroke},
+    utils, TextEntries, WindowState,
+};
+use glyphon::{fontdb, FontSystem};
+use image::{codecs::jpeg::JpegEncoder, RgbImage, RgbaImage};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    io::Write as _,
+    path::Path,
+    sync::Arc,
+};
+
+const PAPER_MARGIN: f32 = 36.0;
+const CONTENT_MARGIN: f32 = 16.0;
+const POINTS_PER_PIXEL: f32 = 0.75;
+/// Bézier handle length, as a fraction of the radius, for a quarter circle.
+const KAPPA: f32 = 0.552_284_8;
+/// Images are embedded as JPEG, which PDF readers decode natively.
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+    /// Each page is as large as its own content, at 96 dpi.
+    FitToContent,
+}
+
+impl PageSize {
+    fn points(self) -> Option<[f32; 2]> {
+        match self {
+            PageSize::A4 => Some([595.28, 841.89]),
+            PageSize::Letter => Some([612.0, 792.0]),
+            PageSize::FitToContent => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PdfExportOptions {
+    pub page_size: PageSize,
+}
+
+/// A font face text was shaped with, embedded as `/F0`, `/F1` and so on.
+struct PdfFont {
+    face: fontdb::ID,
+    /// Text each glyph id stands for, used for the ToUnicode map.
+    text: BTreeMap<u16, String>,
+    /// Advance of each glyph id in 1/1000 em.
+    widths: BTreeMap<u16, f32>,
+}
+
+struct PdfPainter<'a> {
+    font_system: &'a mut FontSystem,
+    /// Every face used in the document, including the fallbacks text
+    /// was shaped with where Vazir lacks a glyph.
+    fonts: &'a mut Vec<PdfFont>,
+    /// Numbers of the fonts this page sets text in.
+    page_fonts: BTreeSet<usize>,
+    /// Pixels of every image in the document by hash, in the order they are
+    /// numbered `/Im0`, `/Im1` and so on.
+    images: &'a mut Vec<(String, Arc<RgbaImage>)>,
+    /// Numbers of the images this page draws.
+    page_images: BTreeSet<usize>,
+    board_size: [f32; 2],
+    /// Color of the paper of the page, which translucent fills are blended
+    /// with.
+    paper: [f32; 4],
+    content: String,
+    bounds: Option<[f32; 4]>,
+}
+
+impl PdfPainter<'_> {
+    fn include(&mut self, [x, y]: [f32; 2]) {
+        let bounds = self.bounds.get_or_insert([x, y, x, y]);
+        bounds[0] = bounds[0].min(x);
+        bounds[1] = bounds[1].min(y);
+        bounds[2] = bounds[2].max(x);
+        bounds[3] = bounds[3].max(y);
+    }
+
+    fn stroke_color(&mut self, color: [f32; 4]) {
+        let [r, g, b, _] = utils::normalized_to_rgba(color);
+        let _ = writeln!(
+            self.content,
+            "{:.3} {:.3} {:.3} RG",
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0
+        );
+    }
+
+    /// Sets the fill color. The page has no transparency, so translucent
+    /// fills are blended with the paper.
+    fn fill_color(&mut self, color: [f32; 4]) {
+        let [r, g, b, a] = utils::normalized_to_rgba(color).map(|value| value as f32 / 255.0);
+        let paper = self.paper;
+        let blend = |value: f32, under: f32| value * a + under * (1.0 - a);
+        let _ = writeln!(
+            self.content,
+            "{:.3} {:.3} {:.3} rg",
+            blend(r, paper[0]),
+            blend(g, paper[1]),
+            blend(b, paper[2])
+        );
+    }
+
+    /// Appends a closed ellipse path, four quarter arcs clockwise on screen
+    /// from the rightmost point.
+    fn ellipse_path(&mut self, center: [f32; 2], [rx, ry]: [f32; 2]) {
+        let point = |x: f32, y: f32| [center[0] + x, center[1] + y];
+        let quarters = [
+            [point(rx, ry * KAPPA), point(rx * KAPPA, ry), point(0.0, ry)],
+            [
+                point(-rx * KAPPA, ry),
+                point(-rx, ry * KAPPA),
+                point(-rx, 0.0),
+            ],
+            [
+                point(-rx, -ry * KAPPA),
+                point(-rx * KAPPA, -ry),
+                point(0.0, -ry),
+            ],
+            [
+                point(rx * KAPPA, -ry),
+                point(rx, -ry * KAPPA),
+                point(rx, 0.0),
+            ],
+        ];
+        let start = point(rx, 0.0);
+        let _ = writeln!(self.content, "{:.2} {:.2} m", start[0], start[1]);
+        for [a, b, c] in quarters {
+            let _ = writeln!(
+                self.content,
+                "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c",
+                a[0], a[1], b[0], b[1], c[0], c[1]
+            );
+        }
+        let _ = writeln!(self.content, "h");
+    }
+
+    /// Sets the colors and outline width of a closed shape and returns the
+    /// operator that paints it, or `None` when it has neither fill nor outline.
+    fn shape_paint(
+        &mut self,
+        color: [f32; 4],
+        fill: [f32; 4],
+        outline_width: f32,
+    ) -> Option<&'static str> {
+        let filled = fill[3] > 0.0;
+        let outlined = outline_width > 0.0;
+        if filled {
+            self.fill_color(fill);
+        }
+        if outlined {
+            self.stroke_color(color);
+            let _ = writeln!(self.content, "{:.2} w", outline_width);
+        }
+        match (filled, outlined) {
+            (true, true) => Some("B"),
+            (true, false) => Some("f"),
+            (false, true) => Some("S"),
+            (false, false) => None,
+        }
+    }
+
+    /// Appends a closed path through `corners`.
+    fn path(&mut self, corners: &[[f32; 2]]) {
+        for (index, [x, y]) in corners.iter().enumerate() {
+            let operator = if index == 0 { "m" } else { "l" };
+            let _ = writeln!(self.content, "{:.2} {:.2} {}", x, y, operator);
+        }
+        let _ = writeln!(self.content, "h");
+    }
+
+    /// Appends a closed rectangle path with corners rounded by `radius`.
+    fn rounded_rectangle(&mut self, [left, top]: [f32; 2], [right, bottom]: [f32; 2], radius: f32) {
+        if radius <= 0.0 {
+            let _ = writeln!(
+                self.content,
+                "{:.2} {:.2} {:.2} {:.2} re",
+                left,
+                top,
+                right - left,
+                bottom - top
+            );
+            return;
+        }
+
+        let handle = radius * (1.0 - KAPPA);
+        let _ = writeln!(self.content, "{:.2} {:.2} m", left + radius, top);
+        let _ = writeln!(self.content, "{:.2} {:.2} l", right - radius, top);
+        let _ = writeln!(
+            self.content,
+            "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c",
+            right - handle,
+            top,
+            right,
+            top + handle,
+            right,
+            top + radius
+        );
+        let _ = writeln!(self.content, "{:.2} {:.2} l", right, bottom - radius);
+        let _ = writeln!(
+            self.content,
+            "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c",
+            right,
+            bottom - handle,
+            right - handle,
+            bottom,
+            right - radius,
+            bottom
+        );
+        let _ = writeln!(self.content, "{:.2} {:.2} l", left + radius, bottom);
+        let _ = writeln!(
+            self.content,
+            "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c",
+            left + handle,
+            bottom,
+            left,
+            bottom - handle,
+            left,
+            bottom - radius
+        );
+        let _ = writeln!(self.content, "{:.2} {:.2} l", left, top + radius);
+        let _ = writeln!(
+            self.content,
+            "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c h",
+            left,
+            top + handle,
+            left + handle,
+            top,
+            left + radius,
+            top
+        );
+    }
+}
+
+impl BoardPainter for PdfPainter<'_> {
+    /// The paper covers the board but doesn't count as content when the page
+    /// fits the content.
+    fn paper(&mut self, paper: &Paper) {
+        self.paper = paper.on_white();
+        self.fill_color(self.paper);
+        let [width, height] = self.board_size;
+        let _ = writeln!(self.content, "0 0 {:.2} {:.2} re f", width, height);
+        for mark in paper::paper_marks(paper, self.board_size) {
+            match mark {
+                PaperMark::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => {
+                    self.stroke_color(color);
+                    let _ = writeln!(
+                        self.content,
+                        "{:.2} w {:.2} {:.2} m {:.2} {:.2} l S",
+                        width, from[0], from[1], to[0], to[1]
+                    );
+                }
+                PaperMark::Dot {
+                    center,
+                    radius,
+                    color,
+                } => {
+                    self.fill_color(color);
+                    self.ellipse_path(center, [radius, radius]);
+                    let _ = writeln!(self.content, "f");
+                }
+            }
+        }
+        let _ = writeln!(self.content, "1 w");
+    }
+
+    fn image(&mut self, image: &Image, stored: &StoredImage) {
+        let number = match self.images.iter().position(|(hash, _)| *hash == image.hash) {
+            Some(number) => number,
+            None => {
+                self.images
+                    .push((image.hash.clone(), stored.pixels.clone()));
+                self.images.len() - 1
+            }
+        };
+        self.page_images.insert(number);
+
+        let [top_left, _, bottom_right, bottom_left] = images::corners(image, self.board_size);
+        for corner in images::corners(image, self.board_size) {
+            self.include(corner);
+        }
+        // Images fill the unit square, with their bottom left corner at the
+        // origin.
+        let _ = writeln!(
+            self.content,
+            "q {:.4} {:.4} {:.4} {:.4} {:.2} {:.2} cm /Im{} Do Q",
+            bottom_right[0] - bottom_left[0],
+            bottom_right[1] - bottom_left[1],
+            top_left[0] - bottom_left[0],
+            top_left[1] - bottom_left[1],
+            bottom_left[0],
+            bottom_left[1],
+            number
+        );
+    }
+
+    fn rectangle(&mut self, rectangle: &Rectangle) {
+        let [x1, y1] = export::ndc_to_board(rectangle.first, self.board_size);
+        let [x2, y2] = export::ndc_to_board(rectangle.last, self.board_size);
+        self.include([x1, y1]);
+        self.include([x2, y2]);
+
+        let Some(operator) =
+            self.shape_paint(rectangle.color, rectangle.fill, rectangle.outline_width)
+        else {
+            return;
+        };
+
+        let (top_left, bottom_right) = ([x1.min(x2), y1.min(y2)], [x1.max(x2), y1.max(y2)]);
+        let radius = rectangle
+            .corner_radius
+            .min((bottom_right[0] - top_left[0]) / 2.0)
+            .min((bottom_right[1] - top_left[1]) / 2.0);
+        self.rounded_rectangle(top_left, bottom_right, radius);
+        let _ = writeln!(self.content, "{}", operator);
+    }
+
+    fn ellipse(&mut self, ellipse: &Ellipse) {
+        let (center, radii) = shapes::ellipse_box(ellipse, self.board_size);
+        self.include([center[0] - radii[0], center[1] - radii[1]]);
+        self.include([center[0] + radii[0], center[1] + radii[1]]);
+
+        let Some(operator) = self.shape_paint(ellipse.color, ellipse.fill, ellipse.outline_width)
+        else {
+            return;
+        };
+        self.ellipse_path(center, radii);
+        let _ = writeln!(self.content, "{}", operator);
+    }
+
+    fn line(&mut self, line: &Line) {
+        let ([start, end], heads) = shapes::line_geometry(line, self.board_size);
+        self.include(export::ndc_to_board(line.first, self.board_size));
+        self.include(export::ndc_to_board(line.last, self.board_size));
+
+        self.stroke_color(line.color);
+        let _ = writeln!(
+            self.content,
+            "{:.2} w {:.2} {:.2} m {:.2} {:.2} l S",
+            line.width, start[0], start[1], end[0], end[1]
+        );
+        if !heads.is_empty() {
+            self.fill_color(line.color);
+        }
+        for head in heads {
+            self.path(&head);
+            let _ = writeln!(self.content, "f");
+        }
+    }
+
+    fn polygon(&mut self, polygon: &Polygon) {
+        let corners: Vec<[f32; 2]> = polygon
+            .points
+            .iter()
+            .map(|point| export::ndc_to_board(*point, self.board_size))
+            .collect();
+        for corner in &corners {
+            self.include(*corner);
+        }
+
+        let Some(operator) = self.shape_paint(polygon.color, polygon.fill, polygon.outline_width)
+        else {
+            return;
+        };
+        self.path(&corners);
+        let _ = writeln!(self.content, "{}", operator);
+    }
+
+    fn stroke(&mut self, stroke: &Stroke) {
+        if let Some((center, width)) = export::stroke_dot(stroke, self.board_size) {
+            let radius = width / 2.0;
+            self.include([center[0] - radius, center[1] - radius]);
+            self.include([center[0] + radius, center[1] + radius]);
+            self.fill_color(stroke.color);
+            self.ellipse_path(center, [radius, radius]);
+            let _ = writeln!(self.content, "f");
+            return;
+        }
+
+        self.stroke_color(stroke.color);
+        let join = match stroke.join {
+            LineJoin::Miter => 0,
+            LineJoin::Round => 1,
+            LineJoin::Bevel => 2,
+        };
+        let _ = writeln!(self.content, "{} j", join);
+        for (range, width) in export::stroke_runs(stroke) {
+            let _ = writeln!(self.content, "{:.2} w", width);
+
+            for (index, point) in stroke.points[range].iter().enumerate() {
+                let [x, y] = export::ndc_to_board(*point, self.board_size);
+                self.include([x, y]);
+                let operator = match index % 3 {
+                    _ if index == 0 => " m\n",
+                    0 => " c\n",
+                    _ => " ",
+                };
+                let _ = write!(self.content, "{:.2} {:.2}{}", x, y, operator);
+            }
+            self.content.push_str("S\n");
+        }
+        self.content.push_str("1 j\n");
+    }
+
+    fn text(&mut self, text_entry: &TextEntries) {
+        let buffer = render::text_buffer(
+            self.font_system,
+            text_entry,
+            self.board_size[0],
+            self.board_size[1],
+        );
+
+        let [r, g, b, _] = text_entry.color;
+        let _ = writeln!(
+            self.content,
+            "BT {:.3} {:.3} {:.3} rg",
+            r as f32 / 255.0,
+            g as f32 / 255.0,
+            b as f32 / 255.0
+        );
+
+        let [left, top] = text_entry.position;
+        let mut current_font = None;
+        for run in buffer.layout_runs() {
+            self.include([left, top + run.line_top]);
+            self.include([left + run.line_w, top + run.line_top + run.line_height]);
+
+            // Glyphs are placed in visual order; ActualText keeps copy and
+            // search in logical order for right-to-left lines.
+            let _ = writeln!(
+                self.content,
+                "/Span << /ActualText <FEFF{}> >> BDC",
+                utf16_hex(&run.text[render::TEXT_PREFIX_LEN.min(run.text.len())..])
+            );
+
+            for glyph in run.glyphs {
+                if glyph.start < render::TEXT_PREFIX_LEN {
+                    continue;
+                }
+
+                let number = match self
+                    .fonts
+                    .iter()
+                    .position(|font| font.face == glyph.font_id)
+                {
+                    Some(number) => number,
+                    None => {
+                        self.fonts.push(PdfFont {
+                            face: glyph.font_id,
+                            text: BTreeMap::new(),
+                            widths: BTreeMap::new(),
+                        });
+                        self.fonts.len() - 1
+                    }
+                };
+                if current_font != Some(number) {
+                    current_font = Some(number);
+                    self.page_fonts.insert(number);
+                    let _ = writeln!(self.content, "/F{} {} Tf", number, text_entry.font_size);
+                }
+
+                let x = left + glyph.x + glyph.font_size * glyph.x_offset;
+                let y = top + run.line_y + glyph.y - glyph.font_size * glyph.y_offset;
+                let _ = writeln!(
+                    self.content,
+                    "1 0 0 -1 {:.2} {:.2} Tm <{:04X}> Tj",
+                    x, y, glyph.glyph_id
+                );
+
+                let font = &mut self.fonts[number];
+                font.text
+                    .entry(glyph.glyph_id)
+                    .or_insert_with(|| run.text[glyph.start..glyph.end].to_owned());
+                font.widths
+                    .entry(glyph.glyph_id)
+                    .or_insert(glyph.w / glyph.font_size * 1000.0);
+            }
+
+            self.content.push_str("EMC\n");
+        }
+
+        self.content.push_str("ET\n");
+    }
+}
+
+struct PdfWriter {
+    bytes: Vec<u8>,
+    offsets: BTreeMap<usize, usize>,
+}
+
+impl PdfWriter {
+    fn object(&mut self, id: usize, body: &str) {
+        self.offsets.insert(id, self.bytes.len());
+        let _ = write!(self.bytes, "{} 0 obj\n{}\nendobj\n", id, body);
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        self.offsets.insert(id, self.bytes.len());
+        let _ = write!(
+            self.bytes,
+            "{} 0 obj\n<< {} /Length {} >>\nstream\n",
+            id,
+            dictionary,
+            data.len()
+        );
+        self.bytes.extend_from_slice(data);
+        self.bytes.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let xref = self.bytes.len();
+        let size = self.offsets.keys().max().map_or(1, |max| max + 1);
+        let _ = write!(self.bytes, "xref\n0 {}\n0000000000 65535 f \n", size);
+        for id in 1..size {
+            match self.offsets.get(&id) {
+                Some(offset) => {
+                    let _ = writeln!(self.bytes, "{:010} 00000 n ", offset);
+                }
+                None => {
+                    let _ = writeln!(self.bytes, "0000000000 65535 f ");
+                }
+            }
+        }
+        let _ = write!(
+            self.bytes,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            size, root, xref
+        );
+        self.bytes
+    }
+}
+
+/// An image XObject of `pixels` on white, as JPEG when it encodes.
+fn write_image(writer: &mut PdfWriter, id: usize, pixels: &RgbaImage) {
+    let rgb = RgbImage::from_fn(pixels.width(), pixels.height(), |x, y| {
+        let [r, g, b, a] = pixels.get_pixel(x, y).0;
+        let blend = |value: u8| ((value as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    });
+    let dictionary = format!(
+        "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+         /BitsPerComponent 8",
+        rgb.width(),
+        rgb.height()
+    );
+
+    let mut jpeg = Vec::new();
+    match JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&rgb) {
+        Ok(()) => writer.stream(id, &format!("{} /Filter /DCTDecode", dictionary), &jpeg),
+        Err(_) => writer.stream(id, &dictionary, rgb.as_raw()),
+    }
+}
+
+fn utf16_hex(text: &str) -> String {
+    text.encode_utf16()
+        .map(|unit| format!("{:04X}", unit))
+        .collect()
+}
+
+fn to_unicode_cmap(glyphs: &BTreeMap<u16, String>) -> String {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+
+    let entries: Vec<_> = glyphs.iter().collect();
+    for chunk in entries.chunks(100) {
+        let _ = writeln!(cmap, "{} beginbfchar", chunk.len());
+        for (glyph_id, text) in chunk {
+            let _ = writeln!(cmap, "<{:04X}> <{}>", glyph_id, utf16_hex(text));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    cmap
+}
+
+/// The font program of face `index` in `data`. A face from a collection is
+/// copied out into a font file of its own, since PDF embeds single faces.
+fn font_program(data: &[u8], index: u32) -> Option<Vec<u8>> {
+    let read_u16 = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    };
+    let read_u32 = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+
+    if data.get(..4) != Some(b"ttcf") {
+        return (index == 0).then(|| data.to_vec());
+    }
+
+    if index as usize >= read_u32(8)? {
+        return None;
+    }
+    let start = read_u32(12 + 4 * index as usize)?;
+    let tables = read_u16(start + 4)?;
+    let mut program = data.get(start..start + 12 + 16 * tables)?.to_vec();
+    for table in 0..tables {
+        let record = 12 + 16 * table;
+        let offset = read_u32(start + record + 8)?;
+        let length = read_u32(start + record + 12)?;
+        let body = data.get(offset..offset + length)?;
+        let moved = program.len() as u32;
+        program[record + 8..record + 12].copy_from_slice(&moved.to_be_bytes());
+        program.extend_from_slice(body);
+        program.resize(program.len().next_multiple_of(4), 0);
+    }
+    Some(program)
+}
+
+/// Writes the Type0 font `id` and the four objects after it: its CID font,
+/// descriptor, font program and ToUnicode map.
+fn write_font(writer: &mut PdfWriter, id: usize, font_system: &FontSystem, font: &PdfFont) {
+    let (cid_font, descriptor, font_file, to_unicode) = (id + 1, id + 2, id + 3, id + 4);
+    let name: String = font_system
+        .db()
+        .face(font.face)
+        .map(|face| face.post_script_name.as_str())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    let name = if name.is_empty() {
+        format!("Font{}", id)
+    } else {
+        name
+    };
+    let program = font_system
+        .db()
+        .with_face_data(font.face, font_program)
+        .flatten();
+    // OpenType fonts with CFF outlines are embedded whole, TrueType ones as
+    // glyph programs.
+    let cff = program
+        .as_ref()
+        .is_some_and(|program| program.starts_with(b"OTTO"));
+
+    writer.object(
+        id,
+        &format!(
+            "<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H \
+             /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+            name, cid_font, to_unicode
+        ),
+    );
+
+    let widths: String = font
+        .widths
+        .iter()
+        .map(|(glyph_id, width)| format!("{} [{:.0}]", glyph_id, width))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writer.object(
+        cid_font,
+        &format!(
+            "<< /Type /Font /Subtype /{} /BaseFont /{} \
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+             /FontDescriptor {} 0 R {}/DW 1000 /W [{}] >>",
+            if cff { "CIDFontType0" } else { "CIDFontType2" },
+            name,
+            descriptor,
+            if cff { "" } else { "/CIDToGIDMap /Identity " },
+            widths
+        ),
+    );
+
+    let file = match &program {
+        Some(_) if cff => format!("/FontFile3 {} 0 R ", font_file),
+        Some(_) => format!("/FontFile2 {} 0 R ", font_file),
+        None => String::new(),
+    };
+    writer.object(
+        descriptor,
+        &format!(
+            "<< /Type /FontDescriptor /FontName /{} /Flags 32 \
+             /FontBBox [-500 -500 1500 1500] /ItalicAngle 0 /Ascent 1000 /Descent -300 \
+             /CapHeight 700 /StemV 80 {}>>",
+            name, file
+        ),
+    );
+    match program {
+        Some(program) if cff => writer.stream(font_file, "/Subtype /OpenType", &program),
+        Some(program) => writer.stream(font_file, &format!("/Length1 {}", program.len()), &program),
+        None => {}
+    }
+    writer.stream(to_unicode, "", to_unicode_cmap(&font.text).as_bytes());
+}
+
+/// A painted page, waiting for the images and fonts of the whole document
+/// to be numbered.
+struct PdfPage {
+    size: [f32; 2],
+    content: String,
+    images: BTreeSet<usize>,
+    fonts: BTreeSet<usize>,
+}
+
+pub(crate) fn write_pdf(
+    font_system: &mut FontSystem,
+    pages: &[PageContent],
+    board_size: [f32; 2],
+    options: &PdfExportOptions,
+) -> Vec<u8> {
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+    const FIRST_PAGE: usize = 3;
+    const FONT_OBJECTS: usize = 5;
+
+    let mut fonts = Vec::new();
+    let mut pdf_images = Vec::new();
+
+    let mut pdf_pages = Vec::new();
+    for page_content in pages {
+        let mut painter = PdfPainter {
+            font_system,
+            fonts: &mut fonts,
+            page_fonts: BTreeSet::new(),
+            images: &mut pdf_images,
+            page_images: BTreeSet::new(),
+            board_size,
+            paper: [1.0; 4],
+            content: String::from("1 w 1 J 1 j 4 M\n"),
+            bounds: None,
+        };
+        export::paint_actions(
+            Some(&page_content.paper),
+            page_content.slide,
+            page_content.actions.iter(),
+            page_content.images,
+            &mut painter,
+        );
+
+        let region = match (options.page_size, painter.bounds) {
+            (PageSize::FitToContent, Some([x1, y1, x2, y2])) => [
+                x1 - CONTENT_MARGIN,
+                y1 - CONTENT_MARGIN,
+                x2 - x1 + CONTENT_MARGIN * 2.0,
+                y2 - y1 + CONTENT_MARGIN * 2.0,
+            ],
+            _ => [0.0, 0.0, board_size[0], board_size[1]],
+        };
+
+        let (page, scale) = match options.page_size.points() {
+            Some([short, long]) => {
+                let page = if region[2] > region[3] {
+                    [long, short]
+                } else {
+                    [short, long]
+                };
+                let scale = ((page[0] - PAPER_MARGIN * 2.0) / region[2])
+                    .min((page[1] - PAPER_MARGIN * 2.0) / region[3]);
+                (page, scale)
+            }
+            None => (
+                [region[2] * POINTS_PER_PIXEL, region[3] * POINTS_PER_PIXEL],
+                POINTS_PER_PIXEL,
+            ),
+        };
+
+        // Board pixels have their origin at the top left, PDF at the bottom left.
+        let translate_x = (page[0] - region[2] * scale) / 2.0 - region[0] * scale;
+        let translate_y = page[1] - (page[1] - region[3] * scale) / 2.0 + region[1] * scale;
+        pdf_pages.push(PdfPage {
+            size: page,
+            content: format!(
+                "q {:.4} 0 0 {:.4} {:.2} {:.2} cm\n{}Q\n",
+                scale, -scale, translate_x, translate_y, painter.content
+            ),
+            images: painter.page_images,
+            fonts: painter.page_fonts,
+        });
+    }
+
+    // Images follow the page and content objects, fonts follow the images.
+    let first_image = FIRST_PAGE + pages.len() * 2;
+    let first_font = first_image + pdf_images.len();
+
+    let mut writer = PdfWriter {
+        bytes: b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+        offsets: BTreeMap::new(),
+    };
+
+    let mut kids = Vec::new();
+    for (index, page) in pdf_pages.iter().enumerate() {
+        let x_objects: String = page
+            .images
+            .iter()
+            .map(|number| format!("/Im{} {} 0 R ", number, first_image + number))
+            .collect();
+        let page_fonts: String = page
+            .fonts
+            .iter()
+            .map(|number| format!("/F{} {} 0 R ", number, first_font + number * FONT_OBJECTS))
+            .collect();
+        let page_id = FIRST_PAGE + index * 2;
+        let content_id = page_id + 1;
+        writer.object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                 /Resources << /Font << {}>> /XObject << {}>> >> /Contents {} 0 R >>",
+                PAGES, page.size[0], page.size[1], page_fonts, x_objects, content_id
+            ),
+        );
+        writer.stream(content_id, "", page.content.as_bytes());
+        kids.push(format!("{} 0 R", page_id));
+    }
+
+    for (number, (_, pixels)) in pdf_images.iter().enumerate() {
+        write_image(&mut writer, first_image + number, pixels);
+    }
+
+    for (number, font) in fonts.iter().enumerate() {
+        write_font(
+            &mut writer,
+            first_font + number * FONT_OBJECTS,
+            font_system,
+            font,
+        );
+    }
+
+    writer.object(
+        CATALOG,
+        &format!("<< /Type /Catalog /Pages {} 0 R >>", PAGES),
+    );
+    writer.object(
+        PAGES,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            kids.len()
+        ),
+    );
+
+    writer.finish(CATALOG)
+}
+
+/// Writes one PDF page per board page, on its paper. Strokes and rectangles
+/// become vector paths and text is set in the fonts it was shaped with,
+/// Vazir and any fallbacks, embedded so it stays selectable.
+/// Slides and images are embedded once each, however many pages show them.
+pub fn export_pdf(
+    pages: &[PageContent],
+    board_size: [f32; 2],
+    options: &PdfExportOptions,
+) -> Vec<u8> {
+    write_pdf(&mut render::font_system(), pages, board_size, options)
+}
+
+impl WindowState {
+    /// Exports every page of the board with its slide, one PDF page each.
+    pub fn export_pdf(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: &PdfExportOptions,
+    ) -> Result<(), ExportError> {
+        let board_size = self.board_size();
+        let actions: Vec<Vec<Action>> = (0..self.pages.len())
+            .map(|index| self.page_scene(index).committed().cloned().collect())
+            .collect();
+        let pages: Vec<PageContent> = self
+            .pages
+            .iter()
+            .zip(&actions)
+            .map(|(page, actions)| PageContent {
+                paper: page.paper,
+                slide: page.slide.as_ref(),
+                actions,
+                images: &self.images,
+            })
+            .collect();
+        let pdf = write_pdf(&mut self.font_system, &pages, board_size, options);
+        std::fs::write(path, pdf).map_err(ExportError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{images::ImageStore, paper::PaperKind, structs::ActionType};
+    use lopdf::{content::Content, Document, Object};
+    use uuid::Uuid;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+
+    fn action(action_type: ActionType) -> Action {
+        Action {
+            id: Uuid::new_v4(),
+            action_type,
+        }
+    }
+
+    fn stroke(points: Vec<[f32; 2]>) -> Action {
+        action(ActionType::Stroke(Stroke {
+            color: [1.0, 0.0, 0.0, 1.0],
+            width: 4.0,
+            points,
+            pressure: vec![],
+            join: LineJoin::Round,
+        }))
+    }
+
+    fn text(text: &str) -> Action {
+        action(ActionType::Text(TextEntries {
+            position: [100.0, 200.0],
+            text: text.to_owned(),
+            pending: false,
+            ..TextEntries::null([0, 0, 0, 255], 24)
+        }))
+    }
+
+    /// Exports a page of `actions` on `paper` and parses it back.
+    fn pdf(paper: Paper, actions: &[Action]) -> Document {
+        let images = ImageStore::default();
+        let page = PageContent {
+            paper,
+            slide: None,
+            actions,
+            images: &images,
+        };
+        let pdf = export_pdf(&[page], BOARD_SIZE, &PdfExportOptions::default());
+        Document::load_mem(&pdf).unwrap()
+    }
+
+    fn operators(document: &Document) -> Vec<String> {
+        operations(document)
+            .into_iter()
+            .map(|operation| operation.operator)
+            .collect()
+    }
+
+    fn operations(document: &Document) -> Vec<lopdf::content::Operation> {
+        let (_, page) = document.get_pages().pop_first().unwrap();
+        let content = document.get_page_content(page).unwrap();
+        Content::decode(&content).unwrap().operations
+    }
+
+    fn dictionary<'a>(document: &'a Document, object: &Object) -> &'a lopdf::Dictionary {
+        document
+            .get_dictionary(object.as_reference().unwrap())
+            .unwrap()
+    }
+
+    /// What the marked spans of the page stand for, in logical order.
+    fn actual_text(document: &Document) -> Vec<String> {
+        operations(document)
+            .into_iter()
+            .filter(|operation| operation.operator == "BDC")
+            .map(|operation| {
+                let span = operation.operands[1].as_dict().unwrap();
+                let bytes = span.get(b"ActualText").unwrap().as_str().unwrap();
+                let units: Vec<u16> = bytes
+                    .chunks(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16(&units[1..]).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writes_one_page_per_board_page() {
+        let images = ImageStore::default();
+        let actions = [stroke(vec![[0.0, 0.0]])];
+        let page = PageContent {
+            paper: Paper::default(),
+            slide: None,
+            actions: &actions,
+            images: &images,
+        };
+        let pdf = export_pdf(&[page; 3], BOARD_SIZE, &PdfExportOptions::default());
+        assert_eq!(Document::load_mem(&pdf).unwrap().get_pages().len(), 3);
+    }
+
+    #[test]
+    fn draws_strokes_as_curves_and_taps_as_dots() {
+        let curve = stroke(vec![[-0.5, 0.0], [-0.2, 0.5], [0.2, 0.5], [0.5, 0.0]]);
+        let curved = operators(&pdf(Paper::default(), &[curve]));
+        let stroked = curved.iter().position(|operator| operator == "S").unwrap();
+        assert_eq!(curved[stroked - 2..=stroked], ["m", "c", "S"]);
+
+        let tap = stroke(vec![[0.0, -0.5]]);
+        let dotted = operators(&pdf(Paper::default(), &[tap]));
+        assert!(!dotted.contains(&"S".to_owned()));
+        let filled = dotted.iter().rposition(|operator| operator == "f").unwrap();
+        assert_eq!(
+            dotted[filled - 6..=filled],
+            ["m", "c", "c", "c", "c", "h", "f"]
+        );
+    }
+
+    #[test]
+    fn draws_the_paper_under_the_page() {
+        let ruled = Paper {
+            kind: PaperKind::Ruled,
+            color: [0.2, 0.4, 0.6, 1.0],
+        };
+        let operations = operations(&pdf(ruled, &[]));
+        let fill = operations
+            .iter()
+            .find(|operation| operation.operator == "rg")
+            .unwrap();
+        let color: Vec<f32> = fill
+            .operands
+            .iter()
+            .map(|operand| operand.as_float().unwrap())
+            .collect();
+        assert_eq!(color, [0.2, 0.4, 0.6]);
+        let rules = operations
+            .iter()
+            .filter(|operation| operation.operator == "S")
+            .count();
+        // Rules every 48 pixels from 96 down, and the margin.
+        assert_eq!(rules, 12);
+    }
+
+    const VAZIR: &[u8] = include_bytes!("assets/vazir.ttf");
+
+    #[test]
+    fn embeds_the_font_with_a_unicode_map() {
+        let document = pdf(Paper::default(), &[text("hello")]);
+        let (_, page) = document.get_pages().pop_first().unwrap();
+        let fonts = document.get_page_fonts(page).unwrap();
+        let font = fonts[&b"F0"[..]];
+        assert_eq!(
+            font.get(b"BaseFont").unwrap().as_name().unwrap(),
+            b"Vazir-Medium"
+        );
+
+        let descendant = &font.get(b"DescendantFonts").unwrap().as_array().unwrap()[0];
+        let descriptor = dictionary(
+            &document,
+            dictionary(&document, descendant)
+                .get(b"FontDescriptor")
+                .unwrap(),
+        );
+        let file = descriptor
+            .get(b"FontFile2")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let file = document.get_object(file).unwrap().as_stream().unwrap();
+        assert_eq!(file.content, VAZIR);
+
+        let to_unicode = font.get(b"ToUnicode").unwrap().as_reference().unwrap();
+        let cmap = document
+            .get_object(to_unicode)
+            .unwrap()
+            .as_stream()
+            .unwrap();
+        let cmap = String::from_utf8_lossy(&cmap.content);
+        // "hello" has four distinct letters.
+        assert!(cmap.contains("4 beginbfchar"));
+        assert!(cmap.contains(&format!("<{}>", utf16_hex("h"))));
+    }
+
+    #[test]
+    fn keeps_right_to_left_text_in_logical_order() {
+        let written = "سلام دنیا";
+        let document = pdf(Paper::default(), &[text(written)]);
+        assert_eq!(actual_text(&document), [written]);
+        let glyphs = operators(&document)
+            .iter()
+            .filter(|operator| *operator == "Tj")
+            .count();
+        // Lam and alef join into one glyph.
+        assert_eq!(glyphs, written.chars().count() - 1);
+    }
+
+    #[test]
+    fn embeds_the_fonts_text_falls_back_to() {
+        // Vazir has no box drawing or math symbols.
+        let written = "سلام ∑ ═";
+        let document = pdf(Paper::default(), &[text(written)]);
+        let (_, page) = document.get_pages().pop_first().unwrap();
+        let fonts = document.get_page_fonts(page).unwrap();
+        for font in fonts.values() {
+            let descendant = &font.get(b"DescendantFonts").unwrap().as_array().unwrap()[0];
+            let descriptor = dictionary(
+                &document,
+                dictionary(&document, descendant)
+                    .get(b"FontDescriptor")
+                    .unwrap(),
+            );
+            assert!(descriptor.has(b"FontFile2") || descriptor.has(b"FontFile3"));
+        }
+
+        let glyphs = operators(&document)
+            .iter()
+            .filter(|operator| *operator == "Tj")
+            .count();
+        let mut font_system = render::font_system();
+        let ActionType::Text(entry) = text(written).action_type else {
+            unreachable!()
+        };
+        let buffer = render::text_buffer(&mut font_system, &entry, BOARD_SIZE[0], BOARD_SIZE[1]);
+        let shaped = buffer
+            .layout_runs()
+            .flat_map(|run| run.glyphs)
+            .filter(|glyph| glyph.start >= render::TEXT_PREFIX_LEN)
+            .count();
+        assert_eq!(glyphs, shaped);
+    }
+
+    #[test]
+    fn copies_a_face_out_of_a_collection() {
+        // A collection holding Vazir as its only face.
+        let mut collection = b"ttcf\0\x01\0\0\0\0\0\x01\0\0\0\x10".to_vec();
+        let tables = u16::from_be_bytes([VAZIR[4], VAZIR[5]]) as usize;
+        let mut directory = VAZIR[..12 + 16 * tables].to_vec();
+        for table in 0..tables {
+            let record = 12 + 16 * table;
+            let offset = u32::from_be_bytes(VAZIR[record + 8..record + 12].try_into().unwrap());
+            let moved = offset + 16;
+            directory[record + 8..record + 12].copy_from_slice(&moved.to_be_bytes());
+        }
+        collection.extend_from_slice(&directory);
+        collection.extend_from_slice(&VAZIR[directory.len()..]);
+
+        let program = font_program(&collection, 0).unwrap();
+        let mut db = fontdb::Database::new();
+        db.load_font_data(program);
+        let face = db.faces().next().unwrap();
+        assert_eq!(face.post_script_name, "Vazir-Medium");
+        assert!(font_program(&collection, 1).is_none());
+    }
+
+    #[test]
+    fn escapes_text() {
+        let written = "(a) \\ b) <c>";
+        let document = pdf(Paper::default(), &[text(written)]);
+        assert_eq!(actual_text(&document), [written]);
+    }
+}
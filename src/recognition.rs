@@ -0,0 +1,368 @@
+use crate::{
+    export,
+    geometry::{self, segment_distance},
+    structs::{ActionType, ArrowHeads, Ellipse, Line, Polygon, Polyline, Rectangle},
+    vector::distance,
+};
+use std::time::{Duration, Instant};
+
+/// How long the pen has to rest at the end of a stroke in hold mode.
+const HOLD_DURATION: Duration = Duration::from_millis(500);
+/// Distance in board pixels the pen can drift and still count as resting.
+const HOLD_TOLERANCE: f32 = 4.0;
+/// Strokes smaller than this on both sides, in board pixels, are left alone.
+const MIN_SIZE: f32 = 16.0;
+/// A stroke whose ends are closer than this share of its length is closed.
+const CLOSED_GAP: f32 = 0.2;
+/// Farthest a line may stray from its chord, relative to the chord length.
+const LINE_TOLERANCE: f32 = 0.06;
+/// Smallest deviation that makes a corner, relative to the bounding box
+/// diagonal.
+const CORNER_TOLERANCE: f32 = 0.08;
+/// Smallest share of its bounding box a rectangle covers.
+const RECTANGLE_COVERAGE: f32 = 0.8;
+/// Largest mean distance of an ellipse from its outline, relative to the
+/// radius.
+const ELLIPSE_TOLERANCE: f32 = 0.1;
+/// How far an arrow head may reach back from the tip, relative to the shaft,
+/// and the shortest it can be drawn.
+const ARROW_HEAD_REACH: f32 = 0.4;
+const MIN_ARROW_HEAD: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecognitionMode {
+    #[default]
+    Off,
+    /// Every stroke that looks like a shape is replaced by it.
+    Auto,
+    /// Only strokes that end with the pen held still are replaced.
+    Hold,
+}
+
+/// Where the pen came to rest while drawing, and since when.
+#[derive(Clone, Copy, Debug)]
+pub struct PenRest {
+    anchor: [f32; 2],
+    since: Instant,
+}
+
+impl PenRest {
+    pub(crate) fn new(point: [f32; 2]) -> Self {
+        PenRest {
+            anchor: point,
+            since: Instant::now(),
+        }
+    }
+
+    /// Starts resting again at `point` once the pen leaves the anchor.
+    pub(crate) fn update(&mut self, point: [f32; 2]) {
+        if distance(self.anchor, point) > HOLD_TOLERANCE {
+            *self = PenRest::new(point);
+        }
+    }
+
+    pub(crate) fn is_held(&self) -> bool {
+        self.since.elapsed() >= HOLD_DURATION
+    }
+}
+
+/// Classifies a freehand stroke as a line, arrow, triangle, rectangle or
+/// ellipse, outlined in `color` and `width` board pixels. Returns `None` when
+/// it looks like none of them.
+pub(crate) fn recognize(
+    polyline: &Polyline,
+    color: [f32; 4],
+    width: f32,
+    board_size: [f32; 2],
+) -> Option<ActionType> {
+    let points: Vec<[f32; 2]> = polyline
+        .points
+        .iter()
+        .map(|point| export::ndc_to_board(*point, board_size))
+        .collect();
+    let bounds = geometry::bounds(points.iter().copied())?;
+    if bounds.width.max(bounds.height) < MIN_SIZE {
+        return None;
+    }
+
+    let ndc = |point: [f32; 2]| export::board_to_ndc(point, board_size);
+    let line = |first: [f32; 2], last: [f32; 2], heads: ArrowHeads| {
+        ActionType::Line(Line {
+            first: ndc(first),
+            last: ndc(last),
+            color,
+            width,
+            heads,
+        })
+    };
+
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if distance(first, last) > CLOSED_GAP * path_length(&points) {
+        if is_straight(&points) {
+            return Some(line(first, last, ArrowHeads::None));
+        }
+        return arrow_tip(&points).map(|tip| line(first, tip, ArrowHeads::End));
+    }
+
+    let diagonal = bounds.width.hypot(bounds.height);
+    let corners = closed_corners(&points, CORNER_TOLERANCE * diagonal);
+    let (top_left, bottom_right) = (
+        [bounds.x, bounds.y],
+        [bounds.x + bounds.width, bounds.y + bounds.height],
+    );
+    let coverage = polygon_area(&points) / (bounds.width * bounds.height).max(f32::EPSILON);
+
+    if corners.len() == 3 {
+        Some(ActionType::Polygon(Polygon {
+            points: corners.into_iter().map(ndc).collect(),
+            color,
+            fill: [0.0; 4],
+            outline_width: width,
+        }))
+    } else if corners.len() == 4 && coverage >= RECTANGLE_COVERAGE {
+        Some(ActionType::Shapes(Rectangle {
+            first: ndc(top_left),
+            last: ndc(bottom_right),
+            color,
+            fill: [0.0; 4],
+            outline_width: width,
+            corner_radius: 0.0,
+        }))
+    } else if ellipse_error(&points, top_left, bottom_right) <= ELLIPSE_TOLERANCE {
+        Some(ActionType::Ellipse(Ellipse {
+            first: ndc(top_left),
+            last: ndc(bottom_right),
+            color,
+            fill: [0.0; 4],
+            outline_width: width,
+        }))
+    } else {
+        None
+    }
+}
+
+fn path_length(points: &[[f32; 2]]) -> f32 {
+    points
+        .windows(2)
+        .map(|pair| distance(pair[0], pair[1]))
+        .sum()
+}
+
+/// Whether every point lies close to the chord between the ends.
+fn is_straight(points: &[[f32; 2]]) -> bool {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let chord = distance(first, last);
+    chord >= MIN_SIZE
+        && points
+            .iter()
+            .all(|point| segment_distance(*point, first, last) <= LINE_TOLERANCE * chord)
+}
+
+/// The tip of an arrow drawn in one go: a straight shaft out to the point
+/// farthest from the start, then a head scribbled around that point.
+fn arrow_tip(points: &[[f32; 2]]) -> Option<[f32; 2]> {
+    let first = points[0];
+    let tip = points
+        .iter()
+        .copied()
+        .max_by(|a, b| distance(first, *a).total_cmp(&distance(first, *b)))?;
+    let shaft_length = distance(first, tip);
+    // The head may pass the tip more than once; the shaft ends on the first.
+    let shaft_end = points
+        .iter()
+        .position(|point| distance(*point, tip) <= LINE_TOLERANCE * shaft_length)?;
+    let (shaft, head) = (&points[..=shaft_end], &points[shaft_end..]);
+
+    let is_head = head
+        .iter()
+        .all(|point| distance(*point, tip) <= ARROW_HEAD_REACH * shaft_length)
+        && path_length(head) >= MIN_ARROW_HEAD * shaft_length;
+    (is_straight(shaft) && is_head).then_some(tip)
+}
+
+/// Corners of a closed stroke: the points that stick out of the outline by
+/// more than `tolerance`.
+fn closed_corners(points: &[[f32; 2]], tolerance: f32) -> Vec<[f32; 2]> {
+    // Splitting at the point farthest from the start gives two open halves.
+    let first = points[0];
+    let far = (0..points.len())
+        .max_by(|&a, &b| distance(first, points[a]).total_cmp(&distance(first, points[b])));
+    let Some(far) = far.filter(|&far| far > 0) else {
+        return Vec::new();
+    };
+    let mut second_half = points[far..].to_vec();
+    second_half.push(first);
+
+    let mut corners = simplify(&points[..=far], tolerance);
+    corners.pop();
+    corners.extend(simplify(&second_half, tolerance));
+    corners.pop();
+
+    // The start rarely falls on a corner; drop it and any other point that
+    // sits on a straight side.
+    let mut index = 0;
+    while corners.len() > 3 && index < corners.len() {
+        let count = corners.len();
+        let previous = corners[(index + count - 1) % count];
+        let next = corners[(index + 1) % count];
+        if segment_distance(corners[index], previous, next) <= tolerance {
+            corners.remove(index);
+        } else {
+            index += 1;
+        }
+    }
+    corners
+}
+
+/// Ramer–Douglas–Peucker simplification of an open line, keeping both ends.
+fn simplify(points: &[[f32; 2]], tolerance: f32) -> Vec<[f32; 2]> {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let farthest = (1..points.len().saturating_sub(1))
+        .map(|index| (index, segment_distance(points[index], first, last)))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+
+    match farthest {
+        Some((index, deviation)) if deviation > tolerance => {
+            let mut kept = simplify(&points[..=index], tolerance);
+            kept.pop();
+            kept.extend(simplify(&points[index..], tolerance));
+            kept
+        }
+        _ if points.len() > 1 => vec![first, last],
+        _ => vec![first],
+    }
+}
+
+fn polygon_area(points: &[[f32; 2]]) -> f32 {
+    let twice_area: f32 = (0..points.len())
+        .map(|index| {
+            let [x1, y1] = points[index];
+            let [x2, y2] = points[(index + 1) % points.len()];
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    twice_area.abs() / 2.0
+}
+
+/// Mean distance of `points` from the ellipse inscribed in the box, relative
+/// to its radius.
+fn ellipse_error(points: &[[f32; 2]], top_left: [f32; 2], bottom_right: [f32; 2]) -> f32 {
+    let center = [
+        (top_left[0] + bottom_right[0]) / 2.0,
+        (top_left[1] + bottom_right[1]) / 2.0,
+    ];
+    let radii = [
+        ((bottom_right[0] - top_left[0]) / 2.0).max(f32::EPSILON),
+        ((bottom_right[1] - top_left[1]) / 2.0).max(f32::EPSILON),
+    ];
+    let total: f32 = points
+        .iter()
+        .map(|point| {
+            let x = (point[0] - center[0]) / radii[0];
+            let y = (point[1] - center[1]) / radii[1];
+            (x.hypot(y) - 1.0).abs()
+        })
+        .sum();
+    total / points.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+
+    /// A stroke through `corners`, in board pixels, with samples every few
+    /// pixels as a pen would give.
+    fn drawn(corners: &[[f32; 2]]) -> Polyline {
+        let mut polyline = Polyline::default();
+        for pair in corners.windows(2) {
+            for step in 0..20 {
+                let t = step as f32 / 20.0;
+                let point = [
+                    pair[0][0] + (pair[1][0] - pair[0][0]) * t,
+                    pair[0][1] + (pair[1][1] - pair[0][1]) * t,
+                ];
+                polyline.push(export::board_to_ndc(point, BOARD_SIZE), 1.0);
+            }
+        }
+        let last = corners[corners.len() - 1];
+        polyline.push(export::board_to_ndc(last, BOARD_SIZE), 1.0);
+        polyline
+    }
+
+    fn recognized(polyline: &Polyline) -> Option<ActionType> {
+        recognize(polyline, [0.0, 0.0, 0.0, 1.0], 2.0, BOARD_SIZE)
+    }
+
+    #[test]
+    fn recognizes_lines_and_arrows() {
+        let line = drawn(&[[100.0, 100.0], [300.0, 200.0]]);
+        let Some(ActionType::Line(line)) = recognized(&line) else {
+            panic!("expected a line");
+        };
+        assert_eq!(line.heads, ArrowHeads::None);
+
+        let arrow = drawn(&[
+            [100.0, 300.0],
+            [400.0, 300.0],
+            [370.0, 280.0],
+            [400.0, 300.0],
+            [370.0, 320.0],
+        ]);
+        let Some(ActionType::Line(arrow)) = recognized(&arrow) else {
+            panic!("expected an arrow");
+        };
+        assert_eq!(arrow.heads, ArrowHeads::End);
+    }
+
+    #[test]
+    fn recognizes_closed_shapes() {
+        let triangle = drawn(&[
+            [100.0, 300.0],
+            [200.0, 100.0],
+            [300.0, 300.0],
+            [100.0, 300.0],
+        ]);
+        assert!(matches!(
+            recognized(&triangle),
+            Some(ActionType::Polygon(polygon)) if polygon.points.len() == 3
+        ));
+
+        let square = drawn(&[
+            [100.0, 100.0],
+            [300.0, 100.0],
+            [300.0, 300.0],
+            [100.0, 300.0],
+            [100.0, 100.0],
+        ]);
+        assert!(matches!(recognized(&square), Some(ActionType::Shapes(_))));
+
+        let circle: Vec<[f32; 2]> = (0..=36)
+            .map(|step| {
+                let angle = step as f32 / 36.0 * TAU;
+                [400.0 + 100.0 * angle.cos(), 300.0 + 80.0 * angle.sin()]
+            })
+            .collect();
+        assert!(matches!(
+            recognized(&drawn(&circle)),
+            Some(ActionType::Ellipse(_))
+        ));
+    }
+
+    #[test]
+    fn leaves_small_strokes_and_scribbles() {
+        let small = drawn(&[[100.0, 100.0], [110.0, 105.0]]);
+        assert!(recognized(&small).is_none());
+
+        let scribble = drawn(&[
+            [100.0, 100.0],
+            [300.0, 250.0],
+            [120.0, 260.0],
+            [280.0, 110.0],
+            [200.0, 400.0],
+        ]);
+        assert!(recognized(&scribble).is_none());
+    }
+}
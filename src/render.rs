@@ -0,0 +1,694 @@
+use crate::{
+    images,
+    paper::PaperUniform,
+    structs::{ActionType, Image, ImageVertex, Rect, Vertex},
+    tessellate, TextEntries,
+};
+use glyphon::{
+    Attrs, Buffer, Family, FontSystem, Metrics, RenderError, Shaping, TextAtlas, TextRenderer,
+    Viewport, Weight,
+};
+use image::RgbaImage;
+use std::ops::Range;
+use wgpu::{
+    util::DeviceExt, vertex_attr_array, BindGroup, BindGroupLayout, Device, FragmentState,
+    MultisampleState, PipelineCompilationOptions, PrimitiveState, RenderPass, RenderPipeline,
+    ShaderModuleDescriptor, ShaderStages, TextureFormat, VertexBufferLayout,
+};
+
+pub(crate) fn font_system() -> FontSystem {
+    let mut font_system = FontSystem::new();
+    font_system
+        .db_mut()
+        .load_font_data(include_bytes!("assets/vazir.ttf").to_vec());
+    font_system
+}
+
+/// Layout of the camera uniform the stroke and shape shaders read at group 0.
+pub(crate) fn camera_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Camera Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+/// A uniform buffer holding `matrix` and the bind group that exposes it.
+pub(crate) fn camera_binding(
+    device: &Device,
+    layout: &BindGroupLayout,
+    matrix: [[f32; 4]; 4],
+) -> (wgpu::Buffer, BindGroup) {
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Camera Buffer"),
+        contents: bytemuck::cast_slice(&matrix),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Camera Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+    (buffer, bind_group)
+}
+
+/// Layout of the paper uniform the paper shader reads at group 1.
+pub(crate) fn paper_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Paper Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+/// A uniform buffer holding `paper` and the bind group that exposes it.
+pub(crate) fn paper_binding(
+    device: &Device,
+    layout: &BindGroupLayout,
+    paper: PaperUniform,
+) -> (wgpu::Buffer, BindGroup) {
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Paper Buffer"),
+        contents: bytemuck::bytes_of(&paper),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Paper Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+    (buffer, bind_group)
+}
+
+/// Draws the paper of the page procedurally under a quad covering the view.
+pub(crate) fn create_paper_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    camera_layout: &BindGroupLayout,
+    paper_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("Paper Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/paper.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Paper Pipeline Layout"),
+        bind_group_layouts: &[camera_layout, paper_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Paper Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![
+                    0 => Float32x2,
+                    1 => Float32x4
+                ],
+            }],
+            compilation_options: PipelineCompilationOptions::default(),
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Layout of the texture and sampler the image shader reads at group 1.
+pub(crate) fn image_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Image Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Uploads `pixels` to a texture and returns the bind group that samples it.
+/// The bind group keeps the texture alive.
+pub(crate) fn image_binding(
+    device: &Device,
+    queue: &wgpu::Queue,
+    layout: &BindGroupLayout,
+    pixels: &RgbaImage,
+) -> BindGroup {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Image Texture"),
+            size: wgpu::Extent3d {
+                width: pixels.width().max(1),
+                height: pixels.height().max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        pixels.as_raw(),
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Image Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Image Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+    })
+}
+
+/// Draws images as textured quads.
+pub(crate) fn create_image_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    camera_layout: &BindGroupLayout,
+    image_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("Image Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/image.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Image Pipeline Layout"),
+        bind_group_layouts: &[camera_layout, image_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Image Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[VertexBufferLayout {
+                array_stride: std::mem::size_of::<ImageVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![
+                    0 => Float32x2,
+                    1 => Float32x2
+                ],
+            }],
+            compilation_options: PipelineCompilationOptions::default(),
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+pub(crate) fn create_stroke_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    camera_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/main.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Pipeline Layout"),
+        bind_group_layouts: &[camera_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![
+                    0 => Float32x2,
+                    1 => Float32x4
+                ],
+            }],
+            compilation_options: PipelineCompilationOptions::default(),
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Line list pipeline used for outlines drawn over the board.
+pub(crate) fn create_rectangle_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    camera_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    create_shape_shader_pipeline(
+        device,
+        format,
+        camera_layout,
+        "rectangle_vs",
+        wgpu::PrimitiveTopology::LineList,
+    )
+}
+
+/// Triangle list pipeline that draws filled and outlined shapes.
+pub(crate) fn create_shape_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    camera_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    create_shape_shader_pipeline(
+        device,
+        format,
+        camera_layout,
+        "triangle_vs",
+        wgpu::PrimitiveTopology::TriangleList,
+    )
+}
+
+fn create_shape_shader_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    camera_layout: &BindGroupLayout,
+    entry_point: &str,
+    topology: wgpu::PrimitiveTopology,
+) -> RenderPipeline {
+    let shader_shape = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("rect shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shape.wgsl").into()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Pipeline Layout"),
+        bind_group_layouts: &[camera_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("rect pipline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader_shape,
+            entry_point: Some(entry_point),
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[VertexBufferLayout {
+                array_stride: size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &vertex_attr_array![
+                    0 => Float32x2,
+                    1 => Float32x4
+                ],
+            }],
+        },
+        primitive: PrimitiveState {
+            topology,
+            strip_index_format: None,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &shader_shape,
+            entry_point: Some("fs_main"),
+            compilation_options: PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Marks text as left-to-right without joining to what follows, so Persian
+/// text starts at its position like English does.
+const TEXT_PREFIX: &str = "\u{200E}\u{200C}";
+/// Byte length of the prefix `text_buffer` adds before the text.
+pub(crate) const TEXT_PREFIX_LEN: usize = TEXT_PREFIX.len();
+
+pub(crate) fn text_buffer(
+    font_system: &mut FontSystem,
+    text_entry: &TextEntries,
+    width: f32,
+    height: f32,
+) -> Buffer {
+    let mut text_buffer = Buffer::new(
+        font_system,
+        Metrics::new(
+            text_entry.font_size as f32,
+            text_entry.font_size as f32 * 0.1,
+        ),
+    );
+
+    text_buffer.set_size(font_system, Some(width), Some(height));
+    text_buffer.shape_until_scroll(font_system, false);
+
+    let text = format!("{}{}", TEXT_PREFIX, text_entry.text);
+    text_buffer.set_text(
+        font_system,
+        &text,
+        // Vazir ships as a single medium face; asking for any other weight
+        // makes cosmic-text fall back to a system font.
+        Attrs::new()
+            .family(Family::Name("Vazir"))
+            .weight(Weight::MEDIUM),
+        Shaping::Advanced,
+    );
+    text_buffer.shape_until_scroll(font_system, false);
+    text_buffer
+}
+
+/// The area covered by the glyphs of `text_entry` on a board of `board_size`,
+/// in board pixels. `None` when there is nothing to draw.
+pub(crate) fn text_extent(
+    font_system: &mut FontSystem,
+    text_entry: &TextEntries,
+    board_size: [f32; 2],
+) -> Option<Rect> {
+    let buffer = text_buffer(font_system, text_entry, board_size[0], board_size[1]);
+    let font_size = text_entry.font_size as f32;
+
+    let (mut left, mut top, mut right, mut bottom) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for run in buffer.layout_runs() {
+        for glyph in run.glyphs.iter().filter(|glyph| glyph.w > 0.0) {
+            left = left.min(glyph.x);
+            right = right.max(glyph.x + glyph.w);
+            top = top.min(run.line_y - font_size);
+            bottom = bottom.max(run.line_y + font_size * 0.25);
+        }
+    }
+
+    (left <= right).then_some(Rect {
+        x: text_entry.position[0] + left,
+        y: text_entry.position[1] + top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// A run of neighbouring actions drawn with the same pipeline. Only
+/// neighbours are merged, so content overlaps as it does on the board.
+pub(crate) enum Batch<'a> {
+    /// Each image with its texture and its range of `BoardBatches::image_vertices`.
+    Images(Vec<(&'a BindGroup, Range<u32>)>),
+    /// A range of `BoardBatches::vertices`.
+    Shapes(Range<u32>),
+    /// A range of `BoardBatches::vertices`.
+    Strokes(Range<u32>),
+    /// Drawn by the text renderer of the same index among the text batches.
+    Text(Vec<&'a TextEntries>),
+}
+
+/// The pipelines board content is drawn with, sharing one camera.
+pub(crate) struct BoardPipelines<'a> {
+    pub images: &'a RenderPipeline,
+    pub shapes: &'a RenderPipeline,
+    pub strokes: &'a RenderPipeline,
+    pub camera: &'a BindGroup,
+    /// The board area the pass is mapped into and the size of the whole
+    /// target, when the board doesn't fill it. Text is laid out in target
+    /// pixels, so it is drawn with the viewport set to the whole target.
+    pub letterbox: Option<([f32; 4], [u32; 2])>,
+}
+
+/// The content of a page split into batches in paint order, with the
+/// vertices they draw.
+pub(crate) struct BoardBatches<'a> {
+    pub batches: Vec<Batch<'a>>,
+    pub image_vertices: Vec<ImageVertex>,
+    pub vertices: Vec<Vertex>,
+}
+
+impl<'a> BoardBatches<'a> {
+    /// Batches the slide under `actions` and the actions in paint order.
+    /// Images whose texture `texture` can't find are left out.
+    pub(crate) fn new(
+        slide: Option<&'a Image>,
+        actions: impl IntoIterator<Item = &'a ActionType>,
+        texture: impl Fn(&str) -> Option<&'a BindGroup>,
+        board_size: [f32; 2],
+    ) -> Self {
+        let mut board = BoardBatches {
+            batches: Vec::new(),
+            image_vertices: Vec::new(),
+            vertices: Vec::new(),
+        };
+        if let Some(slide) = slide {
+            board.push_image(slide, &texture, board_size);
+        }
+        for action_type in actions {
+            match action_type {
+                ActionType::Image(image) => board.push_image(image, &texture, board_size),
+                ActionType::Stroke(stroke) => {
+                    let start = board.vertices.len() as u32;
+                    board
+                        .vertices
+                        .extend(tessellate::stroke_triangles(stroke, board_size));
+                    let end = board.vertices.len() as u32;
+                    match board.batches.last_mut() {
+                        Some(Batch::Strokes(range)) => range.end = end,
+                        _ => board.batches.push(Batch::Strokes(start..end)),
+                    }
+                }
+                ActionType::Text(text_entry) => match board.batches.last_mut() {
+                    Some(Batch::Text(texts)) => texts.push(text_entry),
+                    _ => board.batches.push(Batch::Text(vec![text_entry])),
+                },
+                shape => {
+                    let start = board.vertices.len() as u32;
+                    board
+                        .vertices
+                        .extend(tessellate::shape_triangles(shape, board_size));
+                    let end = board.vertices.len() as u32;
+                    match board.batches.last_mut() {
+                        Some(Batch::Shapes(range)) => range.end = end,
+                        _ => board.batches.push(Batch::Shapes(start..end)),
+                    }
+                }
+            }
+        }
+        board
+    }
+
+    fn push_image(
+        &mut self,
+        image: &Image,
+        texture: impl Fn(&str) -> Option<&'a BindGroup>,
+        board_size: [f32; 2],
+    ) {
+        let Some(texture) = texture(&image.hash) else {
+            return;
+        };
+        let start = self.image_vertices.len() as u32;
+        self.image_vertices
+            .extend(images::image_vertices(image, board_size));
+        let draw = (texture, start..self.image_vertices.len() as u32);
+        match self.batches.last_mut() {
+            Some(Batch::Images(draws)) => draws.push(draw),
+            _ => self.batches.push(Batch::Images(vec![draw])),
+        }
+    }
+
+    /// The text of each text batch, in paint order.
+    pub(crate) fn texts(&self) -> impl Iterator<Item = &[&'a TextEntries]> {
+        self.batches.iter().filter_map(|batch| match batch {
+            Batch::Text(texts) => Some(texts.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Draws the batches in order. `text_renderers` holds one prepared
+    /// renderer per text batch.
+    pub(crate) fn draw(
+        &self,
+        device: &Device,
+        render_pass: &mut RenderPass,
+        pipelines: &BoardPipelines,
+        text_renderers: &[TextRenderer],
+        atlas: &TextAtlas,
+        viewport: &Viewport,
+    ) -> Result<(), RenderError> {
+        let image_buffer = (!self.image_vertices.is_empty()).then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Image Vertex Buffer"),
+                contents: bytemuck::cast_slice(&self.image_vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+        let vertex_buffer = (!self.vertices.is_empty()).then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&self.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            })
+        });
+
+        let mut text_renderers = text_renderers.iter();
+        for batch in &self.batches {
+            match (batch, &image_buffer, &vertex_buffer) {
+                (Batch::Images(draws), Some(buffer), _) => {
+                    render_pass.set_pipeline(pipelines.images);
+                    render_pass.set_bind_group(0, pipelines.camera, &[]);
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    for (texture, range) in draws {
+                        render_pass.set_bind_group(1, *texture, &[]);
+                        render_pass.draw(range.clone(), 0..1);
+                    }
+                }
+                (Batch::Shapes(range) | Batch::Strokes(range), _, Some(buffer)) => {
+                    let pipeline = match batch {
+                        Batch::Shapes(_) => pipelines.shapes,
+                        _ => pipelines.strokes,
+                    };
+                    render_pass.set_pipeline(pipeline);
+                    render_pass.set_bind_group(0, pipelines.camera, &[]);
+                    render_pass.set_vertex_buffer(0, buffer.slice(..));
+                    render_pass.draw(range.clone(), 0..1);
+                }
+                (Batch::Text(_), _, _) => {
+                    let Some(text_renderer) = text_renderers.next() else {
+                        continue;
+                    };
+                    let Some(([left, top, width, height], target_size)) = pipelines.letterbox
+                    else {
+                        text_renderer.render(atlas, viewport, render_pass)?;
+                        continue;
+                    };
+                    let [target_width, target_height] = target_size.map(|side| side as f32);
+                    render_pass.set_viewport(0.0, 0.0, target_width, target_height, 0.0, 1.0);
+                    text_renderer.render(atlas, viewport, render_pass)?;
+                    render_pass.set_viewport(left, top, width, height, 0.0, 1.0);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Grows `text_renderers` to one renderer per text batch of `board`.
+pub(crate) fn reserve_text_renderers(
+    text_renderers: &mut Vec<TextRenderer>,
+    board: &BoardBatches,
+    atlas: &mut TextAtlas,
+    device: &Device,
+) {
+    let needed = board.texts().count();
+    while text_renderers.len() < needed {
+        text_renderers.push(TextRenderer::new(
+            atlas,
+            device,
+            MultisampleState::default(),
+            None,
+        ));
+    }
+}
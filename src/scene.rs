@@ -0,0 +1,239 @@
+use crate::structs::{Action, ActionType, TextEntries};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Number of removed slots tolerated before the store is compacted.
+const COMPACT_THRESHOLD: usize = 64;
+
+/// Every action on the board, in paint order, addressable by id.
+///
+/// Removing leaves a hole in `slots` so lookups, inserts, updates and removals
+/// are all O(1); the holes are dropped once they outnumber the live actions.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    slots: Vec<Option<Action>>,
+    index: HashMap<Uuid, usize>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Scene::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.index.contains_key(id)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&Action> {
+        self.slots[*self.index.get(id)?].as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: &Uuid) -> Option<&mut Action> {
+        self.slots[*self.index.get(id)?].as_mut()
+    }
+
+    /// Appends `action` on top of the board. An action with the same id is
+    /// replaced where it is and returned.
+    pub fn insert(&mut self, action: Action) -> Option<Action> {
+        if let Some(&slot) = self.index.get(&action.id) {
+            return self.slots[slot].replace(action);
+        }
+
+        self.index.insert(action.id, self.slots.len());
+        self.slots.push(Some(action));
+        None
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<Action> {
+        let slot = self.index.remove(id)?;
+        let action = self.slots[slot].take();
+
+        let holes = self.slots.len() - self.index.len();
+        if holes > COMPACT_THRESHOLD && holes > self.index.len() {
+            self.compact();
+        }
+        action
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.index.clear();
+    }
+
+    /// All actions in paint order, including text that is still being typed.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Action> {
+        self.slots.iter().flatten()
+    }
+
+    /// Actions that are final and can be shared, saved or exported.
+    pub fn committed(&self) -> impl DoubleEndedIterator<Item = &Action> {
+        self.iter().filter(|action| !action.is_pending())
+    }
+
+    pub fn texts(&self) -> impl Iterator<Item = (Uuid, &TextEntries)> {
+        self.iter().filter_map(|action| match &action.action_type {
+            ActionType::Text(text_entry) => Some((action.id, text_entry)),
+            _ => None,
+        })
+    }
+
+    pub fn text(&self, id: &Uuid) -> Option<&TextEntries> {
+        match &self.get(id)?.action_type {
+            ActionType::Text(text_entry) => Some(text_entry),
+            _ => None,
+        }
+    }
+
+    pub fn text_mut(&mut self, id: &Uuid) -> Option<&mut TextEntries> {
+        match &mut self.get_mut(id)?.action_type {
+            ActionType::Text(text_entry) => Some(text_entry),
+            _ => None,
+        }
+    }
+
+    fn compact(&mut self) {
+        self.slots.retain(Option::is_some);
+        for (slot, action) in self.slots.iter().flatten().enumerate() {
+            self.index.insert(action.id, slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, pending: bool) -> Action {
+        Action {
+            id: Uuid::new_v4(),
+            action_type: ActionType::Text(TextEntries {
+                text: text.to_owned(),
+                pending,
+                ..TextEntries::null([0, 0, 0, 255], 24)
+            }),
+        }
+    }
+
+    fn texts(scene: &Scene) -> Vec<&str> {
+        scene
+            .texts()
+            .map(|(_, text_entry)| text_entry.text.as_str())
+            .collect()
+    }
+
+    /// A scene of `count` actions numbered in paint order, and their ids.
+    fn numbered(count: usize) -> (Scene, Vec<Uuid>) {
+        let mut scene = Scene::new();
+        let ids = (0..count)
+            .map(|number| {
+                let action = text(&number.to_string(), false);
+                let id = action.id;
+                scene.insert(action);
+                id
+            })
+            .collect();
+        (scene, ids)
+    }
+
+    #[test]
+    fn finds_actions_by_id() {
+        let mut scene = Scene::new();
+        let (a, b) = (text("a", false), text("b", false));
+        let (a_id, b_id) = (a.id, b.id);
+        assert!(scene.insert(a).is_none());
+        assert!(scene.insert(b).is_none());
+
+        assert_eq!(scene.len(), 2);
+        assert!(scene.contains(&a_id));
+        assert_eq!(scene.text(&b_id).unwrap().text, "b");
+        assert!(scene.get(&Uuid::new_v4()).is_none());
+
+        scene.text_mut(&a_id).unwrap().text = "c".to_owned();
+        assert_eq!(texts(&scene), ["c", "b"]);
+    }
+
+    #[test]
+    fn replaces_an_action_where_it_is() {
+        let (mut scene, ids) = numbered(3);
+        let replacement = Action {
+            id: ids[0],
+            ..text("first", false)
+        };
+        let replaced = scene.insert(replacement).unwrap();
+        assert!(matches!(
+            replaced.action_type,
+            ActionType::Text(text_entry) if text_entry.text == "0"
+        ));
+        assert_eq!(scene.len(), 3);
+        assert_eq!(texts(&scene), ["first", "1", "2"]);
+    }
+
+    #[test]
+    fn leaves_a_hole_where_an_action_is_removed() {
+        let (mut scene, ids) = numbered(3);
+        assert!(scene.remove(&ids[1]).is_some());
+        assert!(scene.remove(&ids[1]).is_none());
+
+        assert_eq!(scene.slots.len(), 3);
+        assert_eq!(scene.len(), 2);
+        assert!(!scene.contains(&ids[1]));
+        assert_eq!(texts(&scene), ["0", "2"]);
+        assert_eq!(scene.text(&ids[2]).unwrap().text, "2");
+        assert_eq!(scene.iter().rev().count(), 2);
+    }
+
+    #[test]
+    fn compacts_once_holes_pass_the_threshold_and_outnumber_actions() {
+        let (mut scene, ids) = numbered(100);
+        for id in &ids[..COMPACT_THRESHOLD] {
+            scene.remove(id);
+        }
+        assert_eq!(scene.slots.len(), 100);
+
+        scene.remove(&ids[COMPACT_THRESHOLD]);
+        assert_eq!(scene.slots.len(), 100 - COMPACT_THRESHOLD - 1);
+        let rest: Vec<String> = (COMPACT_THRESHOLD + 1..100)
+            .map(|number| number.to_string())
+            .collect();
+        assert_eq!(texts(&scene), rest);
+        for (number, id) in ids.iter().enumerate().skip(COMPACT_THRESHOLD + 1) {
+            assert_eq!(scene.text(id).unwrap().text, number.to_string());
+        }
+    }
+
+    #[test]
+    fn keeps_holes_while_actions_outnumber_them() {
+        let (mut scene, ids) = numbered(200);
+        for id in &ids[..100] {
+            scene.remove(id);
+        }
+        assert_eq!(scene.slots.len(), 200);
+
+        scene.remove(&ids[100]);
+        assert_eq!(scene.slots.len(), 99);
+        assert_eq!(scene.text(&ids[199]).unwrap().text, "199");
+    }
+
+    #[test]
+    fn leaves_pending_text_out_of_committed_actions() {
+        let mut scene = Scene::new();
+        scene.insert(text("typed", false));
+        scene.insert(text("typing", true));
+        assert_eq!(scene.iter().count(), 2);
+        let committed: Vec<_> = scene.committed().collect();
+        assert_eq!(committed.len(), 1);
+        assert!(!committed[0].is_pending());
+
+        scene.clear();
+        assert!(scene.is_empty());
+        assert_eq!(scene.iter().count(), 0);
+    }
+}
@@ -0,0 +1,571 @@
+use crate::{
+    export,
+    geometry::{self, Transform},
+    structs::{Action, Rect, Vertex},
+    vector, WindowState,
+};
+use dioxus::desktop::tao::{dpi::PhysicalPosition, keyboard::Key};
+use uuid::Uuid;
+
+/// Distance in board pixels within which a click picks an object.
+const PICK_RADIUS: f32 = 4.0;
+/// Gap in board pixels between the selected objects and their bounding box.
+const SELECTION_PADDING: f32 = 6.0;
+/// Half the side of a handle, in board pixels.
+const HANDLE_SIZE: f32 = 5.0;
+/// Distance in board pixels from a handle that still grabs it.
+const HANDLE_REACH: f32 = 8.0;
+/// How far above the bounding box the rotate handle sits, in board pixels.
+const ROTATE_HANDLE_OFFSET: f32 = 24.0;
+/// Regions smaller than this on both sides, in board pixels, count as a click.
+const MIN_REGION: f32 = 3.0;
+/// Smallest factor a corner drag can shrink the selection to.
+const MIN_SCALE: f32 = 0.05;
+/// Board pixels moved by an arrow key, and by an arrow key with Shift held.
+const NUDGE_STEP: f32 = 1.0;
+const LARGE_NUDGE_STEP: f32 = 10.0;
+const OVERLAY_COLOR: [f32; 4] = [0.2, 0.5, 1.0, 1.0];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// Dragging over empty space selects what lies inside a rectangle.
+    #[default]
+    Box,
+    /// Dragging over empty space selects what lies inside a freehand loop.
+    Lasso,
+}
+
+/// State of the selection tool: what is selected and the drag in progress.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    pub mode: SelectionMode,
+    ids: Vec<Uuid>,
+    gesture: Option<Gesture>,
+}
+
+impl Selection {
+    pub fn new(mode: SelectionMode) -> Self {
+        Selection {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn ids(&self) -> &[Uuid] {
+        &self.ids
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Gesture {
+    /// A rubber band or lasso being dragged, in board pixels.
+    Region(Vec<[f32; 2]>),
+    Transform {
+        handle: Handle,
+        start: [f32; 2],
+        /// The selected actions and their box as they were when the drag began.
+        originals: Vec<Action>,
+        bounds: Rect,
+        transform: Transform,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Handle {
+    Move,
+    /// A corner handle; the opposite corner stays in place.
+    Scale {
+        anchor: [f32; 2],
+        corner: [f32; 2],
+    },
+    Rotate {
+        pivot: [f32; 2],
+    },
+}
+
+/// Corners of `bounds`, clockwise from the top left.
+fn corners(bounds: &Rect) -> [[f32; 2]; 4] {
+    let (left, top) = (bounds.x, bounds.y);
+    let (right, bottom) = (bounds.x + bounds.width, bounds.y + bounds.height);
+    [[left, top], [right, top], [right, bottom], [left, bottom]]
+}
+
+fn rotate_handle(bounds: &Rect) -> [f32; 2] {
+    [
+        bounds.x + bounds.width / 2.0,
+        bounds.y - ROTATE_HANDLE_OFFSET,
+    ]
+}
+
+/// The handle of a selection with `bounds` under `point`. Inside the box the
+/// whole selection is moved unless `allow_move` is false.
+fn handle_at(bounds: &Rect, point: [f32; 2], allow_move: bool) -> Option<Handle> {
+    let center = [
+        bounds.x + bounds.width / 2.0,
+        bounds.y + bounds.height / 2.0,
+    ];
+    if vector::distance(point, rotate_handle(bounds)) <= HANDLE_REACH {
+        return Some(Handle::Rotate { pivot: center });
+    }
+
+    let corners = corners(bounds);
+    for (index, corner) in corners.iter().enumerate() {
+        if vector::distance(point, *corner) <= HANDLE_REACH {
+            return Some(Handle::Scale {
+                anchor: corners[(index + 2) % 4],
+                corner: *corner,
+            });
+        }
+    }
+
+    let inside = point[0] >= bounds.x
+        && point[0] <= bounds.x + bounds.width
+        && point[1] >= bounds.y
+        && point[1] <= bounds.y + bounds.height;
+    (allow_move && inside).then_some(Handle::Move)
+}
+
+impl WindowState {
+    /// Box around the selected actions in board pixels, padded to leave room
+    /// for the handles.
+    fn selection_bounds(&mut self) -> Option<Rect> {
+        let board_size = self.board_size();
+        let selection = self.selection.as_ref()?;
+        let mut points = Vec::new();
+        for action in selection.ids.iter().filter_map(|id| self.scene.get(id)) {
+            points.extend(geometry::outline_points(
+                action,
+                board_size,
+                &self.camera,
+                &mut self.font_system,
+            ));
+        }
+
+        geometry::bounds(points).map(|bounds| Rect {
+            x: bounds.x - SELECTION_PADDING,
+            y: bounds.y - SELECTION_PADDING,
+            width: bounds.width + SELECTION_PADDING * 2.0,
+            height: bounds.height + SELECTION_PADDING * 2.0,
+        })
+    }
+
+    /// Starts a drag of the selection tool: grabs a handle of the current
+    /// selection, picks the object under the pointer, or starts a rubber band
+    /// or lasso over empty space. Shift adds to or removes from the selection.
+    pub(crate) fn begin_select(&mut self, position: PhysicalPosition<f64>) {
+        let point = [position.x as f32, position.y as f32];
+        let board_size = self.board_size();
+        let additive = self.pressed_keys.contains(&Key::Shift);
+
+        let mut handle = self
+            .selection_bounds()
+            .and_then(|bounds| handle_at(&bounds, point, !additive));
+        if handle.is_none() {
+            let picked = self
+                .scene
+                .committed()
+                .rev()
+                .find(|action| {
+                    geometry::touches(
+                        action,
+                        point,
+                        PICK_RADIUS,
+                        board_size,
+                        &self.camera,
+                        &mut self.font_system,
+                    )
+                })
+                .map(|action| action.id);
+            let Some(selection) = self.selection.as_mut() else {
+                return;
+            };
+
+            match picked {
+                Some(id) if additive => {
+                    if let Some(index) = selection.ids.iter().position(|selected| *selected == id) {
+                        selection.ids.remove(index);
+                    } else {
+                        selection.ids.push(id);
+                    }
+                }
+                Some(id) => {
+                    if !selection.ids.contains(&id) {
+                        selection.ids = vec![id];
+                    }
+                    handle = Some(Handle::Move);
+                }
+                None => {
+                    if !additive {
+                        selection.ids.clear();
+                    }
+                    selection.gesture = Some(Gesture::Region(vec![point]));
+                }
+            }
+        }
+
+        if let Some(handle) = handle {
+            let bounds = self.selection_bounds();
+            if let (Some(selection), Some(bounds)) = (self.selection.as_mut(), bounds) {
+                let originals = selection
+                    .ids
+                    .iter()
+                    .filter_map(|id| self.scene.get(id).cloned())
+                    .collect();
+                selection.gesture = Some(Gesture::Transform {
+                    handle,
+                    start: point,
+                    originals,
+                    bounds,
+                    transform: Transform::default(),
+                });
+            }
+        }
+    }
+
+    /// Follows the pointer during a selection drag. Transforms are applied to
+    /// the board as the pointer moves and shared with the room on release.
+    pub(crate) fn drag_select(&mut self, position: PhysicalPosition<f64>) {
+        let point = [position.x as f32, position.y as f32];
+        let board_size = self.board_size();
+        let Some(selection) = self.selection.as_mut() else {
+            return;
+        };
+
+        match &mut selection.gesture {
+            Some(Gesture::Region(points)) => {
+                if selection.mode == SelectionMode::Box {
+                    points.truncate(1);
+                }
+                points.push(point);
+            }
+            Some(Gesture::Transform {
+                handle,
+                start,
+                originals,
+                transform,
+                ..
+            }) => {
+                *transform = match *handle {
+                    Handle::Move => {
+                        Transform::translate([point[0] - start[0], point[1] - start[1]])
+                    }
+                    Handle::Scale { anchor, corner } => {
+                        let factor = |axis: usize| {
+                            let span = corner[axis] - anchor[axis];
+                            if span.abs() < f32::EPSILON {
+                                1.0
+                            } else {
+                                ((point[axis] - anchor[axis]) / span).max(MIN_SCALE)
+                            }
+                        };
+                        Transform::scale_about(anchor, [factor(0), factor(1)])
+                    }
+                    Handle::Rotate { pivot } => {
+                        let angle = (point[1] - pivot[1]).atan2(point[0] - pivot[0])
+                            - (start[1] - pivot[1]).atan2(start[0] - pivot[0]);
+                        Transform::rotate_about(pivot, angle)
+                    }
+                };
+
+                for original in originals.iter() {
+                    if let Some(action) = self.scene.get_mut(&original.id) {
+                        action.action_type = original.action_type.clone();
+                        geometry::transform_action(&mut action.action_type, transform, board_size);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Finishes a selection drag: selects what a rubber band or lasso encloses,
+    /// or shares the transformed actions with the room.
+    pub(crate) fn end_select(&mut self) {
+        let Some(selection) = self.selection.as_mut() else {
+            return;
+        };
+        let mode = selection.mode;
+
+        match selection.gesture.take() {
+            Some(Gesture::Region(points)) => {
+                let region = match (mode, points.as_slice()) {
+                    (SelectionMode::Box, [start, .., end]) => corners(&Rect {
+                        x: start[0].min(end[0]),
+                        y: start[1].min(end[1]),
+                        width: (end[0] - start[0]).abs(),
+                        height: (end[1] - start[1]).abs(),
+                    })
+                    .to_vec(),
+                    (SelectionMode::Lasso, _) => points,
+                    _ => return,
+                };
+                if geometry::bounds(region.iter().copied())
+                    .is_none_or(|bounds| bounds.width < MIN_REGION && bounds.height < MIN_REGION)
+                {
+                    return;
+                }
+
+                let board_size = self.board_size();
+                let enclosed: Vec<Uuid> = self
+                    .scene
+                    .committed()
+                    .filter(|action| {
+                        let outline = geometry::outline_points(
+                            action,
+                            board_size,
+                            &self.camera,
+                            &mut self.font_system,
+                        );
+                        !outline.is_empty()
+                            && outline
+                                .iter()
+                                .all(|point| geometry::polygon_contains(&region, *point))
+                    })
+                    .map(|action| action.id)
+                    .collect();
+
+                if let Some(selection) = self.selection.as_mut() {
+                    for id in enclosed {
+                        if !selection.ids.contains(&id) {
+                            selection.ids.push(id);
+                        }
+                    }
+                }
+            }
+            Some(Gesture::Transform {
+                originals,
+                transform,
+                ..
+            }) if transform != Transform::default() => {
+                for original in &originals {
+                    self.send_selection_update(&original.id);
+                }
+                self.redo_stack.clear();
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves the selection with the arrow keys, further with Shift held.
+    /// Returns whether `key` was used.
+    pub(crate) fn nudge_selection(&mut self, key: &Key) -> bool {
+        let step = if self.pressed_keys.contains(&Key::Shift) {
+            LARGE_NUDGE_STEP
+        } else {
+            NUDGE_STEP
+        };
+        let offset = match key {
+            Key::ArrowLeft => [-step, 0.0],
+            Key::ArrowRight => [step, 0.0],
+            Key::ArrowUp => [0.0, -step],
+            Key::ArrowDown => [0.0, step],
+            _ => return false,
+        };
+        let ids = match &self.selection {
+            Some(selection) if !selection.ids.is_empty() => selection.ids.clone(),
+            _ => return false,
+        };
+
+        let board_size = self.board_size();
+        let transform = Transform::translate(offset);
+        for id in &ids {
+            if let Some(action) = self.scene.get_mut(id) {
+                geometry::transform_action(&mut action.action_type, &transform, board_size);
+            }
+            self.send_selection_update(id);
+        }
+        self.redo_stack.clear();
+        self.window.request_redraw();
+        true
+    }
+
+    /// Shares the new version of a transformed action. Actions the room has
+    /// not seen yet go out with the next update anyway.
+    fn send_selection_update(&self, id: &Uuid) {
+        if let Some(action) = self
+            .scene
+            .get(id)
+            .filter(|action| self.events_id.contains(&action.id))
+        {
+            self.send_action_update(action);
+        }
+    }
+
+    /// Lines drawn over the board for the selection tool: the rubber band or
+    /// lasso being dragged, and the box and handles of the selection.
+    pub(crate) fn selection_overlay(&mut self) -> Vec<Vertex> {
+        let board_size = self.board_size();
+        let mut lines: Vec<[f32; 2]> = Vec::new();
+
+        let Some(selection) = &self.selection else {
+            return Vec::new();
+        };
+        let mut dragged = None;
+        match &selection.gesture {
+            Some(Gesture::Region(points)) => {
+                let outline = match (selection.mode, points.as_slice()) {
+                    (SelectionMode::Box, [start, .., end]) => {
+                        vec![*start, [end[0], start[1]], *end, [start[0], end[1]]]
+                    }
+                    _ => points.clone(),
+                };
+                for index in 0..outline.len() {
+                    lines.push(outline[index]);
+                    lines.push(outline[(index + 1) % outline.len()]);
+                }
+            }
+            Some(Gesture::Transform {
+                bounds, transform, ..
+            }) => dragged = Some((*bounds, *transform)),
+            None => {}
+        }
+
+        // While a transform is dragged the box follows it, turning with it.
+        let (bounds, transform) = match dragged {
+            Some((bounds, transform)) => (Some(bounds), transform),
+            None => (self.selection_bounds(), Transform::default()),
+        };
+
+        if let Some(bounds) = bounds {
+            let corners = corners(&bounds).map(|corner| transform.apply(corner));
+            for index in 0..4 {
+                lines.push(corners[index]);
+                lines.push(corners[(index + 1) % 4]);
+            }
+
+            let top = transform.apply([bounds.x + bounds.width / 2.0, bounds.y]);
+            let rotate = transform.apply(rotate_handle(&bounds));
+            lines.push(top);
+            lines.push(rotate);
+
+            for center in corners.into_iter().chain([rotate]) {
+                let square = [
+                    [center[0] - HANDLE_SIZE, center[1] - HANDLE_SIZE],
+                    [center[0] + HANDLE_SIZE, center[1] - HANDLE_SIZE],
+                    [center[0] + HANDLE_SIZE, center[1] + HANDLE_SIZE],
+                    [center[0] - HANDLE_SIZE, center[1] + HANDLE_SIZE],
+                ];
+                for index in 0..4 {
+                    lines.push(square[index]);
+                    lines.push(square[(index + 1) % 4]);
+                }
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|point| Vertex {
+                position: export::board_to_ndc(point, board_size),
+                color: OVERLAY_COLOR,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{ActionType, Rectangle};
+    use std::f32::consts::FRAC_PI_2;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+    const BOUNDS: Rect = Rect {
+        x: 100.0,
+        y: 100.0,
+        width: 200.0,
+        height: 100.0,
+    };
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        vector::distance(a, b) < 1e-3
+    }
+
+    fn rectangle(first: [f32; 2], last: [f32; 2]) -> ActionType {
+        ActionType::Shapes(Rectangle {
+            first: export::board_to_ndc(first, BOARD_SIZE),
+            last: export::board_to_ndc(last, BOARD_SIZE),
+            color: [0.0, 0.0, 0.0, 1.0],
+            fill: [0.0; 4],
+            outline_width: 2.0,
+            corner_radius: 0.0,
+        })
+    }
+
+    #[test]
+    fn picks_the_handle_under_the_pointer() {
+        assert!(matches!(
+            handle_at(&BOUNDS, [202.0, 78.0], true),
+            Some(Handle::Rotate { pivot }) if close(pivot, [200.0, 150.0])
+        ));
+        assert!(matches!(
+            handle_at(&BOUNDS, [296.0, 203.0], true),
+            Some(Handle::Scale { anchor, corner })
+                if close(anchor, [100.0, 100.0]) && close(corner, [300.0, 200.0])
+        ));
+        assert!(matches!(
+            handle_at(&BOUNDS, [150.0, 150.0], true),
+            Some(Handle::Move)
+        ));
+        assert!(handle_at(&BOUNDS, [150.0, 150.0], false).is_none());
+        assert!(handle_at(&BOUNDS, [50.0, 150.0], true).is_none());
+    }
+
+    #[test]
+    fn scales_from_the_opposite_corner() {
+        let mut action_type = rectangle([100.0, 100.0], [300.0, 200.0]);
+        let transform = Transform::scale_about([100.0, 100.0], [2.0, 2.0]);
+        geometry::transform_action(&mut action_type, &transform, BOARD_SIZE);
+        let ActionType::Shapes(scaled) = action_type else {
+            panic!("{:?}", action_type);
+        };
+        assert!(close(
+            export::ndc_to_board(scaled.first, BOARD_SIZE),
+            [100.0, 100.0]
+        ));
+        assert!(close(
+            export::ndc_to_board(scaled.last, BOARD_SIZE),
+            [500.0, 300.0]
+        ));
+        assert_eq!(scaled.outline_width, 4.0);
+    }
+
+    #[test]
+    fn moves_without_changing_size() {
+        let mut action_type = rectangle([100.0, 100.0], [300.0, 200.0]);
+        let transform = Transform::translate([10.0, -20.0]);
+        geometry::transform_action(&mut action_type, &transform, BOARD_SIZE);
+        let ActionType::Shapes(moved) = action_type else {
+            panic!("{:?}", action_type);
+        };
+        assert!(close(
+            export::ndc_to_board(moved.first, BOARD_SIZE),
+            [110.0, 80.0]
+        ));
+        assert!(close(
+            export::ndc_to_board(moved.last, BOARD_SIZE),
+            [310.0, 180.0]
+        ));
+        assert_eq!(moved.outline_width, 2.0);
+    }
+
+    #[test]
+    fn turns_a_rectangle_into_a_polygon() {
+        let mut action_type = rectangle([100.0, 100.0], [300.0, 200.0]);
+        let transform = Transform::rotate_about([200.0, 150.0], FRAC_PI_2);
+        geometry::transform_action(&mut action_type, &transform, BOARD_SIZE);
+        let ActionType::Polygon(polygon) = action_type else {
+            panic!("{:?}", action_type);
+        };
+        assert_eq!(polygon.points.len(), 4);
+        let turned = geometry::bounds(
+            polygon
+                .points
+                .iter()
+                .map(|point| export::ndc_to_board(*point, BOARD_SIZE)),
+        )
+        .unwrap();
+        assert!(close([turned.x, turned.y], [150.0, 50.0]));
+        assert!(close([turned.width, turned.height], [100.0, 200.0]));
+        assert_eq!(polygon.outline_width, 2.0);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{board_file::BoardMetadata, WindowState};
+
+/// Which board a window takes part in, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// The room events are exchanged in. Everyone in a class joins the same
+    /// one.
+    pub room: String,
+    /// Title the board is saved under; the room when `None`.
+    pub title: Option<String>,
+    /// Turn to the pages others turn to, as students following the teacher
+    /// do.
+    pub follow_pages: bool,
+}
+
+impl Session {
+    pub fn new(room: impl Into<String>) -> Self {
+        Session {
+            room: room.into(),
+            title: None,
+            follow_pages: false,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.room)
+    }
+}
+
+impl WindowState {
+    /// Takes part in `session`. Joining another room clears the local board
+    /// and subscribes to the new room, which asks the others for theirs.
+    pub fn join(&mut self, session: Session) {
+        self.follow_pages = session.follow_pages;
+        let title = session.title().to_owned();
+        if self.room == session.room {
+            self.metadata.title = title;
+            return;
+        }
+
+        self.room = session.room;
+        self.clear_board();
+        self.metadata = BoardMetadata::new(title);
+        self.subscribe();
+        self.window.request_redraw();
+    }
+
+    /// Switches the board to another room, keeping the title and page
+    /// following of the current session.
+    pub fn set_room(&mut self, room: String) {
+        self.join(Session {
+            room,
+            title: Some(self.metadata.title.clone()),
+            follow_pages: self.follow_pages,
+        });
+    }
+}
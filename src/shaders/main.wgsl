@@ -5,6 +5,14 @@ struct VertexInput {
     @location(1) color: vec4<f32>,    // RGBA color
 };
 
+// Camera transform from board NDC to clip space
+struct Camera {
+    transform: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
 // Vertex Output Structure
 struct VertexOutput {
     @builtin(position) position: vec4<f32>, // Transformed position
@@ -16,8 +24,8 @@ struct VertexOutput {
 fn vs_main(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
     
-    // Transform 2D position to 4D clip space
-    output.position = vec4<f32>(input.position, 0.0, 1.0);
+    // Transform 2D position to 4D clip space through the camera
+    output.position = camera.transform * vec4<f32>(input.position, 0.0, 1.0);
     
     // Pass color to fragment shader
     output.color = input.color;
@@ -0,0 +1,141 @@
+// paper.wgsl
+
+struct VertexInput {
+    @location(0) position: vec2<f32>, // Board NDC
+    @location(1) color: vec4<f32>,    // Paper color
+};
+
+// Camera transform from board NDC to clip space
+struct Camera {
+    transform: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// Board size in board pixels and the kind of paper to draw
+struct Paper {
+    board_size: vec2<f32>,
+    kind: u32,
+};
+
+@group(1) @binding(0)
+var<uniform> paper: Paper;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) board: vec2<f32>, // Board NDC, interpolated per fragment
+};
+
+const SQUARE_GRID: u32 = 1u;
+const DOT_GRID: u32 = 2u;
+const RULED: u32 = 3u;
+const GRAPH: u32 = 4u;
+const CALLIGRAPHY: u32 = 5u;
+
+// Plain paper is any other kind; it only has its color.
+
+// Spacings and widths in board pixels
+const GRID_SPACING: f32 = 40.0;
+const DOT_RADIUS: f32 = 2.0;
+const RULED_SPACING: f32 = 48.0;
+const RULED_TOP: f32 = 96.0;
+const RULED_MARGIN: f32 = 120.0;
+const GRAPH_MINOR: f32 = 16.0;
+const GRAPH_MAJOR: f32 = 80.0;
+const LINE_WIDTH: f32 = 1.0;
+// One nuqta, the rhombic dot Persian letters are measured in
+const NUQTA: f32 = 14.0;
+const CALLIGRAPHY_ROW: f32 = 180.0;
+const CALLIGRAPHY_BASELINE: f32 = 120.0;
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = camera.transform * vec4<f32>(input.position, 0.0, 1.0);
+    output.color = input.color;
+    output.board = input.position;
+    return output;
+}
+
+// Coverage of a line `width` board pixels wide at `distance` from its center,
+// never thinner than a screen pixel so it stays visible when zoomed out.
+fn line_coverage(distance: f32, width: f32, pixel: f32) -> f32 {
+    let half = max(width, pixel) * 0.5;
+    return 1.0 - smoothstep(half - pixel * 0.5, half + pixel * 0.5, distance);
+}
+
+// Distance to the nearest of the lines every `spacing` board pixels.
+fn line_distance(value: f32, spacing: f32) -> f32 {
+    let offset = value - spacing * round(value / spacing);
+    return abs(offset);
+}
+
+// Coverage of lines every `spacing` board pixels along both axes.
+fn grid(point: vec2<f32>, spacing: f32, width: f32, pixel: vec2<f32>) -> f32 {
+    return max(
+        line_coverage(line_distance(point.x, spacing), width, pixel.x),
+        line_coverage(line_distance(point.y, spacing), width, pixel.y),
+    );
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    // Board pixels from the top left corner, like everything else on the board
+    let point = vec2<f32>(
+        (input.board.x + 1.0) * 0.5 * paper.board_size.x,
+        (1.0 - input.board.y) * 0.5 * paper.board_size.y,
+    );
+    // Board pixels per screen pixel, for antialiasing at any zoom and DPI
+    let pixel = max(fwidth(point), vec2<f32>(1e-4));
+
+    // Guides are a quiet blue on light paper and a quiet white on dark paper
+    let luminance = dot(input.color.rgb, vec3<f32>(0.299, 0.587, 0.114));
+    var guide = vec3<f32>(0.45, 0.6, 0.85);
+    if luminance < 0.5 {
+        guide = vec3<f32>(0.9, 0.9, 0.9);
+    }
+    let margin = vec3<f32>(0.9, 0.35, 0.35);
+
+    var color = input.color.rgb;
+    switch paper.kind {
+        case SQUARE_GRID: {
+            color = mix(color, guide, 0.5 * grid(point, GRID_SPACING, LINE_WIDTH, pixel));
+        }
+        case DOT_GRID: {
+            let offset = point - GRID_SPACING * round(point / GRID_SPACING);
+            let radius = max(DOT_RADIUS, max(pixel.x, pixel.y));
+            let spot = 1.0 - smoothstep(radius - pixel.x, radius + pixel.x, length(offset));
+            color = mix(color, guide, 0.8 * spot);
+        }
+        case RULED: {
+            var rule = 0.0;
+            if point.y > RULED_TOP - RULED_SPACING * 0.5 {
+                let distance = line_distance(point.y - RULED_TOP, RULED_SPACING);
+                rule = line_coverage(distance, LINE_WIDTH, pixel.y);
+            }
+            color = mix(color, guide, 0.6 * rule);
+            let edge = line_coverage(abs(point.x - RULED_MARGIN), LINE_WIDTH * 1.5, pixel.x);
+            color = mix(color, margin, 0.7 * edge);
+        }
+        case GRAPH: {
+            color = mix(color, guide, 0.3 * grid(point, GRAPH_MINOR, LINE_WIDTH * 0.75, pixel));
+            color = mix(color, guide, 0.6 * grid(point, GRAPH_MAJOR, LINE_WIDTH * 1.5, pixel));
+        }
+        case CALLIGRAPHY: {
+            // Each row has a baseline, a guide five nuqtas above it for the
+            // height of tall letters and one three nuqtas below for descenders.
+            let row = point.y - CALLIGRAPHY_ROW * floor(point.y / CALLIGRAPHY_ROW);
+            let baseline = row - CALLIGRAPHY_BASELINE;
+            let top = line_coverage(abs(baseline + 5.0 * NUQTA), LINE_WIDTH, pixel.y);
+            let bottom = line_coverage(abs(baseline - 3.0 * NUQTA), LINE_WIDTH, pixel.y);
+            color = mix(color, guide, 0.4 * max(top, bottom));
+            let line = line_coverage(abs(baseline), LINE_WIDTH * 2.0, pixel.y);
+            color = mix(color, guide, 0.8 * line);
+        }
+        default: {}
+    }
+
+    return vec4<f32>(color, input.color.a);
+}
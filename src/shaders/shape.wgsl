@@ -9,6 +9,14 @@ struct VertexOutput {
     @location(0) color: vec4<f32>,          // Passed color
 };
 
+// Camera transform from board NDC to clip space
+struct Camera {
+    transform: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
 // ==================== TRIANGLE SHADER ====================
 
 // Vertex Shader for Triangle
@@ -16,8 +24,8 @@ struct VertexOutput {
 fn triangle_vs(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
 
-    // Pass-through vertex position and color
-    output.position = vec4<f32>(input.position, 0.0, 1.0);
+    // Place the vertex through the camera and pass the color
+    output.position = camera.transform * vec4<f32>(input.position, 0.0, 1.0);
     output.color = input.color;
 
     return output;
@@ -28,8 +36,8 @@ fn triangle_vs(input: VertexInput) -> VertexOutput {
 fn rectangle_vs(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
 
-    // Pass the position to clip space
-    output.position = vec4<f32>(input.position, 0.0, 1.0);
+    // Place the position through the camera
+    output.position = camera.transform * vec4<f32>(input.position, 0.0, 1.0);
 
     // Pass the color to the fragment shader
     output.color = input.color;
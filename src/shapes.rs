@@ -0,0 +1,483 @@
+use crate::{
+    export,
+    structs::{ActionType, ArrowHeads, Ellipse, Line, Polygon, Rectangle, ShapeStyle, Vertex},
+    vector::distance,
+    WindowState,
+};
+use dioxus::desktop::tao::{dpi::PhysicalPosition, keyboard::Key};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// Distance in board pixels from the first corner of a free polygon within
+/// which a click closes it.
+const CLOSE_DISTANCE: f32 = 8.0;
+/// Length of an arrow head per pixel of line width, and its shortest length
+/// in board pixels.
+const ARROW_HEAD_LENGTH: f32 = 4.0;
+const MIN_ARROW_HEAD: f32 = 10.0;
+/// Range of corners a regular polygon can have.
+pub(crate) const MIN_SIDES: usize = 3;
+pub(crate) const MAX_SIDES: usize = 12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShapeKind {
+    #[default]
+    Rectangle,
+    /// An ellipse, or a circle with Shift held.
+    Ellipse,
+    Line,
+    Arrow,
+    DoubleArrow,
+    Triangle,
+    RegularPolygon,
+    /// Placed corner by corner, one click each.
+    FreePolygon,
+}
+
+impl ShapeKind {
+    pub const ALL: [ShapeKind; 8] = [
+        ShapeKind::Rectangle,
+        ShapeKind::Ellipse,
+        ShapeKind::Line,
+        ShapeKind::Arrow,
+        ShapeKind::DoubleArrow,
+        ShapeKind::Triangle,
+        ShapeKind::RegularPolygon,
+        ShapeKind::FreePolygon,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ShapeKind::Rectangle => "Rectangle",
+            ShapeKind::Ellipse => "Ellipse",
+            ShapeKind::Line => "Line",
+            ShapeKind::Arrow => "Arrow",
+            ShapeKind::DoubleArrow => "Double arrow",
+            ShapeKind::Triangle => "Triangle",
+            ShapeKind::RegularPolygon => "Polygon",
+            ShapeKind::FreePolygon => "Free polygon",
+        }
+    }
+
+    fn is_line(self) -> bool {
+        matches!(
+            self,
+            ShapeKind::Line | ShapeKind::Arrow | ShapeKind::DoubleArrow
+        )
+    }
+}
+
+/// Builds a shape of `kind` dragged from `first` to `last`, in board pixels,
+/// with the pen `color` as its outline.
+pub(crate) fn shape_between(
+    kind: ShapeKind,
+    first: [f32; 2],
+    last: [f32; 2],
+    color: [f32; 4],
+    style: &ShapeStyle,
+    board_size: [f32; 2],
+) -> ActionType {
+    let ndc = |point: [f32; 2]| export::board_to_ndc(point, board_size);
+    let line = |heads: ArrowHeads| {
+        ActionType::Line(Line {
+            first: ndc(first),
+            last: ndc(last),
+            color,
+            width: style.outline_width.max(1.0),
+            heads,
+        })
+    };
+    let polygon = |points: Vec<[f32; 2]>| {
+        ActionType::Polygon(Polygon {
+            points: points.into_iter().map(ndc).collect(),
+            color,
+            fill: style.fill,
+            outline_width: style.outline_width,
+        })
+    };
+
+    let center = [(first[0] + last[0]) / 2.0, (first[1] + last[1]) / 2.0];
+    let radii = [
+        (last[0] - first[0]).abs() / 2.0,
+        (last[1] - first[1]).abs() / 2.0,
+    ];
+    match kind {
+        ShapeKind::Rectangle => ActionType::Shapes(Rectangle {
+            first: ndc(first),
+            last: ndc(last),
+            color,
+            fill: style.fill,
+            outline_width: style.outline_width,
+            corner_radius: style.corner_radius,
+        }),
+        ShapeKind::Ellipse => ActionType::Ellipse(Ellipse {
+            first: ndc(first),
+            last: ndc(last),
+            color,
+            fill: style.fill,
+            outline_width: style.outline_width,
+        }),
+        ShapeKind::Line => line(ArrowHeads::None),
+        ShapeKind::Arrow => line(ArrowHeads::End),
+        ShapeKind::DoubleArrow => line(ArrowHeads::Both),
+        ShapeKind::Triangle => polygon(vec![
+            [center[0], center[1] - radii[1]],
+            [center[0] + radii[0], center[1] + radii[1]],
+            [center[0] - radii[0], center[1] + radii[1]],
+        ]),
+        ShapeKind::RegularPolygon => {
+            // The first corner points up.
+            let sides = style.sides.clamp(MIN_SIDES, MAX_SIDES);
+            polygon(
+                (0..sides)
+                    .map(|corner| {
+                        let angle = corner as f32 / sides as f32 * 2.0 * PI - FRAC_PI_2;
+                        [
+                            center[0] + radii[0] * angle.cos(),
+                            center[1] + radii[1] * angle.sin(),
+                        ]
+                    })
+                    .collect(),
+            )
+        }
+        ShapeKind::FreePolygon => polygon(vec![first, last]),
+    }
+}
+
+/// Where a drag from `first` to `point` ends with Shift held: lines snap to
+/// steps of 45° and other shapes to a square box.
+fn constrain(kind: ShapeKind, first: [f32; 2], point: [f32; 2]) -> [f32; 2] {
+    let [dx, dy] = [point[0] - first[0], point[1] - first[1]];
+    if kind.is_line() {
+        let angle = (dy.atan2(dx) / FRAC_PI_4).round() * FRAC_PI_4;
+        let length = dx.hypot(dy);
+        [
+            first[0] + length * angle.cos(),
+            first[1] + length * angle.sin(),
+        ]
+    } else {
+        let side = dx.abs().max(dy.abs());
+        [first[0] + side.copysign(dx), first[1] + side.copysign(dy)]
+    }
+}
+
+/// Points around an ellipse in board pixels, starting on the right and
+/// clockwise on screen.
+pub(crate) fn ellipse_points(center: [f32; 2], radii: [f32; 2], steps: usize) -> Vec<[f32; 2]> {
+    (0..steps)
+        .map(|step| {
+            let angle = step as f32 / steps as f32 * 2.0 * PI;
+            [
+                center[0] + radii[0] * angle.cos(),
+                center[1] + radii[1] * angle.sin(),
+            ]
+        })
+        .collect()
+}
+
+/// Center and radii of `ellipse` in board pixels.
+pub(crate) fn ellipse_box(ellipse: &Ellipse, board_size: [f32; 2]) -> ([f32; 2], [f32; 2]) {
+    let first = export::ndc_to_board(ellipse.first, board_size);
+    let last = export::ndc_to_board(ellipse.last, board_size);
+    (
+        [(first[0] + last[0]) / 2.0, (first[1] + last[1]) / 2.0],
+        [
+            (last[0] - first[0]).abs() / 2.0,
+            (last[1] - first[1]).abs() / 2.0,
+        ],
+    )
+}
+
+/// The ends of `line` in board pixels, pulled back to the base of its arrow
+/// heads, and the heads as `[tip, corner, corner]` triangles.
+pub(crate) fn line_geometry(
+    line: &Line,
+    board_size: [f32; 2],
+) -> ([[f32; 2]; 2], Vec<[[f32; 2]; 3]>) {
+    let first = export::ndc_to_board(line.first, board_size);
+    let last = export::ndc_to_board(line.last, board_size);
+    let length = distance(first, last);
+    let head_count = match line.heads {
+        ArrowHeads::None => 0,
+        ArrowHeads::End => 1,
+        ArrowHeads::Both => 2,
+    };
+    if head_count == 0 || length == 0.0 {
+        return ([first, last], Vec::new());
+    }
+
+    let direction = [(last[0] - first[0]) / length, (last[1] - first[1]) / length];
+    let head_length = (line.width * ARROW_HEAD_LENGTH)
+        .max(MIN_ARROW_HEAD)
+        .min(length / head_count as f32);
+    let head = |tip: [f32; 2], sign: f32| {
+        let [dx, dy] = [direction[0] * sign, direction[1] * sign];
+        let base = [tip[0] - dx * head_length, tip[1] - dy * head_length];
+        let half = head_length / 2.0;
+        let triangle = [
+            tip,
+            [base[0] - dy * half, base[1] + dx * half],
+            [base[0] + dy * half, base[1] - dx * half],
+        ];
+        (base, triangle)
+    };
+
+    let (end, end_head) = head(last, 1.0);
+    let mut heads = vec![end_head];
+    let mut start = first;
+    if line.heads == ArrowHeads::Both {
+        let (base, start_head) = head(first, -1.0);
+        start = base;
+        heads.push(start_head);
+    }
+    ([start, end], heads)
+}
+
+impl WindowState {
+    /// Starts a shape under `position`. Free polygons instead get a corner on
+    /// every click and are closed by clicking their first corner again.
+    pub(crate) fn press_shape(&mut self, position: PhysicalPosition<f64>) {
+        let point = [position.x as f32, position.y as f32];
+        let board_size = self.board_size();
+        let vertex = Vertex {
+            position: export::board_to_ndc(point, board_size),
+            color: self.current_color,
+        };
+        if self.shape_kind != ShapeKind::FreePolygon {
+            self.shape_positions = vec![vertex];
+            return;
+        }
+
+        // The last corner follows the pointer until the next click fixes it.
+        self.shape_positions.pop();
+        let closes = self.shape_positions.len() >= 3
+            && self.shape_positions.first().is_some_and(|first| {
+                distance(export::ndc_to_board(first.position, board_size), point) <= CLOSE_DISTANCE
+            });
+        if closes {
+            self.close_polygon();
+        } else {
+            self.shape_positions.extend([vertex, vertex]);
+        }
+    }
+
+    /// Moves the corner being placed to `position`.
+    pub(crate) fn drag_shape(&mut self, position: PhysicalPosition<f64>) {
+        let Some(first) = self.shape_positions.first() else {
+            return;
+        };
+        let board_size = self.board_size();
+        let mut point = [position.x as f32, position.y as f32];
+        if self.shape_kind != ShapeKind::FreePolygon && self.pressed_keys.contains(&Key::Shift) {
+            let first = export::ndc_to_board(first.position, board_size);
+            point = constrain(self.shape_kind, first, point);
+        }
+
+        if self.shape_positions.len() > 1 {
+            self.shape_positions.pop();
+        }
+        self.shape_positions.push(Vertex {
+            position: export::board_to_ndc(point, board_size),
+            color: self.current_color,
+        });
+    }
+
+    /// Adds the dragged shape to the board. Free polygons carry on until
+    /// they are closed.
+    pub(crate) fn finish_shape(&mut self) {
+        if self.shape_kind == ShapeKind::FreePolygon {
+            return;
+        }
+        if let Some(action_type) = self.shape_preview() {
+            self.add_action(action_type);
+        }
+        self.create_rect = false;
+        self.shape_positions.clear();
+    }
+
+    /// Enter closes the free polygon being placed and Escape drops it.
+    /// Returns whether the key was used.
+    pub(crate) fn polygon_key(&mut self, key: &Key) -> bool {
+        if !self.create_rect
+            || self.shape_kind != ShapeKind::FreePolygon
+            || self.shape_positions.is_empty()
+        {
+            return false;
+        }
+        match key {
+            Key::Enter => {
+                self.shape_positions.pop();
+                self.close_polygon();
+            }
+            Key::Escape => {
+                self.shape_positions.clear();
+                self.create_rect = false;
+            }
+            _ => return false,
+        }
+        self.window.request_redraw();
+        true
+    }
+
+    /// The shape being drawn, as it would be added to the board.
+    pub(crate) fn shape_preview(&self) -> Option<ActionType> {
+        if !self.create_rect {
+            return None;
+        }
+        let board_size = self.board_size();
+        match (self.shape_kind, self.shape_positions.as_slice()) {
+            (ShapeKind::FreePolygon, positions @ [_, _, ..]) => {
+                Some(ActionType::Polygon(Polygon {
+                    points: positions.iter().map(|vertex| vertex.position).collect(),
+                    color: self.current_color,
+                    fill: self.shape_style.fill,
+                    outline_width: self.shape_style.outline_width,
+                }))
+            }
+            (kind, [first, .., last]) => Some(shape_between(
+                kind,
+                export::ndc_to_board(first.position, board_size),
+                export::ndc_to_board(last.position, board_size),
+                self.current_color,
+                &self.shape_style,
+                board_size,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Adds the free polygon placed so far, if it has at least three corners.
+    fn close_polygon(&mut self) {
+        let points: Vec<[f32; 2]> = std::mem::take(&mut self.shape_positions)
+            .into_iter()
+            .map(|vertex| vertex.position)
+            .collect();
+        self.create_rect = false;
+        if points.len() >= 3 {
+            self.add_action(ActionType::Polygon(Polygon {
+                points,
+                color: self.current_color,
+                fill: self.shape_style.fill,
+                outline_width: self.shape_style.outline_width,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+    const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        distance(a, b) < 1e-3
+    }
+
+    fn corners(action_type: ActionType) -> Vec<[f32; 2]> {
+        let ActionType::Polygon(polygon) = action_type else {
+            panic!("{:?}", action_type);
+        };
+        polygon
+            .points
+            .into_iter()
+            .map(|point| export::ndc_to_board(point, BOARD_SIZE))
+            .collect()
+    }
+
+    fn line(heads: ArrowHeads, width: f32, length: f32) -> Line {
+        Line {
+            first: export::board_to_ndc([100.0, 100.0], BOARD_SIZE),
+            last: export::board_to_ndc([100.0 + length, 100.0], BOARD_SIZE),
+            color: BLACK,
+            width,
+            heads,
+        }
+    }
+
+    #[test]
+    fn fits_a_triangle_in_the_dragged_box() {
+        let triangle = shape_between(
+            ShapeKind::Triangle,
+            [100.0, 100.0],
+            [200.0, 300.0],
+            BLACK,
+            &ShapeStyle::default(),
+            BOARD_SIZE,
+        );
+        let points = corners(triangle);
+        assert!(close(points[0], [150.0, 100.0]));
+        assert!(close(points[1], [200.0, 300.0]));
+        assert!(close(points[2], [100.0, 300.0]));
+    }
+
+    #[test]
+    fn points_the_first_corner_of_regular_polygons_up() {
+        let style = ShapeStyle {
+            sides: 6,
+            ..ShapeStyle::default()
+        };
+        let hexagon = shape_between(
+            ShapeKind::RegularPolygon,
+            [100.0, 100.0],
+            [300.0, 300.0],
+            BLACK,
+            &style,
+            BOARD_SIZE,
+        );
+        let points = corners(hexagon);
+        assert_eq!(points.len(), 6);
+        assert!(close(points[0], [200.0, 100.0]));
+        assert!(points
+            .iter()
+            .all(|point| (distance(*point, [200.0, 200.0]) - 100.0).abs() < 1e-3));
+
+        let style = ShapeStyle { sides: 40, ..style };
+        let most = shape_between(
+            ShapeKind::RegularPolygon,
+            [100.0, 100.0],
+            [300.0, 300.0],
+            BLACK,
+            &style,
+            BOARD_SIZE,
+        );
+        assert_eq!(corners(most).len(), MAX_SIDES);
+    }
+
+    #[test]
+    fn constrains_lines_to_45_degrees_and_boxes_to_squares() {
+        let snapped = constrain(ShapeKind::Line, [0.0, 0.0], [100.0, 90.0]);
+        assert!(close(snapped, [100.0f32.hypot(90.0) / 2.0f32.sqrt(); 2]));
+        let flat = constrain(ShapeKind::Arrow, [0.0, 0.0], [-100.0, 10.0]);
+        assert!(close(flat, [-100.0f32.hypot(10.0), 0.0]));
+
+        let square = constrain(ShapeKind::Rectangle, [50.0, 50.0], [10.0, 80.0]);
+        assert!(close(square, [10.0, 90.0]));
+    }
+
+    #[test]
+    fn pulls_line_ends_back_to_their_arrow_heads() {
+        let (ends, heads) = line_geometry(&line(ArrowHeads::None, 2.0, 100.0), BOARD_SIZE);
+        assert!(close(ends[1], [200.0, 100.0]));
+        assert!(heads.is_empty());
+
+        // Heads are four times the width, and at least MIN_ARROW_HEAD long.
+        let (ends, heads) = line_geometry(&line(ArrowHeads::End, 2.0, 100.0), BOARD_SIZE);
+        assert!(close(ends[0], [100.0, 100.0]));
+        assert!(close(ends[1], [200.0 - MIN_ARROW_HEAD, 100.0]));
+        assert_eq!(heads.len(), 1);
+        assert!(close(heads[0][0], [200.0, 100.0]));
+
+        let (ends, heads) = line_geometry(&line(ArrowHeads::Both, 5.0, 100.0), BOARD_SIZE);
+        assert!(close(ends[0], [120.0, 100.0]));
+        assert!(close(ends[1], [180.0, 100.0]));
+        assert_eq!(heads.len(), 2);
+    }
+
+    #[test]
+    fn keeps_arrow_heads_within_short_lines() {
+        let (ends, _) = line_geometry(&line(ArrowHeads::Both, 5.0, 30.0), BOARD_SIZE);
+        assert!(close(ends[0], [115.0, 100.0]));
+        assert!(close(ends[1], [115.0, 100.0]));
+    }
+}
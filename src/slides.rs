@@ -0,0 +1,325 @@
+use crate::{
+    images::{self, ImageError, ImageStore, StoredImage, MAX_FILE_SIZE},
+    structs::Image,
+    sync, WindowState,
+};
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+};
+use stream::desktop::stream::paint_event::ActionType as StreamActionType;
+use uuid::Uuid;
+
+/// Extensions of the image files slides are imported from.
+const SLIDE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlideImport {
+    /// Slides picked one by one, imported in file name order.
+    Files,
+    /// Every image in a folder, in file name order.
+    Folder,
+}
+
+/// Compares file names the way slides are numbered: runs of digits compare
+/// by value, so `slide2.png` comes before `slide10.png`.
+pub(crate) fn natural_order(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(a_char), Some(b_char)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+            let a_end = a
+                .find(|char: char| !char.is_ascii_digit())
+                .unwrap_or(a.len());
+            let b_end = b
+                .find(|char: char| !char.is_ascii_digit())
+                .unwrap_or(b.len());
+            let (a_digits, b_digits) = (
+                a[..a_end].trim_start_matches('0'),
+                b[..b_end].trim_start_matches('0'),
+            );
+            let order = a_digits
+                .len()
+                .cmp(&b_digits.len())
+                .then_with(|| a_digits.cmp(b_digits));
+            if order != Ordering::Equal {
+                return order;
+            }
+            (a, b) = (&a[a_end..], &b[b_end..]);
+        } else {
+            let order = a_char.to_lowercase().cmp(b_char.to_lowercase());
+            if order != Ordering::Equal {
+                return order;
+            }
+            (a, b) = (&a[a_char.len_utf8()..], &b[b_char.len_utf8()..]);
+        }
+    }
+}
+
+fn sort_slides(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| {
+        let name = |path: &PathBuf| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        };
+        natural_order(&name(a), &name(b))
+    });
+}
+
+/// The image files in `folder` that can be slides, in slide order.
+pub fn slide_files(folder: impl AsRef<Path>) -> Result<Vec<PathBuf>, ImageError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(folder)
+        .map_err(ImageError::Io)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| {
+                let extension = extension.to_string_lossy().to_lowercase();
+                SLIDE_EXTENSIONS.contains(&extension.as_str())
+            })
+        })
+        .collect();
+    sort_slides(&mut paths);
+    Ok(paths)
+}
+
+/// Reads and decodes every slide file, or none of them: sizes are checked
+/// before any file is read. Images `known` already has aren't decoded again.
+fn read_slides(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    known: &ImageStore,
+) -> Result<Vec<(String, StoredImage)>, ImageError> {
+    let paths: Vec<_> = paths.into_iter().collect();
+    for path in &paths {
+        let size = std::fs::metadata(path).map_err(ImageError::Io)?.len() as usize;
+        if size > MAX_FILE_SIZE {
+            return Err(ImageError::TooLarge(size));
+        }
+    }
+
+    let mut slides = Vec::new();
+    for path in paths {
+        let bytes = std::fs::read(path).map_err(ImageError::Io)?;
+        let hash = images::content_hash(&bytes);
+        let image = match known.get(&hash) {
+            Some(stored) => stored.clone(),
+            None => images::decode(bytes).map_err(ImageError::Decode)?,
+        };
+        slides.push((hash, image));
+    }
+    Ok(slides)
+}
+
+/// A slide of `pixels` size centered on the board, as large as it fits
+/// without cropping.
+pub(crate) fn fit_to_board(hash: String, pixels: [u32; 2], board_size: [f32; 2]) -> Image {
+    let [width, height] = pixels.map(|side| side.max(1) as f32);
+    let scale = (board_size[0] / width).min(board_size[1] / height);
+    Image {
+        center: [0.0, 0.0],
+        size: [width * scale, height * scale],
+        rotation: 0.0,
+        hash,
+    }
+}
+
+impl WindowState {
+    /// Adds one page per image file at the end of the board, each with the
+    /// image as its slide, and turns to the first of them. A board that is
+    /// still blank takes the first slide on its only page. Nothing is added
+    /// when any of the files is too large or can't be read.
+    pub fn import_slides(
+        &mut self,
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> Result<(), ImageError> {
+        let decoded = read_slides(paths, &self.images)?;
+        if decoded.is_empty() {
+            return Ok(());
+        }
+
+        let mut slides = Vec::new();
+        for (hash, image) in decoded {
+            let pixels = image.pixels.dimensions();
+            self.images.insert_decoded(hash.clone(), image);
+            slides.push(fit_to_board(hash, pixels.into(), self.board_size()));
+        }
+
+        let blank = self.pages.len() == 1
+            && self.scene.iter().next().is_none()
+            && self.current_slide().is_none();
+        let hashes: Vec<String> = slides.iter().map(|slide| slide.hash.clone()).collect();
+        let mut requests = self.unshared_image_events(&hashes);
+        let mut first = None;
+        for (number, slide) in slides.into_iter().enumerate() {
+            let index = if number == 0 && blank {
+                self.current_page
+            } else {
+                self.insert_page(self.pages.len(), Uuid::new_v4())
+            };
+            let page = self.pages[index].id;
+            requests.push(sync::page_event(
+                &self.room,
+                page,
+                index,
+                StreamActionType::ActionRequest(true),
+            ));
+            requests.push(sync::slide_event(&self.room, page, &slide));
+            self.pages[index].slide = Some(slide);
+            first.get_or_insert(index);
+        }
+        // Announcing pages turns to them, so turn back to the current one
+        // before following to the first slide.
+        requests.push(sync::page_event(
+            &self.room,
+            self.current_page_id(),
+            self.current_page,
+            StreamActionType::ActionRequest(true),
+        ));
+        self.send_paint_events(requests);
+
+        if let Some(first) = first {
+            self.go_to_page(first);
+        }
+        self.window.request_redraw();
+        Ok(())
+    }
+
+    /// Imports every image in `folder` as slides, in file name order.
+    pub fn import_slide_folder(&mut self, folder: impl AsRef<Path>) -> Result<(), ImageError> {
+        self.import_slides(slide_files(folder)?)
+    }
+
+    /// Asks for slide files or a folder with the native file dialog and
+    /// imports them.
+    pub(crate) fn run_slide_import(&mut self, import: SlideImport) {
+        let dialog = rfd::FileDialog::new().add_filter("Slides", &SLIDE_EXTENSIONS);
+        let result = match import {
+            SlideImport::Files => match dialog.pick_files() {
+                Some(mut paths) => {
+                    sort_slides(&mut paths);
+                    self.import_slides(paths)
+                }
+                None => return,
+            },
+            SlideImport::Folder => match dialog.pick_folder() {
+                Some(folder) => self.import_slide_folder(folder),
+                None => return,
+            },
+        };
+
+        if let Err(e) = result {
+            eprintln!("{:?}", e);
+        }
+    }
+
+    /// Puts a slide received from the room under its page. A page the board
+    /// doesn't have yet is added at the end.
+    pub(crate) fn apply_slide_event(&mut self, page: Uuid, slide: Image) {
+        let index = match self.pages.iter().position(|known| known.id == page) {
+            Some(index) => index,
+            None => self.insert_page(self.pages.len(), page),
+        };
+        self.pages[index].slide = Some(slide);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_numbers_by_value() {
+        let mut names = vec![
+            "Slide10.png",
+            "slide2.png",
+            "slide1.png",
+            "slide02b.png",
+            "intro.png",
+            "slide2a.png",
+        ];
+        names.sort_by(|a, b| natural_order(a, b));
+        assert_eq!(
+            names,
+            [
+                "intro.png",
+                "slide1.png",
+                "slide2.png",
+                "slide2a.png",
+                "slide02b.png",
+                "Slide10.png",
+            ]
+        );
+    }
+
+    /// A fresh folder for the files of one test.
+    fn folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("slides-{}-{}", name, Uuid::new_v4()));
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn png(path: &Path) {
+        image::RgbaImage::new(4, 3).save(path).unwrap();
+    }
+
+    #[test]
+    fn reads_every_slide() {
+        let folder = folder("every");
+        png(&folder.join("1.png"));
+        png(&folder.join("2.png"));
+        let slides = read_slides(slide_files(&folder).unwrap(), &ImageStore::default()).unwrap();
+        assert_eq!(slides.len(), 2);
+        assert_eq!(slides[0].1.pixels.dimensions(), (4, 3));
+        assert_eq!(slides[0].0, images::content_hash(&slides[0].1.bytes));
+    }
+
+    #[test]
+    fn reads_no_slide_when_one_is_missing_or_broken() {
+        let folder = folder("broken");
+        png(&folder.join("1.png"));
+        std::fs::write(folder.join("2.png"), b"not a png").unwrap();
+        assert!(matches!(
+            read_slides(
+                [folder.join("1.png"), folder.join("3.png")],
+                &ImageStore::default()
+            ),
+            Err(ImageError::Io(_))
+        ));
+        assert!(matches!(
+            read_slides(slide_files(&folder).unwrap(), &ImageStore::default()),
+            Err(ImageError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_large_slides_before_reading_any() {
+        let folder = folder("large");
+        png(&folder.join("1.png"));
+        let large = std::fs::File::create(folder.join("2.png")).unwrap();
+        large.set_len(MAX_FILE_SIZE as u64 + 1).unwrap();
+        assert!(matches!(
+            read_slides(slide_files(&folder).unwrap(), &ImageStore::default()),
+            Err(ImageError::TooLarge(size)) if size == MAX_FILE_SIZE + 1
+        ));
+    }
+
+    #[test]
+    fn sorts_slide_files_by_name() {
+        let mut paths: Vec<PathBuf> = ["deck/page 11.jpg", "deck/page 9.jpg", "deck/page 10.png"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        sort_slides(&mut paths);
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("deck/page 9.jpg"),
+                PathBuf::from("deck/page 10.png"),
+                PathBuf::from("deck/page 11.jpg"),
+            ]
+        );
+    }
+}
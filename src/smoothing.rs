@@ -0,0 +1,201 @@
+use crate::structs::Polyline;
+use std::collections::VecDeque;
+
+/// Distance in pixels the pointer can move ahead of a lazy brush at full strength.
+const MAX_LAZY_RADIUS: f32 = 32.0;
+/// Number of samples averaged at full strength.
+const MAX_AVERAGE_WINDOW: usize = 16;
+/// Smoothing passes applied on release at full strength.
+const MAX_SMOOTHING_PASSES: usize = 6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StabilizerMode {
+    #[default]
+    Off,
+    /// The line follows the pointer on a string and only moves once the
+    /// pointer pulls it taut, which filters out hand tremor.
+    LazyBrush,
+    /// Each point is the average of the latest pointer samples.
+    MovingAverage,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StabilizerOptions {
+    pub mode: StabilizerMode,
+    /// How strongly the stabilizer and the release pass smooth, in `0.0..=1.0`.
+    pub strength: f32,
+    /// Smooths the whole stroke once more when it is released.
+    pub smooth_on_release: bool,
+}
+
+impl Default for StabilizerOptions {
+    fn default() -> Self {
+        StabilizerOptions {
+            mode: StabilizerMode::default(),
+            strength: 0.3,
+            smooth_on_release: false,
+        }
+    }
+}
+
+/// Filters pointer samples, in board pixels, while a stroke is drawn.
+#[derive(Clone, Debug, Default)]
+pub struct Stabilizer {
+    pub options: StabilizerOptions,
+    samples: VecDeque<[f32; 2]>,
+    brush: Option<[f32; 2]>,
+    /// The latest raw position and the latest point given to the stroke.
+    last: Option<([f32; 2], [f32; 2])>,
+}
+
+impl Stabilizer {
+    pub(crate) fn reset(&mut self) {
+        self.samples.clear();
+        self.brush = None;
+        self.last = None;
+    }
+
+    /// Takes a raw pointer position and returns where the stroke should get
+    /// its next point, or `None` when the line does not move.
+    pub(crate) fn push(&mut self, point: [f32; 2]) -> Option<[f32; 2]> {
+        let output = self.filter(point);
+        let given = output.or(self.last.map(|(_, given)| given));
+        self.last = given.map(|given| (point, given));
+        output
+    }
+
+    /// Where the pointer was released, when the stroke lags behind it. The
+    /// stroke ends there so it reaches what was drawn.
+    pub(crate) fn release(&mut self) -> Option<[f32; 2]> {
+        let (raw, given) = self.last.take()?;
+        (raw != given).then_some(raw)
+    }
+
+    fn filter(&mut self, point: [f32; 2]) -> Option<[f32; 2]> {
+        let strength = self.options.strength.clamp(0.0, 1.0);
+
+        match self.options.mode {
+            StabilizerMode::Off => Some(point),
+            StabilizerMode::LazyBrush => {
+                let Some(brush) = self.brush else {
+                    self.brush = Some(point);
+                    return Some(point);
+                };
+
+                let radius = strength * MAX_LAZY_RADIUS;
+                let [dx, dy] = [point[0] - brush[0], point[1] - brush[1]];
+                let distance = dx.hypot(dy);
+                if distance <= radius {
+                    return None;
+                }
+
+                let pull = (distance - radius) / distance;
+                let brush = [brush[0] + dx * pull, brush[1] + dy * pull];
+                self.brush = Some(brush);
+                Some(brush)
+            }
+            StabilizerMode::MovingAverage => {
+                let window = 1 + (strength * (MAX_AVERAGE_WINDOW - 1) as f32).round() as usize;
+                self.samples.push_back(point);
+                while self.samples.len() > window {
+                    self.samples.pop_front();
+                }
+
+                let count = self.samples.len() as f32;
+                let sum = self.samples.iter().fold([0.0, 0.0], |sum, sample| {
+                    [sum[0] + sample[0], sum[1] + sample[1]]
+                });
+                Some([sum[0] / count, sum[1] / count])
+            }
+        }
+    }
+}
+
+/// Relaxes every inner point of `polyline` toward its neighbours, along with
+/// its pressure. The ends stay where they were drawn.
+pub(crate) fn smooth_stroke(polyline: &mut Polyline, strength: f32) {
+    let passes = (strength.clamp(0.0, 1.0) * MAX_SMOOTHING_PASSES as f32).round() as usize;
+    let len = polyline.points.len();
+    if len < 3 {
+        return;
+    }
+
+    for _ in 0..passes {
+        let points = polyline.points.clone();
+        let pressure = polyline.pressure.clone();
+        for index in 1..len - 1 {
+            let [previous, point, next] = [points[index - 1], points[index], points[index + 1]];
+            polyline.points[index] = std::array::from_fn(|axis| {
+                let neighbours = (previous[axis] + next[axis]) / 2.0;
+                (point[axis] + neighbours) / 2.0
+            });
+            if pressure.len() == len {
+                let neighbours = (pressure[index - 1] + pressure[index + 1]) / 2.0;
+                polyline.pressure[index] = (pressure[index] + neighbours) / 2.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stabilizer(mode: StabilizerMode, strength: f32) -> Stabilizer {
+        Stabilizer {
+            options: StabilizerOptions {
+                mode,
+                strength,
+                smooth_on_release: false,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn is_off_by_default() {
+        let mut stabilizer = Stabilizer::default();
+        assert_eq!(stabilizer.options.mode, StabilizerMode::Off);
+        assert_eq!(stabilizer.push([1.0, 2.0]), Some([1.0, 2.0]));
+        assert_eq!(stabilizer.push([3.0, 4.0]), Some([3.0, 4.0]));
+        assert_eq!(stabilizer.release(), None);
+    }
+
+    #[test]
+    fn lazy_brush_waits_for_the_string_to_pull() {
+        let mut stabilizer = stabilizer(StabilizerMode::LazyBrush, 0.5);
+        assert_eq!(stabilizer.push([0.0, 0.0]), Some([0.0, 0.0]));
+        assert_eq!(stabilizer.push([10.0, 0.0]), None);
+        assert_eq!(stabilizer.push([26.0, 0.0]), Some([10.0, 0.0]));
+    }
+
+    #[test]
+    fn release_reaches_the_pointer() {
+        let mut stabilizer = stabilizer(StabilizerMode::LazyBrush, 0.5);
+        stabilizer.push([0.0, 0.0]);
+        stabilizer.push([30.0, 0.0]);
+        assert_eq!(stabilizer.release(), Some([30.0, 0.0]));
+        assert_eq!(stabilizer.release(), None);
+    }
+
+    #[test]
+    fn moving_average_trails_the_pointer() {
+        let mut stabilizer = stabilizer(StabilizerMode::MovingAverage, 0.0);
+        assert_eq!(stabilizer.push([4.0, 4.0]), Some([4.0, 4.0]));
+        stabilizer.options.strength = 1.0 / (MAX_AVERAGE_WINDOW - 1) as f32;
+        assert_eq!(stabilizer.push([8.0, 0.0]), Some([6.0, 2.0]));
+    }
+
+    #[test]
+    fn smoothing_keeps_the_ends() {
+        let mut polyline = Polyline::default();
+        for (point, pressure) in [([0.0, 0.0], 0.2), ([1.0, 1.0], 1.0), ([2.0, 0.0], 0.2)] {
+            polyline.push(point, pressure);
+        }
+        smooth_stroke(&mut polyline, 1.0);
+        assert_eq!(polyline.points[0], [0.0, 0.0]);
+        assert_eq!(polyline.points[2], [2.0, 0.0]);
+        assert!(polyline.points[1][1] < 1.0);
+        assert!(polyline.pressure[1] < 1.0);
+    }
+}
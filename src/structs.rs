@@ -1,4 +1,8 @@
-use std::{collections::HashSet, sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Instant,
+};
 
 use dioxus::desktop::{
     tao::{keyboard::Key, window::Window},
@@ -7,17 +11,128 @@ use dioxus::desktop::{
 use egui::{Context, ImageSource, RawInput};
 use egui_wgpu::Renderer;
 use glyphon::{FontSystem, SwashCache};
-use stream::desktop::manager::GrpcConnectionManager;
+use serde::{Deserialize, Serialize};
+use stream::desktop::{manager::GrpcConnectionManager, stream::PaintEvent};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+
+use crate::board_file::{BoardFileCommand, BoardMetadata};
+use crate::camera::Camera;
+use crate::caret::TextCaret;
+use crate::eraser::EraserMode;
+use crate::export::{ExportFormat, PngExportOptions};
+use crate::images::ImageStore;
+use crate::pages::{Page, PageCommand};
+use crate::recognition::{PenRest, RecognitionMode};
+use crate::scene::Scene;
+use crate::selection::Selection;
+use crate::shapes::ShapeKind;
+use crate::slides::SlideImport;
+use crate::smoothing::Stabilizer;
 use wgpu::SurfaceConfiguration;
 
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Serialize, Deserialize)]
 pub struct Vertex {
     pub position: [f32; 2],
     pub color: [f32; 4],
 }
 
-#[derive(Clone, Debug)]
+/// A corner of an image quad: a position in NDC and where it samples the
+/// image, from the top left corner.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+pub struct ImageVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+}
+
+/// A stroke as it is drawn: raw pointer samples in NDC and their pen pressure.
+/// It becomes a `Stroke` when the pen is lifted.
+#[derive(Clone, Debug, Default)]
+pub struct Polyline {
+    pub points: Vec<[f32; 2]>,
+    pub pressure: Vec<f32>,
+}
+
+impl Polyline {
+    pub fn push(&mut self, point: [f32; 2], pressure: f32) {
+        self.points.push(point);
+        self.pressure.push(pressure);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+}
+
+/// A committed stroke, stored as piecewise cubic Béziers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stroke {
+    pub color: [f32; 4],
+    /// Line width in board pixels.
+    pub width: f32,
+    /// Control points in NDC: `[p0, c, c, p1, c, c, p2, ...]`, so segment `i`
+    /// is `points[3 * i..=3 * i + 3]`. A single point is a dot.
+    pub points: Vec<[f32; 2]>,
+    /// Pen pressure in `0.0..=1.0` at each end point `p0, p1, ...`, scaling
+    /// `width` and interpolated along each segment. Empty for constant width.
+    pub pressure: Vec<f32>,
+    pub join: LineJoin,
+}
+
+/// How the pieces of a stroke meet where it turns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineJoin {
+    #[default]
+    Round,
+    /// A sharp corner, beveled when it would reach too far past the turn.
+    Miter,
+    Bevel,
+}
+
+impl Stroke {
+    /// Joins the samples of `polyline` with straight segments, without fitting.
+    pub fn from_polyline(polyline: &Polyline, color: [f32; 4], width: f32, join: LineJoin) -> Self {
+        let mut points = Vec::with_capacity(polyline.points.len() * 3);
+        for (index, point) in polyline.points.iter().enumerate() {
+            if let Some(previous) = index.checked_sub(1).map(|index| polyline.points[index]) {
+                let third = [
+                    (point[0] - previous[0]) / 3.0,
+                    (point[1] - previous[1]) / 3.0,
+                ];
+                points.push([previous[0] + third[0], previous[1] + third[1]]);
+                points.push([point[0] - third[0], point[1] - third[1]]);
+            }
+            points.push(*point);
+        }
+
+        Stroke {
+            color,
+            width,
+            points,
+            pressure: polyline.pressure.clone(),
+            join,
+        }
+    }
+
+    /// The cubic segments of the stroke, as `[start, control, control, end]`.
+    pub fn segments(&self) -> impl Iterator<Item = [[f32; 2]; 4]> + '_ {
+        self.points
+            .windows(4)
+            .step_by(3)
+            .map(|segment| [segment[0], segment[1], segment[2], segment[3]])
+    }
+
+    /// Width in board pixels at end point `index`.
+    pub fn width_at(&self, index: usize) -> f32 {
+        self.width * self.pressure.get(index).copied().unwrap_or(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -26,60 +141,112 @@ pub struct Rect {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Serialize, Deserialize)]
 pub struct Rectangle {
     pub first: [f32; 2],
     pub last: [f32; 2],
+    /// Outline color.
     pub color: [f32; 4],
+    /// Fill color, fully transparent for a hollow rectangle.
+    pub fill: [f32; 4],
+    /// Outline width in board pixels; 0 draws no outline.
+    pub outline_width: f32,
+    /// Corner radius in board pixels.
+    pub corner_radius: f32,
 }
 
-impl Rectangle {
-    pub fn to_vertices(self) -> Vec<Vertex> {
-        let (x1, y1) = (self.first[0], self.first[1]);
-        let (x2, y2) = (self.last[0], self.last[1]);
+/// An ellipse filling the box between two corners in NDC.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Ellipse {
+    pub first: [f32; 2],
+    pub last: [f32; 2],
+    /// Outline color.
+    pub color: [f32; 4],
+    /// Fill color, fully transparent for a hollow ellipse.
+    pub fill: [f32; 4],
+    /// Outline width in board pixels; 0 draws no outline.
+    pub outline_width: f32,
+}
 
-        vec![
-            Vertex {
-                position: [x1, y2],
-                color: self.color,
-            },
-            Vertex {
-                position: [x2, y2],
-                color: self.color,
-            },
-            Vertex {
-                position: [x2, y2],
-                color: self.color,
-            },
-            Vertex {
-                position: [x2, y1],
-                color: self.color,
-            },
-            Vertex {
-                position: [x2, y1],
-                color: self.color,
-            },
-            Vertex {
-                position: [x1, y1],
-                color: self.color,
-            },
-            Vertex {
-                position: [x1, y1],
-                color: self.color,
-            },
-            Vertex {
-                position: [x1, y2],
-                color: self.color,
-            },
-        ]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArrowHeads {
+    #[default]
+    None,
+    /// A head at `last`.
+    End,
+    /// Heads at both ends.
+    Both,
+}
+
+/// A straight line from `first` to `last` in NDC.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Line {
+    pub first: [f32; 2],
+    pub last: [f32; 2],
+    pub color: [f32; 4],
+    /// Line width in board pixels.
+    pub width: f32,
+    pub heads: ArrowHeads,
+}
+
+/// A closed polygon, such as a triangle or a regular or freehand polygon.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Polygon {
+    /// Corners in NDC, in drawing order.
+    pub points: Vec<[f32; 2]>,
+    /// Outline color.
+    pub color: [f32; 4],
+    /// Fill color, fully transparent for a hollow polygon.
+    pub fill: [f32; 4],
+    /// Outline width in board pixels; 0 draws no outline.
+    pub outline_width: f32,
+}
+
+/// A raster image placed on the board. Its pixels are kept once per content
+/// hash in `ImageStore`, so copies and edits of an image don't carry them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Image {
+    /// Center in NDC.
+    pub center: [f32; 2],
+    /// Width and height in board pixels, before rotation.
+    pub size: [f32; 2],
+    /// Radians, clockwise on screen.
+    pub rotation: f32,
+    /// SHA-256 of the encoded image file, in lowercase hex.
+    pub hash: String,
+}
+
+/// Style given to new shapes. Their outline takes the pen color.
+#[derive(Clone, Copy, Debug)]
+pub struct ShapeStyle {
+    pub fill: [f32; 4],
+    /// Outline width of closed shapes and width of lines, in board pixels.
+    pub outline_width: f32,
+    pub corner_radius: f32,
+    /// Corners of new regular polygons.
+    pub sides: usize,
+}
+
+impl Default for ShapeStyle {
+    fn default() -> Self {
+        ShapeStyle {
+            fill: [0.0; 4],
+            outline_width: 1.0,
+            corner_radius: 0.0,
+            sides: 5,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Text is always upright on screen: its corner follows the board and its
+/// size the zoom, but it never turns with the view or with a rotation of the
+/// selection. Hit testing follows what is drawn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextEntries {
     pub position: [f32; 2],
     pub color: [u8; 4],
     pub text: String,
+    #[serde(skip)]
     pub pending: bool,
     pub bounds: Rect,
     pub font_size: i32,
@@ -103,31 +270,77 @@ impl TextEntries {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ActionType {
-    Stroke(Vec<Vertex>),
+    Stroke(Stroke),
     Text(TextEntries),
     Shapes(Rectangle),
+    Ellipse(Ellipse),
+    Line(Line),
+    Polygon(Polygon),
+    Image(Image),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Background {
+    Transparent,
+    /// The paper of the page, on white.
+    #[default]
+    White,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Action {
     pub id: uuid::Uuid,
     pub action_type: ActionType,
 }
 
+impl Action {
+    /// Text that is still being typed; it is not shared until it is committed.
+    pub fn is_pending(&self) -> bool {
+        matches!(&self.action_type, ActionType::Text(text_entry) if text_entry.pending)
+    }
+}
+
 pub struct WindowState {
     pub device: egui_wgpu::wgpu::Device,
     pub pressed_keys: HashSet<Key<'static>>,
     pub queue: egui_wgpu::wgpu::Queue,
     pub show_modal_fonts: bool,
+    pub show_modal_widths: bool,
+    pub show_modal_shapes: bool,
+    pub show_modal_view: bool,
+    pub show_modal_paper: bool,
+    pub show_modal_slides: bool,
     pub font_size: i32,
     pub events_id: HashSet<uuid::Uuid>,
     pub show_modal_colors: bool,
+    pub show_modal_export: bool,
+    pub png_export_options: PngExportOptions,
+    pub pending_export: Option<ExportFormat>,
+    pub metadata: BoardMetadata,
+    pub pending_board_file: Option<BoardFileCommand>,
     pub surface: egui_wgpu::wgpu::Surface<'static>,
     pub surface_config: SurfaceConfiguration,
     pub last_cursor_position: PhysicalPosition<f64>,
-    pub actions: Vec<Action>,
+    pub scene: Scene,
+    /// Pages of the board in order. The actions of the current page are in
+    /// `scene`.
+    pub pages: Vec<Page>,
+    pub current_page: usize,
+    /// Whether to turn to the page other participants turn to.
+    pub follow_pages: bool,
+    pub pending_page: Option<PageCommand>,
+    /// Image files of the board by content hash.
+    pub images: ImageStore,
+    /// Hashes of the image files the room already has.
+    pub shared_images: HashSet<String>,
+    /// Textures of the images drawn so far, by content hash.
+    pub image_textures: HashMap<String, egui_wgpu::wgpu::BindGroup>,
+    /// Small copies of the images for page thumbnails, by content hash.
+    pub thumbnail_textures: HashMap<String, egui::TextureHandle>,
+    pub pending_image_file: bool,
+    pub pending_slides: Option<SlideImport>,
     pub scale_factor: f64,
     pub egui_renderer: Renderer,
     pub raw_input: RawInput,
@@ -137,36 +350,84 @@ pub struct WindowState {
     pub font_system: FontSystem,
     pub swash_cache: SwashCache,
     pub viewport: glyphon::Viewport,
-    pub texts: Vec<TextEntries>,
     pub atlas: glyphon::TextAtlas,
-    pub text_renderer: glyphon::TextRenderer,
+    /// One per run of text in paint order, so text is layered between the
+    /// other content.
+    pub text_renderers: Vec<glyphon::TextRenderer>,
     pub window: Arc<Window>,
 
     pub mouse_pressed: bool,
-    pub strokes: Vec<Vec<Vertex>>,
-    pub current_stroke: Vec<Vertex>,
+    pub current_stroke: Polyline,
+    pub last_stroke_point: Instant,
+    pub stabilizer: Stabilizer,
+    pub stroke_width: f32,
+    /// Join of the strokes drawn next.
+    pub stroke_join: LineJoin,
+    /// Whether finished strokes that look like shapes are replaced by them.
+    pub shape_recognition: RecognitionMode,
+    /// Where the pen is resting in the stroke being drawn, in board pixels.
+    pub pen_rest: Option<PenRest>,
+    /// The eraser replaces the pen while it is set.
+    pub eraser: Option<EraserMode>,
+    /// Where the eraser was on the last pointer event, in board pixels.
+    pub last_erase_position: Option<[f32; 2]>,
+    /// The selection tool replaces the pen while it is set.
+    pub selection: Option<Selection>,
     pub current_color: [f32; 4],
 
     pub render_pipeline: egui_wgpu::wgpu::RenderPipeline,
+    /// Draws the paper of the current page under the board content.
+    pub paper_pipeline: egui_wgpu::wgpu::RenderPipeline,
+    pub paper_buffer: egui_wgpu::wgpu::Buffer,
+    pub paper_bind_group: egui_wgpu::wgpu::BindGroup,
+    /// Draws images as textured quads, one bind group per texture.
+    pub image_pipeline: egui_wgpu::wgpu::RenderPipeline,
+    pub image_layout: egui_wgpu::wgpu::BindGroupLayout,
+    /// How the board is panned, zoomed and turned on screen.
+    pub camera: Camera,
+    pub camera_buffer: egui_wgpu::wgpu::Buffer,
+    pub camera_bind_group: egui_wgpu::wgpu::BindGroup,
+    /// The last pointer position of a pan in progress, in screen pixels.
+    pub pan_anchor: Option<PhysicalPosition<f64>>,
     pub rectangle_shader: Option<egui_wgpu::wgpu::RenderPipeline>,
-    pub vertex_buffer: egui_wgpu::wgpu::Buffer,
+    pub shape_shader: Option<egui_wgpu::wgpu::RenderPipeline>,
     pub start_typing: bool,
     pub shape_positions: Vec<Vertex>,
-    pub shapes: Vec<Rectangle>,
+    pub shape_style: ShapeStyle,
+    pub shape_kind: ShapeKind,
     pub create_rect: bool,
     pub cursor_visible: bool,
     pub cursor_timer: Instant,
     pub last_click_time: Option<Instant>,
     pub last_click_position: Option<PhysicalPosition<f64>>,
-    pub editing_text_index: Option<usize>,
+    pub editing_text: Option<uuid::Uuid>,
+    /// Where typing goes in the text being edited.
+    pub text_caret: TextCaret,
 
     pub color: ImageSource<'static>,
     pub rect: ImageSource<'static>,
-    // pub prev: ImageSource<'static>,
+    pub prev: ImageSource<'static>,
     pub font: ImageSource<'static>,
 
     pub stream_client: Arc<GrpcConnectionManager>,
+    pub room: String,
+    /// Tells this window apart from the others in the room.
+    pub client_id: uuid::Uuid,
+    /// Events from the room stream, handed over by `subscription` and
+    /// applied on the next frame.
+    pub incoming_events: UnboundedReceiver<PaintEvent>,
+    pub incoming_sender: UnboundedSender<PaintEvent>,
+    /// The task subscribed to the stream of `room`.
+    pub subscription: Option<JoinHandle<()>>,
+    /// Clients that asked for the board, and when to answer them unless
+    /// someone else does first.
+    pub sync_answers: HashMap<uuid::Uuid, Instant>,
 
     pub actions_changed: bool,
-    // pub undo_button: bool,
+    pub redo_stack: Vec<Action>,
+    /// Ids of the actions this client drew, oldest first. Undo only takes
+    /// these back, never what others drew.
+    pub undo_history: Vec<uuid::Uuid>,
+    pub undo_button: bool,
+    pub redo_button: bool,
 }
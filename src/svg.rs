@@ -0,0 +1,516 @@
+use crate::{
+    export::{self, BoardPainter, ExportError, PageContent},
+    images::StoredImage,
+    paper::{self, Paper, PaperMark},
+    render, shapes,
+    structs::{Background, Ellipse, Image, Line, LineJoin, Polygon, Rectangle, Stroke},
+    utils, TextEntries, WindowState,
+};
+use glyphon::FontSystem;
+use std::{fmt::Write, path::Path};
+
+#[derive(Clone, Copy, Debug)]
+pub struct SvgExportOptions {
+    pub background: Background,
+    /// Embeds Vazir as a data URI; otherwise the font is referenced by name and
+    /// must be installed where the SVG is opened.
+    pub embed_font: bool,
+}
+
+impl Default for SvgExportOptions {
+    fn default() -> Self {
+        SvgExportOptions {
+            background: Background::White,
+            embed_font: true,
+        }
+    }
+}
+
+struct SvgPainter<'a> {
+    font_system: &'a mut FontSystem,
+    board_size: [f32; 2],
+    body: String,
+}
+
+impl SvgPainter<'_> {
+    /// Draws `points`, laid out as in `Stroke::points`, as one SVG path.
+    fn path(&mut self, points: &[[f32; 2]], color: [f32; 4], width: f32, join: LineJoin) {
+        let mut data = Vec::with_capacity(points.len() + points.len() / 3);
+        for (index, point) in points.iter().enumerate() {
+            let [x, y] = export::ndc_to_board(*point, self.board_size);
+            match index {
+                0 => data.push(format!("M{:.2},{:.2}", x, y)),
+                _ if index % 3 == 1 => data.push(format!("C{:.2},{:.2}", x, y)),
+                _ => data.push(format!("{:.2},{:.2}", x, y)),
+            }
+        }
+
+        let _ = writeln!(
+            self.body,
+            r#"  <path d="{}" fill="none" {} stroke-width="{}" stroke-linecap="round" stroke-linejoin="{}"/>"#,
+            data.join(" "),
+            paint("stroke", utils::normalized_to_rgba(color)),
+            width,
+            match join {
+                LineJoin::Round => "round",
+                LineJoin::Miter => "miter",
+                LineJoin::Bevel => "bevel",
+            },
+        );
+    }
+}
+
+impl BoardPainter for SvgPainter<'_> {
+    fn paper(&mut self, paper: &Paper) {
+        let _ = writeln!(
+            self.body,
+            r#"  <rect width="100%" height="100%" {}/>"#,
+            paint("fill", utils::normalized_to_rgba(paper.on_white())),
+        );
+        for mark in paper::paper_marks(paper, self.board_size) {
+            let _ = match mark {
+                PaperMark::Line {
+                    from,
+                    to,
+                    width,
+                    color,
+                } => writeln!(
+                    self.body,
+                    r#"  <line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" {} stroke-width="{}"/>"#,
+                    from[0],
+                    from[1],
+                    to[0],
+                    to[1],
+                    paint("stroke", utils::normalized_to_rgba(color)),
+                    width,
+                ),
+                PaperMark::Dot {
+                    center,
+                    radius,
+                    color,
+                } => writeln!(
+                    self.body,
+                    r#"  <circle cx="{:.2}" cy="{:.2}" r="{}" {}/>"#,
+                    center[0],
+                    center[1],
+                    radius,
+                    paint("fill", utils::normalized_to_rgba(color)),
+                ),
+            };
+        }
+    }
+
+    fn image(&mut self, image: &Image, stored: &StoredImage) {
+        let [x, y] = export::ndc_to_board(image.center, self.board_size);
+        let [width, height] = image.size;
+        let mime = match image::guess_format(&stored.bytes) {
+            Ok(image::ImageFormat::Jpeg) => "image/jpeg",
+            _ => "image/png",
+        };
+
+        let _ = writeln!(
+            self.body,
+            r#"  <image x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" transform="rotate({:.3} {:.2} {:.2})" preserveAspectRatio="none" href="data:{};base64,{}"/>"#,
+            x - width / 2.0,
+            y - height / 2.0,
+            width,
+            height,
+            image.rotation.to_degrees(),
+            x,
+            y,
+            mime,
+            utils::base64_encode(&stored.bytes),
+        );
+    }
+
+    fn rectangle(&mut self, rectangle: &Rectangle) {
+        let [x1, y1] = export::ndc_to_board(rectangle.first, self.board_size);
+        let [x2, y2] = export::ndc_to_board(rectangle.last, self.board_size);
+
+        let (width, height) = ((x2 - x1).abs(), (y2 - y1).abs());
+        let radius = rectangle.corner_radius.min(width / 2.0).min(height / 2.0);
+
+        let _ = writeln!(
+            self.body,
+            r#"  <rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" rx="{:.2}" {}/>"#,
+            x1.min(x2),
+            y1.min(y2),
+            width,
+            height,
+            radius,
+            shape_paint(rectangle.color, rectangle.fill, rectangle.outline_width),
+        );
+    }
+
+    fn ellipse(&mut self, ellipse: &Ellipse) {
+        let (center, radii) = shapes::ellipse_box(ellipse, self.board_size);
+
+        let _ = writeln!(
+            self.body,
+            r#"  <ellipse cx="{:.2}" cy="{:.2}" rx="{:.2}" ry="{:.2}" {}/>"#,
+            center[0],
+            center[1],
+            radii[0],
+            radii[1],
+            shape_paint(ellipse.color, ellipse.fill, ellipse.outline_width),
+        );
+    }
+
+    fn line(&mut self, line: &Line) {
+        let ([start, end], heads) = shapes::line_geometry(line, self.board_size);
+        let color = utils::normalized_to_rgba(line.color);
+
+        let _ = writeln!(
+            self.body,
+            r#"  <line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" {} stroke-width="{}" stroke-linecap="round"/>"#,
+            start[0],
+            start[1],
+            end[0],
+            end[1],
+            paint("stroke", color),
+            line.width,
+        );
+        for head in heads {
+            let _ = writeln!(
+                self.body,
+                r#"  <polygon points="{}" {}/>"#,
+                points(head),
+                paint("fill", color),
+            );
+        }
+    }
+
+    fn polygon(&mut self, polygon: &Polygon) {
+        let corners = polygon
+            .points
+            .iter()
+            .map(|point| export::ndc_to_board(*point, self.board_size));
+
+        let _ = writeln!(
+            self.body,
+            r#"  <polygon points="{}" {} stroke-linejoin="round"/>"#,
+            points(corners),
+            shape_paint(polygon.color, polygon.fill, polygon.outline_width),
+        );
+    }
+
+    fn stroke(&mut self, stroke: &Stroke) {
+        if let Some((center, width)) = export::stroke_dot(stroke, self.board_size) {
+            let _ = writeln!(
+                self.body,
+                r#"  <circle cx="{:.2}" cy="{:.2}" r="{:.2}" {}/>"#,
+                center[0],
+                center[1],
+                width / 2.0,
+                paint("fill", utils::normalized_to_rgba(stroke.color)),
+            );
+        }
+        for (range, width) in export::stroke_runs(stroke) {
+            self.path(&stroke.points[range], stroke.color, width, stroke.join);
+        }
+    }
+
+    fn text(&mut self, text_entry: &TextEntries) {
+        if text_entry.text.is_empty() {
+            return;
+        }
+
+        let buffer = render::text_buffer(
+            self.font_system,
+            text_entry,
+            self.board_size[0],
+            self.board_size[1],
+        );
+        let baseline = buffer
+            .layout_runs()
+            .next()
+            .map_or(text_entry.font_size as f32, |run| run.line_y);
+
+        let direction = if utils::is_rtl(&text_entry.text) {
+            r#" direction="rtl" unicode-bidi="embed" text-anchor="end""#
+        } else {
+            ""
+        };
+
+        let _ = writeln!(
+            self.body,
+            r#"  <text x="{:.2}" y="{:.2}" font-family="Vazir" font-size="{}" {}{}>{}</text>"#,
+            text_entry.position[0],
+            text_entry.position[1] + baseline,
+            text_entry.font_size,
+            paint("fill", text_entry.color),
+            direction,
+            utils::xml_escape(&text_entry.text),
+        );
+    }
+}
+
+/// Fill and outline attributes of a closed shape.
+fn shape_paint(color: [f32; 4], fill: [f32; 4], outline_width: f32) -> String {
+    let fill = if fill[3] > 0.0 {
+        paint("fill", utils::normalized_to_rgba(fill))
+    } else {
+        r#"fill="none""#.to_owned()
+    };
+    let outline = if outline_width > 0.0 {
+        format!(
+            r#"{} stroke-width="{}""#,
+            paint("stroke", utils::normalized_to_rgba(color)),
+            outline_width
+        )
+    } else {
+        r#"stroke="none""#.to_owned()
+    };
+    format!("{} {}", fill, outline)
+}
+
+/// A `points` attribute value, in board pixels.
+fn points(points: impl IntoIterator<Item = [f32; 2]>) -> String {
+    points
+        .into_iter()
+        .map(|[x, y]| format!("{:.2},{:.2}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn paint(attribute: &str, color: [u8; 4]) -> String {
+    format!(
+        r##"{attribute}="#{:02x}{:02x}{:02x}" {attribute}-opacity="{:.3}""##,
+        color[0],
+        color[1],
+        color[2],
+        color[3] as f32 / 255.0,
+    )
+}
+
+pub(crate) fn write_svg(
+    font_system: &mut FontSystem,
+    page: &PageContent,
+    board_size: [f32; 2],
+    options: &SvgExportOptions,
+) -> String {
+    let mut painter = SvgPainter {
+        font_system,
+        board_size,
+        body: String::new(),
+    };
+    let paper = (options.background == Background::White).then_some(&page.paper);
+    export::paint_actions(
+        paper,
+        page.slide,
+        page.actions.iter(),
+        page.images,
+        &mut painter,
+    );
+
+    let font_source = if options.embed_font {
+        format!(
+            "url(data:font/ttf;base64,{}) format(\"truetype\")",
+            utils::base64_encode(include_bytes!("assets/vazir.ttf"))
+        )
+    } else {
+        "local(\"Vazir\")".to_owned()
+    };
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = board_size[0],
+        h = board_size[1],
+    );
+    let _ = writeln!(
+        svg,
+        "  <defs><style>@font-face {{ font-family: \"Vazir\"; src: {}; }}</style></defs>",
+        font_source
+    );
+    svg.push_str(&painter.body);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Converts a page into a standalone SVG document whose viewBox is the board
+/// size in pixels, so strokes, shapes and text share one coordinate space.
+/// Images are embedded as data URIs. On a white background the page is
+/// drawn on its paper.
+pub fn export_svg(page: &PageContent, board_size: [f32; 2], options: &SvgExportOptions) -> String {
+    write_svg(&mut render::font_system(), page, board_size, options)
+}
+
+impl WindowState {
+    pub fn export_svg(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: &SvgExportOptions,
+    ) -> Result<(), ExportError> {
+        let board_size = self.board_size();
+        let actions = self.board_actions();
+        let page = PageContent {
+            paper: self.current_paper(),
+            slide: self.pages[self.current_page].slide.as_ref(),
+            actions: &actions,
+            images: &self.images,
+        };
+        let svg = write_svg(&mut self.font_system, &page, board_size, options);
+        std::fs::write(path, svg).map_err(ExportError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        images::ImageStore,
+        paper::PaperKind,
+        structs::{Action, ActionType},
+    };
+    use uuid::Uuid;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+
+    fn action(action_type: ActionType) -> Action {
+        Action {
+            id: Uuid::new_v4(),
+            action_type,
+        }
+    }
+
+    fn stroke(points: Vec<[f32; 2]>) -> Action {
+        action(ActionType::Stroke(Stroke {
+            color: [1.0, 0.0, 0.0, 1.0],
+            width: 4.0,
+            points,
+            pressure: vec![],
+            join: LineJoin::Round,
+        }))
+    }
+
+    fn text(text: &str) -> Action {
+        action(ActionType::Text(TextEntries {
+            position: [100.0, 200.0],
+            text: text.to_owned(),
+            pending: false,
+            ..TextEntries::null([0, 0, 0, 255], 24)
+        }))
+    }
+
+    fn svg(actions: &[Action], options: &SvgExportOptions) -> String {
+        let images = ImageStore::default();
+        let page = PageContent {
+            paper: Paper {
+                kind: PaperKind::Ruled,
+                ..Paper::default()
+            },
+            slide: None,
+            actions,
+            images: &images,
+        };
+        export_svg(&page, BOARD_SIZE, options)
+    }
+
+    /// The elements named `name`, checking the document parses.
+    fn elements<'a>(document: &'a roxmltree::Document, name: &str) -> Vec<roxmltree::Node<'a, 'a>> {
+        document
+            .descendants()
+            .filter(|node| node.has_tag_name(name))
+            .collect()
+    }
+
+    #[test]
+    fn draws_strokes_as_paths_and_taps_as_dots() {
+        let curve = stroke(vec![[-0.5, 0.0], [-0.2, 0.5], [0.2, 0.5], [0.5, 0.0]]);
+        let tap = stroke(vec![[0.0, -0.5]]);
+        let svg = svg(&[curve, tap], &SvgExportOptions::default());
+        let document = roxmltree::Document::parse(&svg).unwrap();
+
+        let [path] = elements(&document, "path")[..] else {
+            panic!("expected one path");
+        };
+        assert_eq!(
+            path.attribute("d"),
+            Some("M200.00,300.00 C320.00,150.00 480.00,150.00 600.00,300.00")
+        );
+        assert_eq!(path.attribute("stroke"), Some("#ff0000"));
+        assert_eq!(path.attribute("stroke-width"), Some("4"));
+
+        let dot = elements(&document, "circle")
+            .into_iter()
+            .find(|circle| circle.attribute("fill") == Some("#ff0000"))
+            .expect("a dot for the tap");
+        assert_eq!(dot.attribute("cx"), Some("400.00"));
+        assert_eq!(dot.attribute("cy"), Some("450.00"));
+        assert_eq!(dot.attribute("r"), Some("2.00"));
+    }
+
+    #[test]
+    fn draws_the_paper_only_on_a_white_background() {
+        let white = svg(&[], &SvgExportOptions::default());
+        let document = roxmltree::Document::parse(&white).unwrap();
+        assert_eq!(elements(&document, "rect").len(), 1);
+        assert!(!elements(&document, "line").is_empty());
+
+        let transparent = svg(
+            &[],
+            &SvgExportOptions {
+                background: Background::Transparent,
+                ..Default::default()
+            },
+        );
+        let document = roxmltree::Document::parse(&transparent).unwrap();
+        assert!(elements(&document, "rect").is_empty());
+        assert!(elements(&document, "line").is_empty());
+    }
+
+    #[test]
+    fn escapes_text() {
+        let written = "<b> & \"quotes\" 'too'";
+        let svg = svg(&[text(written)], &SvgExportOptions::default());
+        let document = roxmltree::Document::parse(&svg).unwrap();
+        let [element] = elements(&document, "text")[..] else {
+            panic!("expected one text");
+        };
+        assert_eq!(element.text(), Some(written));
+        assert_eq!(element.attribute("direction"), None);
+    }
+
+    #[test]
+    fn sets_right_to_left_text_from_the_right() {
+        let svg = svg(&[text("سلام دنیا")], &SvgExportOptions::default());
+        let document = roxmltree::Document::parse(&svg).unwrap();
+        let [element] = elements(&document, "text")[..] else {
+            panic!("expected one text");
+        };
+        assert_eq!(element.text(), Some("سلام دنیا"));
+        assert_eq!(element.attribute("direction"), Some("rtl"));
+        assert_eq!(element.attribute("text-anchor"), Some("end"));
+        assert_eq!(element.attribute("font-family"), Some("Vazir"));
+    }
+
+    #[test]
+    fn embeds_the_font_unless_asked_not_to() {
+        let style = |options: &SvgExportOptions| {
+            let svg = svg(&[], options);
+            let document = roxmltree::Document::parse(&svg).unwrap();
+            let style = elements(&document, "style")[0].text().unwrap().to_owned();
+            style
+        };
+
+        let embedded = style(&SvgExportOptions::default());
+        let encoded = embedded
+            .split("data:font/ttf;base64,")
+            .nth(1)
+            .and_then(|rest| rest.split(')').next())
+            .expect("an embedded font");
+        assert_eq!(
+            utils::base64_decode(encoded).as_deref(),
+            Some(&include_bytes!("assets/vazir.ttf")[..])
+        );
+
+        let referenced = style(&SvgExportOptions {
+            embed_font: false,
+            ..Default::default()
+        });
+        assert!(referenced.contains("local(\"Vazir\")"));
+        assert!(!referenced.contains("base64"));
+    }
+}
@@ -0,0 +1,1239 @@
+use crate::{
+    images,
+    paper::{Paper, PaperKind},
+    structs::{
+        Action, ActionType, ArrowHeads, Ellipse, Image, Line, LineJoin, Polygon, Polyline, Rect,
+        Rectangle, Stroke,
+    },
+    utils, TextEntries, WindowState,
+};
+use prost_types::Timestamp;
+use std::time::{Duration, Instant, SystemTime};
+use stream::desktop::stream::{
+    paint_event::ActionType as StreamActionType, PaintEvent, StreamAction, StreamActions,
+    StreamRect, StreamRectangle, StreamTextEntries, StreamVertex,
+};
+use uuid::Uuid;
+
+/// Sixth color component of the first vertex of a stroke sent as Bézier
+/// control points, followed by its join. Strokes without it are plain
+/// polylines.
+const CURVE_FORMAT: f32 = 1.0;
+/// Sixth color component of the first vertex of a straight line, followed
+/// by its arrow heads.
+const LINE_FORMAT: f32 = 2.0;
+/// Sixth color component of the first vertex of a polygon, followed by its
+/// fill color.
+const POLYGON_FORMAT: f32 = 3.0;
+/// Sixth color component of the only vertex of an image, at its center,
+/// followed by its width, height and rotation.
+const IMAGE_FORMAT: f32 = 4.0;
+/// Eleventh color component of a rectangle that is drawn as an ellipse.
+const ELLIPSE_SHAPE: f32 = 1.0;
+/// Separates the id of an action from the id of its page in
+/// `StreamAction::id`. Actions on the first page are sent with a bare id, as
+/// older clients do.
+const PAGE_SEPARATOR: char = '@';
+/// Id prefix of the message that tells the room a page was turned to or
+/// deleted. Older clients can't parse it as an action id and skip it.
+const PAGE_MESSAGE: &str = "page";
+/// Second position component of a page message that only announces the
+/// page. Receivers add the page without turning to it.
+const PAGE_ANNOUNCEMENT: f32 = 1.0;
+/// Id prefix of the message a client sends when it joins a room, followed
+/// by its client id. One of the others answers by sharing their whole board.
+const SYNC_MESSAGE: &str = "sync";
+/// Id prefix of the message that starts an answer to a sync request,
+/// followed by the client that asked. The others waiting to answer it leave
+/// it at that.
+const SYNC_ANSWER_MESSAGE: &str = "synced";
+/// Longest wait before answering a sync request. Each client waits part of
+/// it, taken from its id, so that in most rooms the first answer arrives
+/// before anyone else starts theirs.
+const SYNC_ANSWER_WINDOW: Duration = Duration::from_millis(600);
+/// Id prefix of the message that sets the paper of a page.
+const PAPER_MESSAGE: &str = "paper";
+/// Ends the id of an image action, followed by the content hash of its file.
+/// Older clients can't parse the id and skip the image.
+const HASH_SEPARATOR: char = '#';
+/// Id prefix of the messages that carry an image file, base64 encoded in
+/// the text, with the chunk index and count as its position.
+const IMAGE_MESSAGE: &str = "image";
+/// Id prefix of the message that puts a slide under a page, followed by the
+/// page id and the hash of the slide image. Its only vertex is at the center
+/// of the slide, with its width, height and rotation as color.
+const SLIDE_MESSAGE: &str = "slide";
+/// How long to wait before subscribing to the room stream again after it
+/// ends or fails to open.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(2);
+
+fn stream_id(id: Uuid, page: Uuid) -> String {
+    if page.is_nil() {
+        id.to_string()
+    } else {
+        format!("{}{}{}", id, PAGE_SEPARATOR, page)
+    }
+}
+
+fn join_code(join: LineJoin) -> f32 {
+    match join {
+        LineJoin::Round => 0.0,
+        LineJoin::Miter => 1.0,
+        LineJoin::Bevel => 2.0,
+    }
+}
+
+/// Vertices that only carry style on the first one, as `[r, g, b, a,
+/// width, format, ..extra]`.
+fn styled_vertices(
+    points: impl IntoIterator<Item = [f32; 2]>,
+    color: [f32; 4],
+    width: f32,
+    format: f32,
+    extra: &[f32],
+) -> Vec<StreamVertex> {
+    points
+        .into_iter()
+        .enumerate()
+        .map(|(index, point)| StreamVertex {
+            position: point.to_vec(),
+            color: if index == 0 {
+                [&color[..], &[width, format], extra].concat()
+            } else {
+                vec![]
+            },
+        })
+        .collect()
+}
+
+/// Positions go out as they are stored, NDC of the shared board for strokes
+/// and shapes and board pixels for text, so every participant places them the
+/// same way whatever its window size. The id also names the `page` the
+/// action is on.
+pub(crate) fn to_stream_action(action: &Action, page: Uuid) -> StreamAction {
+    match action {
+        Action {
+            action_type: ActionType::Stroke(stroke),
+            id,
+        } => StreamAction {
+            vertices: stroke
+                .points
+                .iter()
+                .enumerate()
+                .map(|(index, point)| {
+                    let mut position = point.to_vec();
+                    if let Some(pressure) = (index % 3 == 0)
+                        .then(|| stroke.pressure.get(index / 3))
+                        .flatten()
+                    {
+                        position.push(*pressure);
+                    }
+                    let color = if index == 0 {
+                        let mut color = stroke.color.to_vec();
+                        color.extend([stroke.width, CURVE_FORMAT, join_code(stroke.join)]);
+                        color
+                    } else {
+                        vec![]
+                    };
+                    StreamVertex { position, color }
+                })
+                .collect(),
+            text: None,
+            rectangle: None,
+            id: stream_id(*id, page),
+        },
+        Action {
+            action_type: ActionType::Text(text_entries),
+            id,
+        } => StreamAction {
+            vertices: vec![],
+            text: Some(StreamTextEntries {
+                position: text_entries.position.to_vec(),
+                color: text_entries
+                    .color
+                    .iter()
+                    .map(|color| *color as f32)
+                    .collect(),
+                text: text_entries.text.clone(),
+                pending: text_entries.pending,
+                bounds: Some(StreamRect {
+                    x: text_entries.bounds.x,
+                    y: text_entries.bounds.y,
+                    width: text_entries.bounds.width,
+                    height: text_entries.bounds.height,
+                }),
+                font_size: text_entries.font_size,
+            }),
+            rectangle: None,
+            id: stream_id(*id, page),
+        },
+        Action {
+            action_type: ActionType::Shapes(rectangle),
+            id,
+        } => StreamAction {
+            vertices: vec![],
+            text: None,
+            rectangle: Some(StreamRectangle {
+                first: rectangle.first.to_vec(),
+                last: rectangle.last.to_vec(),
+                color: [
+                    &rectangle.color[..],
+                    &[rectangle.outline_width, rectangle.corner_radius],
+                    &rectangle.fill[..],
+                ]
+                .concat(),
+            }),
+            id: stream_id(*id, page),
+        },
+        Action {
+            action_type: ActionType::Ellipse(ellipse),
+            id,
+        } => StreamAction {
+            vertices: vec![],
+            text: None,
+            rectangle: Some(StreamRectangle {
+                first: ellipse.first.to_vec(),
+                last: ellipse.last.to_vec(),
+                color: [
+                    &ellipse.color[..],
+                    &[ellipse.outline_width, 0.0],
+                    &ellipse.fill[..],
+                    &[ELLIPSE_SHAPE],
+                ]
+                .concat(),
+            }),
+            id: stream_id(*id, page),
+        },
+        Action {
+            action_type: ActionType::Line(line),
+            id,
+        } => StreamAction {
+            vertices: styled_vertices(
+                [line.first, line.last],
+                line.color,
+                line.width,
+                LINE_FORMAT,
+                &[match line.heads {
+                    ArrowHeads::None => 0.0,
+                    ArrowHeads::End => 1.0,
+                    ArrowHeads::Both => 2.0,
+                }],
+            ),
+            text: None,
+            rectangle: None,
+            id: stream_id(*id, page),
+        },
+        Action {
+            action_type: ActionType::Polygon(polygon),
+            id,
+        } => StreamAction {
+            // The first corner is repeated at the end, so clients that read
+            // this as a stroke still draw a closed outline.
+            vertices: styled_vertices(
+                polygon.points.iter().chain(polygon.points.first()).copied(),
+                polygon.color,
+                polygon.outline_width,
+                POLYGON_FORMAT,
+                &polygon.fill,
+            ),
+            text: None,
+            rectangle: None,
+            id: stream_id(*id, page),
+        },
+        Action {
+            action_type: ActionType::Image(image),
+            id,
+        } => StreamAction {
+            vertices: styled_vertices(
+                [image.center],
+                [0.0; 4],
+                0.0,
+                IMAGE_FORMAT,
+                &[image.size[0], image.size[1], image.rotation],
+            ),
+            text: None,
+            rectangle: None,
+            id: format!("{}{}{}", stream_id(*id, page), HASH_SEPARATOR, image.hash),
+        },
+    }
+}
+
+/// The page an action received from the room is on, and the action.
+pub(crate) fn from_stream_action(stream_action: &StreamAction) -> Option<(Uuid, Action)> {
+    let (id, hash) = match stream_action.id.split_once(HASH_SEPARATOR) {
+        Some((id, hash)) => (id, Some(hash)),
+        None => (stream_action.id.as_str(), None),
+    };
+    let (id, page) = match id.split_once(PAGE_SEPARATOR) {
+        Some((id, page)) => (id, Uuid::parse_str(page).ok()?),
+        None => (id, Uuid::nil()),
+    };
+    let id = Uuid::parse_str(id).ok()?;
+
+    let action_type = if let Some(text) = &stream_action.text {
+        let bounds = text.bounds.clone().unwrap_or_default();
+        ActionType::Text(TextEntries {
+            position: to_array(&text.position)?,
+            color: to_array::<4>(&text.color)?.map(|color| color as u8),
+            text: text.text.clone(),
+            pending: false,
+            bounds: Rect {
+                x: bounds.x,
+                y: bounds.y,
+                width: bounds.width,
+                height: bounds.height,
+            },
+            font_size: text.font_size,
+        })
+    } else if let Some(rectangle) = &stream_action.rectangle {
+        // Style follows the outline color as `[.., outline_width,
+        // corner_radius, fill r, g, b, a, shape]`. Older clients send only
+        // the color, for a hollow 1 px rectangle with square corners.
+        let color = &rectangle.color;
+        let first = to_array(&rectangle.first)?;
+        let last = to_array(&rectangle.last)?;
+        let fill = color.get(6..).and_then(to_array).unwrap_or_default();
+        let outline_width = color.get(4).copied().unwrap_or(1.0);
+        if color.get(10) == Some(&ELLIPSE_SHAPE) {
+            ActionType::Ellipse(Ellipse {
+                first,
+                last,
+                color: to_array(color)?,
+                fill,
+                outline_width,
+            })
+        } else {
+            ActionType::Shapes(Rectangle {
+                first,
+                last,
+                color: to_array(color)?,
+                fill,
+                outline_width,
+                corner_radius: color.get(5).copied().unwrap_or(0.0),
+            })
+        }
+    } else {
+        // The stream has no stroke fields: style travels on the first vertex
+        // as `[r, g, b, a, width, CURVE_FORMAT, join]` and pressure as a
+        // third position component of the end points. Older clients send
+        // every sample with its color, strokes without a width are 1 px wide
+        // and strokes without a join are rounded.
+        // Lines and polygons use the same layout with their own format.
+        let first_color = &stream_action.vertices.first()?.color;
+        let color = to_array(first_color)?;
+        let width = first_color.get(4).copied().unwrap_or(1.0);
+        let format = first_color.get(5).copied();
+        let is_curve = format == Some(CURVE_FORMAT);
+
+        let mut points = stream_action
+            .vertices
+            .iter()
+            .map(|vertex| to_array(&vertex.position))
+            .collect::<Option<Vec<_>>>()?;
+
+        if format == Some(IMAGE_FORMAT) {
+            let [center] = points.try_into().ok()?;
+            let [width, height, rotation] = to_array(first_color.get(6..)?)?;
+            ActionType::Image(Image {
+                center,
+                size: [width, height],
+                rotation,
+                hash: hash?.to_owned(),
+            })
+        } else if format == Some(LINE_FORMAT) {
+            let [first, last] = points.try_into().ok()?;
+            let heads = match first_color.get(6).copied() {
+                Some(1.0) => ArrowHeads::End,
+                Some(2.0) => ArrowHeads::Both,
+                _ => ArrowHeads::None,
+            };
+            ActionType::Line(Line {
+                first,
+                last,
+                color,
+                width,
+                heads,
+            })
+        } else if format == Some(POLYGON_FORMAT) {
+            points.pop();
+            ActionType::Polygon(Polygon {
+                points,
+                color,
+                fill: first_color.get(6..).and_then(to_array).unwrap_or_default(),
+                outline_width: width,
+            })
+        } else {
+            let pressure = stream_action
+                .vertices
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !is_curve || index % 3 == 0)
+                .map(|(_, vertex)| vertex.position.get(2).copied())
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_default();
+
+            let join = match first_color.get(6).copied() {
+                Some(1.0) => LineJoin::Miter,
+                Some(2.0) => LineJoin::Bevel,
+                _ => LineJoin::Round,
+            };
+            if is_curve {
+                if points.len() % 3 != 1 {
+                    return None;
+                }
+                ActionType::Stroke(Stroke {
+                    color,
+                    width,
+                    points,
+                    pressure,
+                    join,
+                })
+            } else {
+                let polyline = Polyline { points, pressure };
+                ActionType::Stroke(Stroke::from_polyline(&polyline, color, width, join))
+            }
+        }
+    };
+
+    Some((page, Action { id, action_type }))
+}
+
+/// The page, its index on the sender's board and whether it was turned to,
+/// when `stream_action` is a page message rather than an action.
+pub(crate) fn from_page_message(stream_action: &StreamAction) -> Option<(Uuid, usize, bool)> {
+    let page = stream_action
+        .id
+        .strip_prefix(PAGE_MESSAGE)?
+        .strip_prefix(PAGE_SEPARATOR)?;
+    let position = stream_action
+        .vertices
+        .first()
+        .map(|vertex| vertex.position.as_slice())
+        .unwrap_or_default();
+    let index = position.first().map_or(0, |index| *index as usize);
+    let turned = position.get(1) != Some(&PAGE_ANNOUNCEMENT);
+    Some((Uuid::parse_str(page).ok()?, index, turned))
+}
+
+/// The client asking for the board, when `stream_action` is a sync request.
+pub(crate) fn from_sync_request(stream_action: &StreamAction) -> Option<Uuid> {
+    let client = stream_action
+        .id
+        .strip_prefix(SYNC_MESSAGE)?
+        .strip_prefix(PAGE_SEPARATOR)?;
+    Uuid::parse_str(client).ok()
+}
+
+/// The client whose sync request is being answered, when `stream_action`
+/// starts an answer.
+pub(crate) fn from_sync_answer(stream_action: &StreamAction) -> Option<Uuid> {
+    let client = stream_action
+        .id
+        .strip_prefix(SYNC_ANSWER_MESSAGE)?
+        .strip_prefix(PAGE_SEPARATOR)?;
+    Uuid::parse_str(client).ok()
+}
+
+/// How long `client` waits before answering a sync request.
+fn sync_answer_delay(client: Uuid) -> Duration {
+    let window = SYNC_ANSWER_WINDOW.as_millis() as u64;
+    Duration::from_millis(client.as_u128() as u64 % window)
+}
+
+/// The page and its paper, when `stream_action` is a paper message.
+pub(crate) fn from_paper_message(stream_action: &StreamAction) -> Option<(Uuid, Paper)> {
+    let page = stream_action
+        .id
+        .strip_prefix(PAPER_MESSAGE)?
+        .strip_prefix(PAGE_SEPARATOR)?;
+    let values = &stream_action.vertices.first()?.color;
+    let (kind, color) = values.split_first()?;
+    Some((
+        Uuid::parse_str(page).ok()?,
+        Paper {
+            kind: PaperKind::from_index(*kind as u32),
+            color: to_array(color)?,
+        },
+    ))
+}
+
+pub(crate) fn paint_event<'a>(
+    chat_room: &str,
+    page: Uuid,
+    actions: impl IntoIterator<Item = &'a Action>,
+    action_type: StreamActionType,
+) -> PaintEvent {
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        actions: actions
+            .into_iter()
+            .map(|action| StreamActions {
+                actions: vec![to_stream_action(action, page)],
+            })
+            .collect(),
+        timestamp: Some(Timestamp::from(SystemTime::now())),
+        action_type: Some(action_type),
+    }
+}
+
+/// Tells the room that `page`, at `index` on this board, was turned to, or
+/// deleted with `IsDeleted`. The index travels as the only vertex.
+pub(crate) fn page_event(
+    chat_room: &str,
+    page: Uuid,
+    index: usize,
+    action_type: StreamActionType,
+) -> PaintEvent {
+    page_message(chat_room, page, vec![index as f32], action_type)
+}
+
+/// Tells the room that `page` is at `index` on this board, without asking
+/// anyone to turn to it.
+pub(crate) fn page_announcement(chat_room: &str, page: Uuid, index: usize) -> PaintEvent {
+    page_message(
+        chat_room,
+        page,
+        vec![index as f32, PAGE_ANNOUNCEMENT],
+        StreamActionType::ActionRequest(true),
+    )
+}
+
+/// Takes a board away from the room before another replaces it: the shared
+/// actions of each page, given with its id, are deleted, then every page.
+/// Receivers empty the last page rather than removing it.
+pub(crate) fn board_reset_events<'a>(
+    chat_room: &str,
+    pages: impl IntoIterator<Item = (Uuid, Vec<&'a Action>)>,
+) -> Vec<PaintEvent> {
+    let pages: Vec<(Uuid, Vec<&Action>)> = pages.into_iter().collect();
+    let deleted_actions =
+        pages
+            .iter()
+            .filter(|(_, actions)| !actions.is_empty())
+            .map(|(page, actions)| {
+                paint_event(
+                    chat_room,
+                    *page,
+                    actions.iter().copied(),
+                    StreamActionType::IsDeleted(true),
+                )
+            });
+    let deleted_pages = pages.iter().enumerate().rev().map(|(index, (page, _))| {
+        page_event(chat_room, *page, index, StreamActionType::IsDeleted(true))
+    });
+    deleted_actions.chain(deleted_pages).collect()
+}
+
+fn page_message(
+    chat_room: &str,
+    page: Uuid,
+    position: Vec<f32>,
+    action_type: StreamActionType,
+) -> PaintEvent {
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        actions: vec![StreamActions {
+            actions: vec![StreamAction {
+                vertices: vec![StreamVertex {
+                    position,
+                    color: vec![],
+                }],
+                text: None,
+                rectangle: None,
+                id: format!("{}{}{}", PAGE_MESSAGE, PAGE_SEPARATOR, page),
+            }],
+        }],
+        timestamp: Some(Timestamp::from(SystemTime::now())),
+        action_type: Some(action_type),
+    }
+}
+
+/// Opens the stream of what is drawn in `chat_room`.
+fn subscription_event(chat_room: &str) -> PaintEvent {
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        ..Default::default()
+    }
+}
+
+/// Asks the others in the room to share their boards with `client`, which
+/// just joined.
+pub(crate) fn sync_request_event(chat_room: &str, client: Uuid) -> PaintEvent {
+    client_message(chat_room, SYNC_MESSAGE, client)
+}
+
+/// Tells the room the sync request of `client` is being answered.
+pub(crate) fn sync_answer_event(chat_room: &str, client: Uuid) -> PaintEvent {
+    client_message(chat_room, SYNC_ANSWER_MESSAGE, client)
+}
+
+fn client_message(chat_room: &str, prefix: &str, client: Uuid) -> PaintEvent {
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        actions: vec![StreamActions {
+            actions: vec![StreamAction {
+                id: format!("{}{}{}", prefix, PAGE_SEPARATOR, client),
+                ..Default::default()
+            }],
+        }],
+        timestamp: Some(Timestamp::from(SystemTime::now())),
+        action_type: Some(StreamActionType::ActionRequest(true)),
+    }
+}
+
+/// Sets the paper of `page` for the room. The kind and color travel as the
+/// color of the only vertex.
+pub(crate) fn paper_event(chat_room: &str, page: Uuid, paper: &Paper) -> PaintEvent {
+    let mut color = vec![paper.kind.index() as f32];
+    color.extend(paper.color);
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        actions: vec![StreamActions {
+            actions: vec![StreamAction {
+                vertices: vec![StreamVertex {
+                    position: vec![],
+                    color,
+                }],
+                text: None,
+                rectangle: None,
+                id: format!("{}{}{}", PAPER_MESSAGE, PAGE_SEPARATOR, page),
+            }],
+        }],
+        timestamp: Some(Timestamp::from(SystemTime::now())),
+        action_type: Some(StreamActionType::ActionRequest(true)),
+    }
+}
+
+pub(crate) fn slide_event(chat_room: &str, page: Uuid, slide: &Image) -> PaintEvent {
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        actions: vec![StreamActions {
+            actions: vec![StreamAction {
+                vertices: vec![StreamVertex {
+                    position: slide.center.to_vec(),
+                    color: vec![slide.size[0], slide.size[1], slide.rotation],
+                }],
+                text: None,
+                rectangle: None,
+                id: format!(
+                    "{}{}{}{}{}",
+                    SLIDE_MESSAGE, PAGE_SEPARATOR, page, HASH_SEPARATOR, slide.hash
+                ),
+            }],
+        }],
+        timestamp: Some(Timestamp::from(SystemTime::now())),
+        action_type: Some(StreamActionType::ActionRequest(true)),
+    }
+}
+
+pub(crate) fn from_slide_message(stream_action: &StreamAction) -> Option<(Uuid, Image)> {
+    let (page, hash) = stream_action
+        .id
+        .strip_prefix(SLIDE_MESSAGE)?
+        .strip_prefix(PAGE_SEPARATOR)?
+        .split_once(HASH_SEPARATOR)?;
+    let vertex = stream_action.vertices.first()?;
+    let [width, height, rotation] = to_array(&vertex.color)?;
+    Some((
+        Uuid::parse_str(page).ok()?,
+        Image {
+            center: to_array(&vertex.position)?,
+            size: [width, height],
+            rotation,
+            hash: hash.to_owned(),
+        },
+    ))
+}
+
+/// Carries chunk `index` of `count` of the image file with `hash`.
+pub(crate) fn image_chunk_event(
+    chat_room: &str,
+    hash: &str,
+    index: usize,
+    count: usize,
+    data: &[u8],
+) -> PaintEvent {
+    PaintEvent {
+        chat_room: chat_room.to_owned(),
+        actions: vec![StreamActions {
+            actions: vec![StreamAction {
+                vertices: vec![],
+                text: Some(StreamTextEntries {
+                    position: vec![index as f32, count as f32],
+                    text: utils::base64_encode(data),
+                    ..Default::default()
+                }),
+                rectangle: None,
+                id: format!("{}{}{}", IMAGE_MESSAGE, PAGE_SEPARATOR, hash),
+            }],
+        }],
+        timestamp: Some(Timestamp::from(SystemTime::now())),
+        action_type: Some(StreamActionType::ActionRequest(true)),
+    }
+}
+
+/// The image hash, chunk index and count and the chunk, when `stream_action`
+/// carries part of an image file.
+pub(crate) fn from_image_chunk(
+    stream_action: &StreamAction,
+) -> Option<(String, usize, usize, Vec<u8>)> {
+    let hash = stream_action
+        .id
+        .strip_prefix(IMAGE_MESSAGE)?
+        .strip_prefix(PAGE_SEPARATOR)?;
+    let text = stream_action.text.as_ref()?;
+    // Counts from the room are checked before anything is allocated for them.
+    let [index, count] = to_array(&text.position)?;
+    let whole = |value: f32| value.is_finite() && value >= 0.0 && value.fract() == 0.0;
+    if !whole(index) || !whole(count) || index >= count || count > images::MAX_CHUNKS as f32 {
+        return None;
+    }
+    Some((
+        hash.to_owned(),
+        index as usize,
+        count as usize,
+        utils::base64_decode(&text.text)?,
+    ))
+}
+
+fn to_array<const N: usize>(values: &[f32]) -> Option<[f32; N]> {
+    values.get(..N)?.try_into().ok()
+}
+
+impl WindowState {
+    pub(crate) fn send_paint_event(&self, request: PaintEvent) {
+        let stream_service = self.stream_client.clone();
+        tokio::spawn({
+            async move {
+                let client = stream_service.get_client().await;
+                if let Ok(mut client) = client {
+                    let _ = client.window_paint(request).await;
+                }
+            }
+        });
+    }
+
+    /// Shares a new version of an action the room already has. It is sent
+    /// under the same id, and receivers that know the id replace the action
+    /// where it is, so it keeps its place in paint order.
+    pub(crate) fn send_action_update(&self, action: &Action) {
+        self.send_paint_event(paint_event(
+            &self.room,
+            self.current_page_id(),
+            [action],
+            StreamActionType::ActionRequest(true),
+        ));
+    }
+
+    /// Sends `requests` one after the other, for events the room has to get
+    /// in order.
+    pub(crate) fn send_paint_events(&self, requests: impl IntoIterator<Item = PaintEvent>) {
+        let requests: Vec<PaintEvent> = requests.into_iter().collect();
+        let stream_service = self.stream_client.clone();
+        tokio::spawn({
+            async move {
+                let client = stream_service.get_client().await;
+                if let Ok(mut client) = client {
+                    for request in requests {
+                        let _ = client.window_paint(request).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Shares the committed actions of the current page the room doesn't have
+    /// yet.
+    pub(crate) fn send_unsent_actions(&mut self) {
+        let unsent_actions: Vec<Action> = self
+            .scene
+            .committed()
+            .filter(|action| !self.events_id.contains(&action.id))
+            .cloned()
+            .collect();
+        if !unsent_actions.is_empty() {
+            // Image files go first, so the images can be drawn as they arrive.
+            let hashes: Vec<String> = unsent_actions
+                .iter()
+                .filter_map(|action| match &action.action_type {
+                    ActionType::Image(image) => Some(image.hash.clone()),
+                    _ => None,
+                })
+                .collect();
+            let mut requests = self.unshared_image_events(&hashes);
+            requests.push(paint_event(
+                &self.room,
+                self.current_page_id(),
+                &unsent_actions,
+                StreamActionType::ActionRequest(true),
+            ));
+            self.send_paint_events(requests);
+        }
+
+        self.events_id
+            .extend(unsent_actions.iter().map(|action| action.id));
+        self.actions_changed = false;
+    }
+
+    /// Subscribes to the stream of `room` in place of the previous room.
+    /// What arrives is applied on the UI thread by `apply_incoming_events`.
+    /// Each time the subscription opens the others are asked for their
+    /// boards, since what they sent while it was closed was missed.
+    pub(crate) fn subscribe(&mut self) {
+        if let Some(subscription) = self.subscription.take() {
+            subscription.abort();
+        }
+        let stream_service = self.stream_client.clone();
+        let incoming = self.incoming_sender.clone();
+        let room = self.room.clone();
+        let sync_request = sync_request_event(&self.room, self.client_id);
+        self.subscription = Some(tokio::spawn(async move {
+            loop {
+                if let Ok(mut client) = stream_service.get_client().await {
+                    if let Ok(response) = client.subscribe_paint(subscription_event(&room)).await {
+                        let mut events = response.into_inner();
+                        let _ = client.window_paint(sync_request.clone()).await;
+                        while let Ok(Some(event)) = events.message().await {
+                            // The board is gone.
+                            if incoming.send(event).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+            }
+        }));
+    }
+
+    /// Applies the events received from the room since the last frame.
+    pub(crate) fn apply_incoming_events(&mut self) {
+        while let Ok(event) = self.incoming_events.try_recv() {
+            self.apply_paint_event(event);
+        }
+    }
+
+    /// Shares the board with the clients whose sync requests nobody else
+    /// answered in time.
+    pub(crate) fn answer_sync_requests(&mut self) {
+        let now = Instant::now();
+        let due: Vec<Uuid> = self
+            .sync_answers
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(client, _)| *client)
+            .collect();
+        if due.is_empty() {
+            return;
+        }
+
+        let mut requests = Vec::new();
+        for client in due {
+            self.sync_answers.remove(&client);
+            requests.push(sync_answer_event(&self.room, client));
+        }
+        requests.extend(self.page_events(false));
+        self.send_paint_events(requests);
+    }
+
+    /// Applies a `PaintEvent` received from the room stream to the local board.
+    /// Actions whose id is already in `events_id` were either sent by us or
+    /// already applied, so their echoes are ignored. Actions on a page this
+    /// board doesn't have yet add it at the end.
+    pub fn apply_paint_event(&mut self, event: PaintEvent) {
+        if event.chat_room != self.room {
+            return;
+        }
+
+        let deleted = matches!(event.action_type, Some(StreamActionType::IsDeleted(true)));
+
+        for stream_action in event.actions.iter().flat_map(|actions| &actions.actions) {
+            if let Some((page, index, turned)) = from_page_message(stream_action) {
+                self.apply_page_event(page, index, deleted, turned);
+                continue;
+            }
+            if let Some(client) = from_sync_request(stream_action) {
+                if client != self.client_id && !deleted {
+                    let at = Instant::now() + sync_answer_delay(self.client_id);
+                    self.sync_answers.entry(client).or_insert(at);
+                }
+                continue;
+            }
+            if let Some(client) = from_sync_answer(stream_action) {
+                self.sync_answers.remove(&client);
+                continue;
+            }
+            if let Some((page, paper)) = from_paper_message(stream_action) {
+                self.apply_paper_event(page, paper);
+                continue;
+            }
+            if let Some((page, slide)) = from_slide_message(stream_action) {
+                self.apply_slide_event(page, slide);
+                continue;
+            }
+            if let Some((hash, index, count, data)) = from_image_chunk(stream_action) {
+                // The room has the file now, whoever sent it.
+                self.shared_images.insert(hash.clone());
+                self.images.receive_chunk(&hash, index, count, data);
+                continue;
+            }
+            let Some((page, action)) = from_stream_action(stream_action) else {
+                continue;
+            };
+
+            if deleted {
+                // Forget the id too, so a redo of the same action is applied again.
+                self.events_id.remove(&action.id);
+                if let Some(scene) = self.page_scene_mut(page) {
+                    scene.remove(&action.id);
+                }
+            } else if self.events_id.contains(&action.id) {
+                // An update, or the echo of what was sent. Text being typed
+                // here is left alone, and what was removed here stays removed.
+                if self.editing_text == Some(action.id) {
+                    continue;
+                }
+                if let Some(scene) = self.page_scene_mut(page) {
+                    if scene.contains(&action.id) {
+                        scene.insert(action);
+                    }
+                }
+            } else {
+                if self.page_scene_mut(page).is_none() {
+                    self.insert_page(self.pages.len(), page);
+                }
+                self.events_id.insert(action.id);
+                if let Some(scene) = self.page_scene_mut(page) {
+                    scene.insert(action);
+                }
+            }
+        }
+
+        self.window.request_redraw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn only_action(event: &PaintEvent) -> &StreamAction {
+        let [actions] = event.actions.as_slice() else {
+            panic!("expected a single action");
+        };
+        let [action] = actions.actions.as_slice() else {
+            panic!("expected a single action");
+        };
+        action
+    }
+
+    /// Sends `action_type` on `page` and reads it back, checking the id and
+    /// page came along.
+    fn round_trip(action_type: ActionType, page: Uuid) -> ActionType {
+        let action = Action {
+            id: Uuid::new_v4(),
+            action_type,
+        };
+        let (received_page, received) =
+            from_stream_action(&to_stream_action(&action, page)).unwrap();
+        assert_eq!(received_page, page);
+        assert_eq!(received.id, action.id);
+        assert_eq!(
+            serde_json::to_value(&received.action_type).unwrap(),
+            serde_json::to_value(&action.action_type).unwrap()
+        );
+        received.action_type
+    }
+
+    #[test]
+    fn round_trips_every_action_type() {
+        let page = Uuid::new_v4();
+        let color = [0.1, 0.2, 0.3, 1.0];
+        let fill = [1.0, 0.5, 0.0, 0.5];
+        for join in [LineJoin::Round, LineJoin::Miter, LineJoin::Bevel] {
+            let stroke = Stroke {
+                color,
+                width: 3.0,
+                points: vec![[0.0, 0.0], [0.1, 0.2], [0.2, 0.2], [0.3, 0.0]],
+                pressure: vec![0.25, 0.75],
+                join,
+            };
+            let ActionType::Stroke(received) = round_trip(ActionType::Stroke(stroke), page) else {
+                panic!("expected a stroke");
+            };
+            assert_eq!(received.join, join);
+        }
+        round_trip(
+            ActionType::Text(TextEntries {
+                position: [120.0, 48.0],
+                color: [10, 20, 30, 255],
+                text: "سلام".to_owned(),
+                pending: false,
+                bounds: Rect {
+                    x: 120.0,
+                    y: 48.0,
+                    width: 60.0,
+                    height: 24.0,
+                },
+                font_size: 24,
+            }),
+            page,
+        );
+        round_trip(
+            ActionType::Shapes(Rectangle {
+                first: [-0.5, -0.5],
+                last: [0.5, 0.25],
+                color,
+                fill,
+                outline_width: 2.0,
+                corner_radius: 8.0,
+            }),
+            page,
+        );
+        round_trip(
+            ActionType::Ellipse(Ellipse {
+                first: [-0.5, -0.5],
+                last: [0.5, 0.25],
+                color,
+                fill,
+                outline_width: 2.0,
+            }),
+            page,
+        );
+        for heads in [ArrowHeads::None, ArrowHeads::End, ArrowHeads::Both] {
+            round_trip(
+                ActionType::Line(Line {
+                    first: [-0.5, 0.0],
+                    last: [0.5, 0.5],
+                    color,
+                    width: 4.0,
+                    heads,
+                }),
+                page,
+            );
+        }
+        round_trip(
+            ActionType::Polygon(Polygon {
+                points: vec![[0.0, 0.5], [-0.5, -0.5], [0.5, -0.5]],
+                color,
+                fill,
+                outline_width: 1.5,
+            }),
+            page,
+        );
+        round_trip(
+            ActionType::Image(Image {
+                center: [0.25, -0.25],
+                size: [640.0, 480.0],
+                rotation: 0.5,
+                hash: "ab12".to_owned(),
+            }),
+            page,
+        );
+    }
+
+    #[test]
+    fn actions_on_the_first_page_have_bare_ids() {
+        let action = Action {
+            id: Uuid::new_v4(),
+            action_type: ActionType::Line(Line {
+                first: [0.0, 0.0],
+                last: [1.0, 1.0],
+                color: [0.0, 0.0, 0.0, 1.0],
+                width: 1.0,
+                heads: ArrowHeads::None,
+            }),
+        };
+        let stream_action = to_stream_action(&action, Uuid::nil());
+        assert_eq!(stream_action.id, action.id.to_string());
+        round_trip(action.action_type, Uuid::nil());
+    }
+
+    #[test]
+    fn reads_strokes_from_older_clients() {
+        let id = Uuid::new_v4();
+        let stream_action = StreamAction {
+            vertices: [[0.0, 0.0], [0.3, 0.6]]
+                .into_iter()
+                .map(|point| StreamVertex {
+                    position: point.to_vec(),
+                    color: vec![1.0, 0.0, 0.0, 1.0],
+                })
+                .collect(),
+            text: None,
+            rectangle: None,
+            id: id.to_string(),
+        };
+        let (page, action) = from_stream_action(&stream_action).unwrap();
+        assert!(page.is_nil());
+        assert_eq!(action.id, id);
+        let ActionType::Stroke(stroke) = action.action_type else {
+            panic!("expected a stroke");
+        };
+        assert_eq!(stroke.width, 1.0);
+        assert_eq!(stroke.join, LineJoin::Round);
+        assert_eq!(stroke.points.len(), 4);
+        assert_eq!(stroke.points[3], [0.3, 0.6]);
+    }
+
+    #[test]
+    fn round_trips_page_messages() {
+        let page = Uuid::new_v4();
+        let turned = page_event("room", page, 3, StreamActionType::ActionRequest(true));
+        assert_eq!(
+            from_page_message(only_action(&turned)),
+            Some((page, 3, true))
+        );
+        let announced = page_announcement("room", page, 2);
+        assert_eq!(
+            from_page_message(only_action(&announced)),
+            Some((page, 2, false))
+        );
+        assert!(from_stream_action(only_action(&turned)).is_none());
+    }
+
+    #[test]
+    fn round_trips_sync_requests() {
+        let client = Uuid::new_v4();
+        let event = sync_request_event("room", client);
+        assert_eq!(from_sync_request(only_action(&event)), Some(client));
+        assert!(from_page_message(only_action(&event)).is_none());
+    }
+
+    #[test]
+    fn resets_every_shared_action_and_page() {
+        let [first, second] = [Uuid::new_v4(), Uuid::new_v4()];
+        let stroke = Action {
+            id: Uuid::new_v4(),
+            action_type: ActionType::Stroke(Stroke {
+                color: [0.0; 4],
+                width: 1.0,
+                points: vec![[0.0, 0.0], [0.1, 0.1], [0.2, 0.1], [0.3, 0.0]],
+                pressure: vec![],
+                join: LineJoin::Round,
+            }),
+        };
+        let line = Action {
+            id: Uuid::new_v4(),
+            action_type: ActionType::Line(Line {
+                first: [0.0, 0.0],
+                last: [0.5, 0.5],
+                color: [0.0; 4],
+                width: 2.0,
+                heads: ArrowHeads::None,
+            }),
+        };
+        let events = board_reset_events(
+            "room",
+            [
+                (first, vec![&stroke, &line]),
+                (second, vec![]),
+                (Uuid::new_v4(), vec![]),
+            ],
+        );
+        assert!(events
+            .iter()
+            .all(|event| event.action_type == Some(StreamActionType::IsDeleted(true))));
+
+        let [actions, pages @ ..] = events.as_slice() else {
+            panic!("expected the actions first");
+        };
+        let deleted: Vec<(Uuid, Uuid)> = actions
+            .actions
+            .iter()
+            .flat_map(|actions| &actions.actions)
+            .filter_map(from_stream_action)
+            .map(|(page, action)| (page, action.id))
+            .collect();
+        assert_eq!(deleted, [(first, stroke.id), (first, line.id)]);
+
+        // Later pages go first, so the indices stay right as they are removed.
+        let pages: Vec<(Uuid, usize)> = pages
+            .iter()
+            .filter_map(|event| from_page_message(only_action(event)))
+            .map(|(page, index, _)| (page, index))
+            .collect();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[1], (second, 1));
+        assert_eq!(pages[2], (first, 0));
+    }
+
+    #[test]
+    fn round_trips_sync_answers() {
+        let client = Uuid::new_v4();
+        let event = sync_answer_event("room", client);
+        assert_eq!(from_sync_answer(only_action(&event)), Some(client));
+        assert!(from_sync_request(only_action(&event)).is_none());
+        assert!(from_sync_answer(only_action(&sync_request_event("room", client))).is_none());
+    }
+
+    #[test]
+    fn answers_sync_requests_within_the_window() {
+        for _ in 0..100 {
+            assert!(sync_answer_delay(Uuid::new_v4()) < SYNC_ANSWER_WINDOW);
+        }
+    }
+
+    #[test]
+    fn round_trips_paper_messages() {
+        let page = Uuid::new_v4();
+        let paper = Paper {
+            kind: PaperKind::Calligraphy,
+            color: [0.9, 0.85, 0.7, 1.0],
+        };
+        let event = paper_event("room", page, &paper);
+        assert_eq!(from_paper_message(only_action(&event)), Some((page, paper)));
+    }
+
+    #[test]
+    fn round_trips_slide_messages() {
+        let page = Uuid::new_v4();
+        let slide = Image {
+            center: [0.0, 0.0],
+            size: [1920.0, 1080.0],
+            rotation: 0.0,
+            hash: "cd34".to_owned(),
+        };
+        let event = slide_event("room", page, &slide);
+        let (received_page, received) = from_slide_message(only_action(&event)).unwrap();
+        assert_eq!(received_page, page);
+        assert_eq!(
+            serde_json::to_value(&received).unwrap(),
+            serde_json::to_value(&slide).unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trips_image_chunks() {
+        let data = [0, 1, 2, 254, 255];
+        let event = image_chunk_event("room", "ef56", 1, 3, &data);
+        assert_eq!(
+            from_image_chunk(only_action(&event)),
+            Some(("ef56".to_owned(), 1, 3, data.to_vec()))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_chunk_counts() {
+        let chunk = |index: f32, count: f32| StreamAction {
+            text: Some(StreamTextEntries {
+                position: vec![index, count],
+                text: utils::base64_encode(b"data"),
+                ..Default::default()
+            }),
+            id: format!("{}{}{}", IMAGE_MESSAGE, PAGE_SEPARATOR, "ef56"),
+            ..Default::default()
+        };
+        assert!(from_image_chunk(&chunk(0.0, 1.0)).is_some());
+        for (index, count) in [
+            (1.0, 1.0),
+            (0.5, 2.0),
+            (-1.0, 2.0),
+            (0.0, f32::NAN),
+            (0.0, f32::INFINITY),
+            (0.0, images::MAX_CHUNKS as f32 + 1.0),
+        ] {
+            assert!(from_image_chunk(&chunk(index, count)).is_none());
+        }
+    }
+}
@@ -0,0 +1,521 @@
+use crate::{
+    curve, export, shapes,
+    structs::{ActionType, Ellipse, Line, LineJoin, Polygon, Rectangle, Stroke, Vertex},
+    vector::{add, cross, direction, length, normal, normalize, scale, sub},
+};
+use std::f32::consts::PI;
+
+/// Angle covered by one triangle of a round join or cap.
+const ARC_STEP: f32 = PI / 12.0;
+/// Longest miter, relative to half the stroke width, before a miter join is
+/// beveled. The default miter limit of SVG, which the exports leave as is.
+const MITER_LIMIT: f32 = 4.0;
+/// Points closer than this, in board pixels, are merged.
+const MIN_SEGMENT: f32 = 0.01;
+/// Triangles in each rounded corner of a rectangle.
+const CORNER_STEPS: usize = 8;
+/// Points around an ellipse.
+const ELLIPSE_STEPS: usize = 64;
+
+/// Turns a stroke into a triangle list. The curves are flattened at the
+/// resolution of a board of `board_size`, and widths are in its pixels, so
+/// the line keeps the same thickness whatever the aspect ratio. Pressure makes
+/// each piece a trapezoid between the widths of its ends. Turns are joined as
+/// the stroke asks and both ends are rounded.
+pub(crate) fn stroke_triangles(stroke: &Stroke, board_size: [f32; 2]) -> Vec<Vertex> {
+    let color = stroke.color;
+    let mut points: Vec<([f32; 2], [f32; 4], f32)> = Vec::new();
+    for (point, width) in curve::flatten(stroke, board_size) {
+        if points
+            .last()
+            .is_some_and(|(last, _, _)| length(sub(point, *last)) < MIN_SEGMENT)
+        {
+            continue;
+        }
+        points.push((point, color, width / 2.0));
+    }
+
+    let mut mesh = Mesh {
+        board_size,
+        vertices: Vec::new(),
+    };
+
+    match points.as_slice() {
+        [] => {}
+        [(point, color, half_width)] => mesh.fan(*point, *half_width, 0.0, 2.0 * PI, *color),
+        [(first, first_color, first_half_width), .., (last, last_color, last_half_width)] => {
+            for pair in points.windows(2) {
+                let ((start, color, start_half_width), (end, _, end_half_width)) =
+                    (pair[0], pair[1]);
+                mesh.segment(start, end, start_half_width, end_half_width, color);
+            }
+
+            for triple in points.windows(3) {
+                let (point, color, half_width) = triple[1];
+                let incoming = direction(triple[0].0, point);
+                let outgoing = direction(point, triple[2].0);
+                mesh.join(point, incoming, outgoing, half_width, stroke.join, color);
+            }
+
+            let start_direction = direction(points[1].0, *first);
+            let end_direction = direction(points[points.len() - 2].0, *last);
+            mesh.fan(
+                *first,
+                *first_half_width,
+                angle(start_direction) - PI / 2.0,
+                PI,
+                *first_color,
+            );
+            mesh.fan(
+                *last,
+                *last_half_width,
+                angle(end_direction) - PI / 2.0,
+                PI,
+                *last_color,
+            );
+        }
+    }
+
+    mesh.vertices
+}
+
+/// Turns a rectangle into a triangle list: the fill, then the outline on top.
+/// The outline is centred on the edge of the shape and its corners follow the
+/// corner radius; square rectangles keep square outline corners.
+pub(crate) fn rectangle_triangles(rectangle: &Rectangle, board_size: [f32; 2]) -> Vec<Vertex> {
+    let first = export::ndc_to_board(rectangle.first, board_size);
+    let last = export::ndc_to_board(rectangle.last, board_size);
+    let center = scale(add(first, last), 0.5);
+    let half = [
+        (last[0] - first[0]).abs() / 2.0,
+        (last[1] - first[1]).abs() / 2.0,
+    ];
+    let radius = rectangle.corner_radius.clamp(0.0, half[0].min(half[1]));
+
+    let mut mesh = Mesh {
+        board_size,
+        vertices: Vec::new(),
+    };
+
+    if rectangle.fill[3] > 0.0 {
+        mesh.fill_around(
+            center,
+            &rounded_outline(center, half, radius),
+            rectangle.fill,
+        );
+    }
+
+    let half_width = rectangle.outline_width / 2.0;
+    if half_width > 0.0 && rectangle.color[3] > 0.0 {
+        let outer_radius = if radius > 0.0 {
+            radius + half_width
+        } else {
+            0.0
+        };
+        let outer = rounded_outline(center, add(half, [half_width, half_width]), outer_radius);
+        let inner_half = [
+            (half[0] - half_width).max(0.0),
+            (half[1] - half_width).max(0.0),
+        ];
+        let inner_radius = (radius - half_width).clamp(0.0, inner_half[0].min(inner_half[1]));
+        let inner = rounded_outline(center, inner_half, inner_radius);
+        mesh.ring(&outer, &inner, rectangle.color);
+    }
+
+    mesh.vertices
+}
+
+/// Turns an ellipse into a triangle list: the fill, then the outline on top,
+/// centred on the edge.
+pub(crate) fn ellipse_triangles(ellipse: &Ellipse, board_size: [f32; 2]) -> Vec<Vertex> {
+    let (center, radii) = shapes::ellipse_box(ellipse, board_size);
+    let mut mesh = Mesh {
+        board_size,
+        vertices: Vec::new(),
+    };
+
+    if ellipse.fill[3] > 0.0 {
+        let outline = shapes::ellipse_points(center, radii, ELLIPSE_STEPS);
+        mesh.fill_around(center, &outline, ellipse.fill);
+    }
+
+    let half_width = ellipse.outline_width / 2.0;
+    if half_width > 0.0 && ellipse.color[3] > 0.0 {
+        let outer =
+            shapes::ellipse_points(center, add(radii, [half_width, half_width]), ELLIPSE_STEPS);
+        let inner_radii = [
+            (radii[0] - half_width).max(0.0),
+            (radii[1] - half_width).max(0.0),
+        ];
+        let inner = shapes::ellipse_points(center, inner_radii, ELLIPSE_STEPS);
+        mesh.ring(&outer, &inner, ellipse.color);
+    }
+
+    mesh.vertices
+}
+
+/// Turns a line into a triangle list: a round-capped bar and its arrow heads.
+pub(crate) fn line_triangles(line: &Line, board_size: [f32; 2]) -> Vec<Vertex> {
+    let ([start, end], heads) = shapes::line_geometry(line, board_size);
+    let half_width = line.width / 2.0;
+    let mut mesh = Mesh {
+        board_size,
+        vertices: Vec::new(),
+    };
+
+    if length(sub(end, start)) >= MIN_SEGMENT {
+        mesh.segment(start, end, half_width, half_width, line.color);
+        let along = angle(direction(start, end));
+        mesh.fan(start, half_width, along + PI / 2.0, PI, line.color);
+        mesh.fan(end, half_width, along - PI / 2.0, PI, line.color);
+    } else {
+        mesh.fan(start, half_width, 0.0, 2.0 * PI, line.color);
+    }
+    for [tip, left, right] in heads {
+        mesh.triangle(tip, left, right, line.color);
+    }
+
+    mesh.vertices
+}
+
+/// Turns a polygon into a triangle list: the fill, then the outline on top
+/// with round joins. Concave polygons are filled by ear clipping.
+pub(crate) fn polygon_triangles(polygon: &Polygon, board_size: [f32; 2]) -> Vec<Vertex> {
+    let mut points: Vec<[f32; 2]> = Vec::with_capacity(polygon.points.len());
+    for point in &polygon.points {
+        let point = export::ndc_to_board(*point, board_size);
+        if points
+            .last()
+            .is_none_or(|last| length(sub(point, *last)) >= MIN_SEGMENT)
+        {
+            points.push(point);
+        }
+    }
+    while points.len() > 1 && length(sub(points[0], points[points.len() - 1])) < MIN_SEGMENT {
+        points.pop();
+    }
+
+    let mut mesh = Mesh {
+        board_size,
+        vertices: Vec::new(),
+    };
+
+    if polygon.fill[3] > 0.0 {
+        for [a, b, c] in triangulate(&points) {
+            mesh.triangle(points[a], points[b], points[c], polygon.fill);
+        }
+    }
+
+    let half_width = polygon.outline_width / 2.0;
+    if half_width > 0.0 && polygon.color[3] > 0.0 && points.len() > 1 {
+        let count = points.len();
+        for index in 0..count {
+            let (previous, point, next) = (
+                points[(index + count - 1) % count],
+                points[index],
+                points[(index + 1) % count],
+            );
+            mesh.segment(point, next, half_width, half_width, polygon.color);
+            mesh.join(
+                point,
+                direction(previous, point),
+                direction(point, next),
+                half_width,
+                LineJoin::Round,
+                polygon.color,
+            );
+        }
+    }
+
+    mesh.vertices
+}
+
+/// Triangles for any shape action; strokes, text and images give none.
+pub(crate) fn shape_triangles(action_type: &ActionType, board_size: [f32; 2]) -> Vec<Vertex> {
+    match action_type {
+        ActionType::Shapes(rectangle) => rectangle_triangles(rectangle, board_size),
+        ActionType::Ellipse(ellipse) => ellipse_triangles(ellipse, board_size),
+        ActionType::Line(line) => line_triangles(line, board_size),
+        ActionType::Polygon(polygon) => polygon_triangles(polygon, board_size),
+        ActionType::Stroke(_) | ActionType::Text(_) | ActionType::Image(_) => Vec::new(),
+    }
+}
+
+/// Splits a simple polygon into triangles by ear clipping, as indices into
+/// `points`. Self-intersecting leftovers are closed with a fan.
+fn triangulate(points: &[[f32; 2]]) -> Vec<[usize; 3]> {
+    let signed_area = (0..points.len())
+        .map(|index| cross(points[index], points[(index + 1) % points.len()]))
+        .sum::<f32>();
+    let orientation = signed_area.signum();
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(2));
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&index| {
+            let [a, b, c] = [
+                remaining[(index + count - 1) % count],
+                remaining[index],
+                remaining[(index + 1) % count],
+            ];
+            let turn = cross(sub(points[b], points[a]), sub(points[c], points[b]));
+            turn * orientation > 0.0
+                && remaining.iter().all(|&other| {
+                    other == a
+                        || other == b
+                        || other == c
+                        || !triangle_contains([points[a], points[b], points[c]], points[other])
+                })
+        });
+        let Some(index) = ear else {
+            break;
+        };
+        triangles.push([
+            remaining[(index + count - 1) % count],
+            remaining[index],
+            remaining[(index + 1) % count],
+        ]);
+        remaining.remove(index);
+    }
+
+    for index in 1..remaining.len().saturating_sub(1) {
+        triangles.push([remaining[0], remaining[index], remaining[index + 1]]);
+    }
+    triangles
+}
+
+fn triangle_contains([a, b, c]: [[f32; 2]; 3], point: [f32; 2]) -> bool {
+    let sides = [
+        cross(sub(b, a), sub(point, a)),
+        cross(sub(c, b), sub(point, b)),
+        cross(sub(a, c), sub(point, c)),
+    ];
+    sides.iter().all(|side| *side >= 0.0) || sides.iter().all(|side| *side <= 0.0)
+}
+
+/// Points around a rectangle of `half` extents with corners rounded by
+/// `radius`, clockwise on screen. The count doesn't depend on the radius, so
+/// outlines of different sizes can be stitched point by point.
+pub(crate) fn rounded_outline(center: [f32; 2], half: [f32; 2], radius: f32) -> Vec<[f32; 2]> {
+    let corners = [
+        ([1.0, 1.0], 0.0),
+        ([-1.0, 1.0], PI / 2.0),
+        ([-1.0, -1.0], PI),
+        ([1.0, -1.0], PI * 1.5),
+    ];
+
+    let mut points = Vec::with_capacity(corners.len() * (CORNER_STEPS + 1));
+    for (sign, start) in corners {
+        let corner = [
+            center[0] + sign[0] * (half[0] - radius),
+            center[1] + sign[1] * (half[1] - radius),
+        ];
+        for step in 0..=CORNER_STEPS {
+            let angle = start + PI / 2.0 * step as f32 / CORNER_STEPS as f32;
+            points.push(add(corner, scale([angle.cos(), angle.sin()], radius)));
+        }
+    }
+    points
+}
+
+struct Mesh {
+    board_size: [f32; 2],
+    vertices: Vec<Vertex>,
+}
+
+impl Mesh {
+    fn triangle(&mut self, a: [f32; 2], b: [f32; 2], c: [f32; 2], color: [f32; 4]) {
+        for point in [a, b, c] {
+            self.vertices.push(Vertex {
+                position: [
+                    point[0] / self.board_size[0] * 2.0 - 1.0,
+                    1.0 - point[1] / self.board_size[1] * 2.0,
+                ],
+                color,
+            });
+        }
+    }
+
+    /// A bar from `start` to `end`, tapering between the two half widths.
+    fn segment(
+        &mut self,
+        start: [f32; 2],
+        end: [f32; 2],
+        start_half_width: f32,
+        end_half_width: f32,
+        color: [f32; 4],
+    ) {
+        let normal = normal(direction(start, end));
+        let start_offset = scale(normal, start_half_width);
+        let end_offset = scale(normal, end_half_width);
+        self.triangle(
+            add(start, start_offset),
+            sub(start, start_offset),
+            add(end, end_offset),
+            color,
+        );
+        self.triangle(
+            sub(start, start_offset),
+            sub(end, end_offset),
+            add(end, end_offset),
+            color,
+        );
+    }
+
+    /// Fills the convex area enclosed by `outline` with a fan from `center`.
+    fn fill_around(&mut self, center: [f32; 2], outline: &[[f32; 2]], color: [f32; 4]) {
+        for index in 0..outline.len() {
+            let next = outline[(index + 1) % outline.len()];
+            self.triangle(center, outline[index], next, color);
+        }
+    }
+
+    /// Fills the band between two closed outlines with the same point count.
+    fn ring(&mut self, outer: &[[f32; 2]], inner: &[[f32; 2]], color: [f32; 4]) {
+        for index in 0..outer.len() {
+            let next = (index + 1) % outer.len();
+            self.triangle(outer[index], inner[index], outer[next], color);
+            self.triangle(inner[index], inner[next], outer[next], color);
+        }
+    }
+
+    /// A circular sector around `center`, starting at `start` and turning by `sweep`.
+    fn fan(&mut self, center: [f32; 2], radius: f32, start: f32, sweep: f32, color: [f32; 4]) {
+        let steps = (sweep.abs() / ARC_STEP).ceil().max(1.0) as usize;
+        let point = |step: usize| {
+            let angle = start + sweep * step as f32 / steps as f32;
+            add(center, scale([angle.cos(), angle.sin()], radius))
+        };
+        for step in 0..steps {
+            self.triangle(center, point(step), point(step + 1), color);
+        }
+    }
+
+    /// Fills the gap left on the outer side of a turn between two segments.
+    fn join(
+        &mut self,
+        point: [f32; 2],
+        incoming: [f32; 2],
+        outgoing: [f32; 2],
+        half_width: f32,
+        join: LineJoin,
+        color: [f32; 4],
+    ) {
+        let turn = wrap_angle(angle(outgoing) - angle(incoming));
+        if turn.abs() < 1e-4 {
+            return;
+        }
+
+        let outer = -turn.signum();
+        let from = scale(normal(incoming), outer * half_width);
+        let to = scale(normal(outgoing), outer * half_width);
+
+        let miter_length = 1.0 / (turn / 2.0).cos();
+        match join {
+            LineJoin::Round => self.fan(point, half_width, angle(from), turn, color),
+            // A turn all the way back has no miter; its length is infinite,
+            // or negative once rounded.
+            LineJoin::Miter
+                if miter_length.is_finite()
+                    && miter_length > 0.0
+                    && miter_length <= MITER_LIMIT =>
+            {
+                let miter = add(
+                    point,
+                    scale(normalize(add(from, to)), half_width * miter_length),
+                );
+                self.triangle(point, add(point, from), miter, color);
+                self.triangle(point, miter, add(point, to), color);
+            }
+            LineJoin::Miter | LineJoin::Bevel => {
+                self.triangle(point, add(point, from), add(point, to), color)
+            }
+        }
+    }
+}
+
+fn angle(a: [f32; 2]) -> f32 {
+    a[1].atan2(a[0])
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::Polyline;
+
+    const BOARD_SIZE: [f32; 2] = [800.0, 600.0];
+
+    /// A stroke through `points`, given in board pixels.
+    fn stroke(points: &[[f32; 2]], join: LineJoin) -> Stroke {
+        let mut polyline = Polyline::default();
+        for point in points {
+            polyline
+                .points
+                .push(export::board_to_ndc(*point, BOARD_SIZE));
+        }
+        Stroke::from_polyline(&polyline, [0.0, 0.0, 0.0, 1.0], 10.0, join)
+    }
+
+    #[test]
+    fn joins_a_right_angle_as_asked() {
+        let corner = [[100.0, 100.0], [300.0, 100.0], [300.0, 300.0]];
+        let bevel = stroke_triangles(&stroke(&corner, LineJoin::Bevel), BOARD_SIZE);
+        let miter = stroke_triangles(&stroke(&corner, LineJoin::Miter), BOARD_SIZE);
+        let round = stroke_triangles(&stroke(&corner, LineJoin::Round), BOARD_SIZE);
+        assert_eq!(bevel.len() % 3, 0);
+        assert_eq!(miter.len(), bevel.len() + 3);
+        assert!(round.len() > miter.len());
+    }
+
+    #[test]
+    fn bevels_miters_past_the_limit() {
+        let hairpin = [[100.0, 100.0], [300.0, 100.0], [100.0, 120.0]];
+        let bevel = stroke_triangles(&stroke(&hairpin, LineJoin::Bevel), BOARD_SIZE);
+        let miter = stroke_triangles(&stroke(&hairpin, LineJoin::Miter), BOARD_SIZE);
+        assert_eq!(miter.len(), bevel.len());
+    }
+
+    #[test]
+    fn bevels_a_turn_all_the_way_back() {
+        let reversal = [[100.0, 100.0], [300.0, 100.0], [100.0, 100.0]];
+        let bevel = stroke_triangles(&stroke(&reversal, LineJoin::Bevel), BOARD_SIZE);
+        let miter = stroke_triangles(&stroke(&reversal, LineJoin::Miter), BOARD_SIZE);
+        assert_eq!(miter.len(), bevel.len());
+        assert!(miter
+            .iter()
+            .all(|vertex| vertex.position.iter().all(|value| value.abs() <= 1.5)));
+    }
+
+    #[test]
+    fn draws_a_dot_as_a_circle() {
+        let dot = stroke(&[[100.0, 100.0]], LineJoin::Round);
+        let vertices = stroke_triangles(&dot, BOARD_SIZE);
+        assert_eq!(vertices.len(), 3 * (2.0 * PI / ARC_STEP).ceil() as usize);
+    }
+
+    #[test]
+    fn triangulates_concave_polygons() {
+        let points = [
+            [0.0, 0.0],
+            [20.0, 0.0],
+            [20.0, 10.0],
+            [10.0, 10.0],
+            [10.0, 20.0],
+            [0.0, 20.0],
+        ];
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), points.len() - 2);
+        let area: f32 = triangles
+            .iter()
+            .map(|[a, b, c]| {
+                cross(sub(points[*b], points[*a]), sub(points[*c], points[*a])).abs() / 2.0
+            })
+            .sum();
+        assert!((area - 300.0).abs() < 1e-3, "{area}");
+    }
+}
@@ -1,7 +1,17 @@
 use dioxus::desktop::tao;
 use egui::{Color32, Key as KeyEgui};
+use std::time::Duration;
 use tao::keyboard::Key;
 
+/// Lightest pressure a stroke point can have, so fast or light lines stay visible.
+pub(crate) const MIN_PRESSURE: f32 = 0.25;
+/// Simulated pressure of the first point, so mouse strokes taper in.
+pub(crate) const INITIAL_PRESSURE: f32 = 0.5;
+/// Pointer speed, in pixels per millisecond, at which simulated pressure bottoms out.
+const MAX_INK_SPEED: f32 = 3.0;
+/// How far simulated pressure moves toward its target on each new point.
+const PRESSURE_SMOOTHING: f32 = 0.3;
+
 pub(crate) fn convert_to_buffer(color: Color32) -> [f32; 4] {
     [
         color.r().into(),
@@ -29,3 +39,88 @@ pub(crate) fn egui_key(key: Key) -> Option<KeyEgui> {
         _ => None,
     }
 }
+
+/// True when the first strongly directional character of `text` is Persian,
+/// Arabic or Hebrew.
+pub(crate) fn is_rtl(text: &str) -> bool {
+    text.chars()
+        .find(|char| char.is_alphabetic())
+        .is_some_and(|char| {
+            matches!(char as u32,
+                0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF)
+        })
+}
+
+pub(crate) fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let triple = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(triple >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes padded base64 as written by `base64_encode`; `None` on anything
+/// else.
+pub(crate) fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for chunk in encoded.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&byte| byte == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut triple = 0u32;
+        for &byte in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&letter| letter == byte)?;
+            triple = triple << 6 | value as u32;
+        }
+        triple <<= 6 * padding;
+        bytes.extend(&triple.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+/// Simulates pen pressure for input devices without it: the faster the
+/// pointer moves, the thinner the line. Eased from `previous` so the width
+/// changes gradually.
+pub(crate) fn velocity_pressure(previous: f32, distance: f32, elapsed: Duration) -> f32 {
+    let milliseconds = elapsed.as_secs_f32() * 1000.0;
+    if milliseconds <= 0.0 {
+        return previous;
+    }
+
+    let speed = distance / milliseconds;
+    let target = (1.0 - speed / MAX_INK_SPEED).clamp(MIN_PRESSURE, 1.0);
+    previous + (target - previous) * PRESSURE_SMOOTHING
+}
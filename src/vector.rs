@@ -0,0 +1,43 @@
+//! Arithmetic on 2D vectors and points, stored as `[x, y]`.
+
+pub(crate) fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+pub(crate) fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+pub(crate) fn scale(a: [f32; 2], factor: f32) -> [f32; 2] {
+    [a[0] * factor, a[1] * factor]
+}
+
+pub(crate) fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+pub(crate) fn cross(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+pub(crate) fn length(a: [f32; 2]) -> f32 {
+    a[0].hypot(a[1])
+}
+
+pub(crate) fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    length(sub(a, b))
+}
+
+pub(crate) fn normalize(a: [f32; 2]) -> [f32; 2] {
+    scale(a, 1.0 / length(a))
+}
+
+/// Unit vector pointing from `from` to `to`.
+pub(crate) fn direction(from: [f32; 2], to: [f32; 2]) -> [f32; 2] {
+    normalize(sub(to, from))
+}
+
+/// `a` turned a quarter turn counterclockwise, in a y-up frame.
+pub(crate) fn normal(a: [f32; 2]) -> [f32; 2] {
+    [-a[1], a[0]]
+}
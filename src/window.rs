@@ -1,44 +1,53 @@
 #![allow(deprecated)]
 use crate::{
     app::{DOUBLE_CLICK_DISTANCE, DOUBLE_CLICK_THRESHOLD},
-    structs::{Action, ActionType, Rectangle, Vertex},
-    utils, TextEntries, WindowState,
+    board_file::{BoardFileCommand, BoardMetadata},
+    camera::{self, Camera},
+    caret::TextCaret,
+    curve,
+    eraser::{self, EraserMode},
+    export::{self, ExportFormat, PngExportOptions},
+    geometry,
+    images::ImageStore,
+    pages::{self, Page, PageCommand},
+    paper::{self, Paper, PaperKind, PaperUniform},
+    recognition::{self, PenRest, RecognitionMode},
+    render::{self, BoardBatches, BoardPipelines},
+    scene::Scene,
+    selection::{Selection, SelectionMode},
+    session::Session,
+    shapes::{self, ShapeKind},
+    slides::SlideImport,
+    smoothing::{self, Stabilizer, StabilizerMode},
+    structs::{Action, ActionType, Background, LineJoin, Polyline, ShapeStyle, Stroke},
+    sync, utils, TextEntries, WindowState,
 };
 use dioxus::desktop::tao::{self};
 use egui::{
     include_image, Align2, Event as EventEgui, Image, ImageButton, Key as KeyEgui, RawInput,
 };
 use egui_wgpu::{Renderer, ScreenDescriptor};
-use glyphon::{
-    Attrs, Buffer, Cache, Color, Family, FontSystem, Metrics, Shaping, SwashCache, TextArea,
-    TextAtlas, TextBounds, TextRenderer, Viewport,
-};
-use prost_types::Timestamp;
+use glyphon::{Buffer, Cache, Color, SwashCache, TextArea, TextAtlas, TextBounds, Viewport};
 use std::{
     borrow::BorrowMut,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     sync::Arc,
-    time::{Instant, SystemTime},
+    time::Instant,
 };
 use stream::desktop::{
-    manager::GrpcConnectionManager,
-    stream::{
-        paint_event::ActionType as StreamActionType, PaintEvent, StreamAction, StreamActions,
-        StreamRect, StreamRectangle, StreamTextEntries, StreamVertex,
-    },
+    manager::GrpcConnectionManager, stream::paint_event::ActionType as StreamActionType,
 };
 use tao::{
     dpi::{PhysicalPosition, PhysicalSize},
-    event::{ElementState, MouseButton, WindowEvent},
+    event::{ElementState, MouseButton, TouchPhase, WindowEvent},
     keyboard::Key,
     window::Window,
 };
 use uuid::Uuid;
 use wgpu::{
-    self, util::DeviceExt, vertex_attr_array, CompositeAlphaMode, DeviceDescriptor, FragmentState,
-    Instance, InstanceDescriptor, MultisampleState, PipelineCompilationOptions, PresentMode,
-    PrimitiveState, RequestAdapterOptions, ShaderModuleDescriptor, StoreOp, SurfaceConfiguration,
-    TextureFormat, TextureUsages, VertexBufferLayout,
+    self, util::DeviceExt, CompositeAlphaMode, DeviceDescriptor, Instance, InstanceDescriptor,
+    PresentMode, RequestAdapterOptions, StoreOp, SurfaceConfiguration, TextureFormat,
+    TextureUsages,
 };
 
 impl WindowState {
@@ -90,31 +99,27 @@ impl WindowState {
                         )));
                 }
 
-                if self.mouse_pressed {
-                    let x = position.x as f32 / self.size.width as f32 * 2.0 - 1.0;
-                    let y = -(position.y as f32 / self.size.height as f32 * 2.0 - 1.0);
+                let world = self.world_position(*position);
+                if self.pan_to(*position) {
+                    window.request_redraw();
+                } else if self.mouse_pressed {
                     if self.create_rect {
-                        if self.shape_positions.is_empty() {
-                            self.shape_positions.push(Vertex {
-                                position: [x, y],
-                                color: self.current_color,
-                            });
-                        } else {
-                            if self.shape_positions.len() > 1 {
-                                self.shape_positions.pop();
-                            }
-                            self.shape_positions.push(Vertex {
-                                position: [x, y],
-                                color: self.current_color,
-                            });
-                        }
+                        self.drag_shape(world);
+                    } else if self.eraser.is_some() {
+                        self.erase_to(world);
+                    } else if self.selection.is_some() {
+                        self.drag_select(world);
                     } else {
-                        self.current_stroke.push(Vertex {
-                            position: [x, y],
-                            color: self.current_color,
-                        });
+                        self.push_stroke_point(world, None);
                     }
 
+                    window.request_redraw();
+                } else if self.create_rect && !self.shape_positions.is_empty() {
+                    // The next corner of a free polygon follows the pointer.
+                    self.drag_shape(world);
+                    window.request_redraw();
+                } else if self.eraser.is_some() {
+                    // Keeps the eraser outline under the pointer.
                     window.request_redraw();
                 }
                 true
@@ -144,6 +149,11 @@ impl WindowState {
                     modifiers: self.raw_input.modifiers,
                 });
 
+                if self.pan_button(*button, pressed) {
+                    window.request_redraw();
+                    return true;
+                }
+
                 if (*button == MouseButton::Right && *state == ElementState::Pressed)
                     || (cfg!(target_os = "macos")
                         && *button == MouseButton::Left
@@ -169,198 +179,91 @@ impl WindowState {
                         }
                     }
 
-                    if double_click_detected {
-                        for (i, text_entry) in self.texts.iter_mut().enumerate() {
-                            let bounds = &text_entry.bounds;
-                            if position.x >= bounds.x as f64
-                                && position.x <= (bounds.x + bounds.width) as f64
-                                && position.y >= bounds.y as f64
-                                && position.y <= (bounds.y + bounds.height) as f64
-                            {
-                                self.editing_text_index = Some(i);
-                                self.start_typing = true;
-                                text_entry.pending = true;
-                                window.request_redraw();
-
-                                break;
-                            }
-                        }
-                    }
+                    let world = self.world_position(position);
+                    let board_size = self.board_size();
+                    let hit_text = if double_click_detected {
+                        self.scene
+                            .iter()
+                            .rev()
+                            .filter(|action| matches!(action.action_type, ActionType::Text(_)))
+                            .find(|action| {
+                                geometry::touches(
+                                    action,
+                                    [world.x as f32, world.y as f32],
+                                    0.0,
+                                    board_size,
+                                    &self.camera,
+                                    &mut self.font_system,
+                                )
+                            })
+                            .map(|action| action.id)
+                    } else {
+                        None
+                    };
 
                     self.last_click_time = Some(now);
                     self.last_click_position = Some(position);
 
-                    if self.start_typing && self.editing_text_index.is_none() {
-                        self.start_typing = false;
-                        if let Some(text) = self.texts.last_mut() {
-                            text.pending = false;
-                            self.actions.push(Action {
-                                action_type: ActionType::Text(text.clone()),
-                                id: Uuid::new_v4(),
-                            });
+                    let world = [world.x as f32, world.y as f32];
+                    if let Some(id) = hit_text {
+                        self.commit_text();
+                        if let Some(text_entry) = self.scene.text_mut(&id) {
+                            text_entry.pending = true;
+                            self.text_caret = TextCaret::at(text_entry.text.len());
+                            self.editing_text = Some(id);
+                            self.start_typing = true;
+                            self.click_text(world, false);
+                        }
+                    } else if self.start_typing {
+                        // Clicks on the text being typed move the caret,
+                        // clicks elsewhere finish it.
+                        let extend = self.pressed_keys.contains(&Key::Shift);
+                        if !self.click_text(world, extend) {
+                            self.commit_text();
                         }
                     } else {
-                        self.start_typing = true;
-                        self.texts.push(TextEntries::null(
+                        let mut text_entry = TextEntries::null(
                             utils::normalized_to_rgba(self.current_color),
                             self.font_size,
-                        ));
-                        let position = self.last_cursor_position;
-                        let x = position.x as f32;
-                        let y = position.y as f32;
-                        if let Some(text) = self.texts.last_mut() {
-                            text.position = [x, y];
-                        }
+                        );
+                        text_entry.position = world;
+
+                        let id = Uuid::new_v4();
+                        self.scene.insert(Action {
+                            action_type: ActionType::Text(text_entry),
+                            id,
+                        });
+                        self.editing_text = Some(id);
+                        self.text_caret = TextCaret::default();
+                        self.start_typing = true;
                     }
+                    window.request_redraw();
                 }
-                if *button == MouseButton::Left {
+                // Clicks on the page strip turn pages instead of drawing.
+                if *button == MouseButton::Left && !(pressed && self.pointer_over_pages()) {
+                    let world = self.world_position(self.last_cursor_position);
                     if *state == ElementState::Pressed {
                         self.mouse_pressed = true;
-                        self.current_stroke = Vec::new();
+                        self.begin_stroke();
 
                         if self.pressed_keys.contains(&Key::Character("s")) {
                             self.create_rect = true;
                         }
-
-                        // if self.undo_button {
-                        //     self.actions_changed = false;
-                        //     if self.actions.len() == 1 {
-                        //         self.actions.clear();
-                        //         self.texts.clear();
-                        //         self.shapes.clear();
-                        //         return true;
-                        //     }
-                        //     if let Some(ref action) = self.actions.pop() {
-                        //         let request = PaintEvent {
-                        //             chat_room: "general".to_owned(),
-                        //             actions: {
-                        //                 let stream_action = match action {
-                        //                     Action {
-                        //                         action_type: ActionType::Stroke(vertices),
-                        //                         id,
-                        //                     } => StreamAction {
-                        //                         vertices: vertices
-                        //                             .iter()
-                        //                             .map(|vertex| StreamVertex {
-                        //                                 position: vertex.position.to_vec(),
-                        //                                 color: vertex.color.to_vec(),
-                        //                             })
-                        //                             .collect(),
-                        //                         text: None,
-                        //                         rectangle: None,
-                        //                         id: id.to_string(),
-                        //                     },
-                        //                     Action {
-                        //                         action_type: ActionType::Text(text_entries),
-                        //                         id,
-                        //                     } => StreamAction {
-                        //                         vertices: vec![],
-                        //                         text: Some(StreamTextEntries {
-                        //                             position: text_entries.position.to_vec(),
-                        //                             color: text_entries
-                        //                                 .color
-                        //                                 .iter()
-                        //                                 .map(|color| *color as f32)
-                        //                                 .collect(),
-                        //                             text: text_entries.text.clone(),
-                        //                             pending: text_entries.pending,
-                        //                             bounds: Some(StreamRect {
-                        //                                 x: text_entries.bounds.x,
-                        //                                 y: text_entries.bounds.y,
-                        //                                 width: text_entries.bounds.width,
-                        //                                 height: text_entries.bounds.height,
-                        //                             }),
-                        //                             font_size: text_entries.font_size,
-                        //                         }),
-                        //                         rectangle: None,
-                        //                         id: id.to_string(),
-                        //                     },
-                        //                     Action {
-                        //                         action_type: ActionType::Shapes(rectangle),
-                        //                         id,
-                        //                     } => StreamAction {
-                        //                         vertices: vec![],
-                        //                         text: None,
-                        //                         rectangle: Some(StreamRectangle {
-                        //                             first: rectangle.first.to_vec(),
-                        //                             last: rectangle.last.to_vec(),
-                        //                             color: rectangle.color.to_vec(),
-                        //                         }),
-                        //                         id: id.to_string(),
-                        //                     },
-                        //                 };s
-                        //                 vec![StreamActions {
-                        //                     actions: vec![stream_action],
-                        //                 }]
-                        //             },
-                        //             timestamp: Some(Timestamp::from(SystemTime::now())),
-                        //             action_type: Some(StreamActionType::IsDeleted(true)),
-                        //         };
-                        //         let stream_service = self.stream_client.clone();
-                        //         tokio::spawn({
-                        //             async move {
-                        //                 let client = stream_service.get_client().await;
-                        //                 if let Ok(mut client) = client {
-                        //                     let _ = client.window_paint(request).await;
-                        //                 }
-                        //             }
-                        //         });
-                        //         match action {
-                        //             Action {
-                        //                 action_type: ActionType::Stroke(_),
-                        //                 ..
-                        //             } => {
-                        //                 self.strokes.pop();
-                        //             }
-                        //             Action {
-                        //                 action_type: ActionType::Text(_),
-                        //                 ..
-                        //             } => {
-                        //                 self.texts.pop();
-                        //             }
-                        //             Action {
-                        //                 action_type: ActionType::Shapes(_),
-                        //                 ..
-                        //             } => {
-                        //                 self.shapes.pop();
-                        //             }
-                        //         }
-                        //     }
-                        //     window.request_redraw();
-                        //     return true;
-                        // }
+                        if self.create_rect {
+                            self.press_shape(world);
+                            window.request_redraw();
+                        } else if self.eraser.is_some() {
+                            self.begin_erase(world);
+                            window.request_redraw();
+                        } else if self.selection.is_some() {
+                            self.begin_select(world);
+                            window.request_redraw();
+                        }
                     } else {
                         self.mouse_pressed = false;
-                        if !self.current_stroke.is_empty() {
-                            self.strokes.push(self.current_stroke.clone());
-                            self.actions.push(Action {
-                                action_type: ActionType::Stroke(self.current_stroke.clone()),
-                                id: Uuid::new_v4(),
-                            });
-                            self.current_stroke.clear();
-                        }
-                        // if self.undo_button {
-                        //     self.undo_button = false;
-                        // }
-                        self.create_rect = false;
-
-                        if let (Some(first), Some(last)) =
-                            (self.shape_positions.first(), self.shape_positions.last())
-                        {
-                            let rectangle = Rectangle {
-                                first: first.position,
-                                last: last.position,
-                                color: self.current_color,
-                            };
-
-                            self.actions.push(Action {
-                                action_type: ActionType::Shapes(rectangle),
-                                id: Uuid::new_v4(),
-                            });
-                            self.shapes.push(rectangle);
-                        }
-
-                        self.shape_positions.clear();
+                        self.finish_stroke();
+                        self.end_select();
+                        self.finish_shape();
 
                         window.request_redraw();
                     }
@@ -382,211 +285,74 @@ impl WindowState {
                     ElementState::Pressed => {
                         self.pressed_keys.insert(event.logical_key.clone());
 
-                        if self.start_typing || self.editing_text_index.is_some() {
-                            if let Key::Character(char) = &event.logical_key {
-                                if let Some(text) = self.texts.last_mut() {
-                                    if text.pending {
-                                        text.text.push_str(char);
-                                        window.request_redraw();
-                                    }
-                                }
+                        if self.editing_text.is_some() {
+                            if self.text_key(&event.logical_key) {
+                                window.request_redraw();
                             }
-                            match event.logical_key {
-                                Key::Enter => {
-                                    self.start_typing = false;
-                                    self.editing_text_index = None;
-                                    if let Some(text) = self.texts.last_mut() {
-                                        text.pending = false;
-                                        self.actions.push(Action {
-                                            action_type: ActionType::Text(text.clone()),
-                                            id: Uuid::new_v4(),
-                                        });
-                                        self.actions_changed = true;
-                                    }
-                                    window.request_redraw();
-                                }
-                                Key::Delete => {
-                                    let text_entry = if let Some(index) = self.editing_text_index {
-                                        self.texts.get_mut(index)
-                                    } else {
-                                        self.texts.last_mut()
-                                    };
-                                    if let Some(entry) = text_entry {
-                                        entry.text.pop();
-                                        window.request_redraw();
-                                    }
-                                }
-                                Key::GoBack => {
-                                    self.start_typing = false;
-                                    self.editing_text_index = None;
-                                    if let Some(text) = self.texts.last_mut() {
-                                        text.pending = false;
-                                        self.actions.push(Action {
-                                            id: Uuid::new_v4(),
-                                            action_type: ActionType::Text(text.clone()),
-                                        });
-                                    }
-                                    window.request_redraw();
-                                }
-                                Key::Backspace => {
-                                    if self.editing_text_index.is_some() {
-                                        let editing_text = self.texts
-                                            [self.editing_text_index.unwrap()]
-                                        .borrow_mut();
-                                        if editing_text.pending
-                                            && editing_text.text.chars().count() > 0
-                                        {
-                                            editing_text.text = editing_text
-                                                .text
-                                                .chars()
-                                                .take(editing_text.text.chars().count() - 1)
-                                                .collect();
-                                            window.request_redraw();
-                                        }
-                                    } else if let Some(text) = self.texts.last_mut() {
-                                        if text.pending && text.text.chars().count() > 0 {
-                                            text.text = text
-                                                .text
-                                                .chars()
-                                                .take(text.text.chars().count() - 1)
-                                                .collect();
-                                            window.request_redraw();
-                                        }
-                                    }
-                                }
-                                _ => {}
+                        } else if self.pressed_keys.contains(&Key::Control)
+                            && (self.pressed_keys.contains(&Key::Character("v"))
+                                || self.pressed_keys.contains(&Key::Character("V")))
+                        {
+                            if let Err(e) = self.paste_image() {
+                                eprintln!("{:?}", e);
                             }
+                            return true;
+                        } else if self.pressed_keys.contains(&Key::Control)
+                            && (self.pressed_keys.contains(&Key::Character("y"))
+                                || (self.pressed_keys.contains(&Key::Shift)
+                                    && (self.pressed_keys.contains(&Key::Character("z"))
+                                        || self.pressed_keys.contains(&Key::Character("Z")))))
+                        {
+                            self.redo();
+                            return true;
                         } else if self.pressed_keys.contains(&Key::Control)
                             && self.pressed_keys.contains(&Key::Character("z"))
                         {
-                            self.actions_changed = false;
-                            if let Some(ref action) = self.actions.pop() {
-                                let request = PaintEvent {
-                                    chat_room: "general".to_owned(),
-                                    actions: {
-                                        let stream_action = match action {
-                                            Action {
-                                                action_type: ActionType::Stroke(vertices),
-                                                id,
-                                            } => StreamAction {
-                                                vertices: vertices
-                                                    .iter()
-                                                    .map(|vertex| StreamVertex {
-                                                        position: vertex.position.to_vec(),
-                                                        color: vertex.color.to_vec(),
-                                                    })
-                                                    .collect(),
-                                                text: None,
-                                                rectangle: None,
-                                                id: id.to_string(),
-                                            },
-                                            Action {
-                                                action_type: ActionType::Text(text_entries),
-                                                id,
-                                            } => StreamAction {
-                                                vertices: vec![],
-                                                text: Some(StreamTextEntries {
-                                                    position: text_entries.position.to_vec(),
-                                                    color: text_entries
-                                                        .color
-                                                        .iter()
-                                                        .map(|color| *color as f32)
-                                                        .collect(),
-                                                    text: text_entries.text.clone(),
-                                                    pending: text_entries.pending,
-                                                    bounds: Some(StreamRect {
-                                                        x: text_entries.bounds.x,
-                                                        y: text_entries.bounds.y,
-                                                        width: text_entries.bounds.width,
-                                                        height: text_entries.bounds.height,
-                                                    }),
-                                                    font_size: text_entries.font_size,
-                                                }),
-                                                rectangle: None,
-                                                id: id.to_string(),
-                                            },
-                                            Action {
-                                                action_type: ActionType::Shapes(rectangle),
-                                                id,
-                                            } => StreamAction {
-                                                vertices: vec![],
-                                                text: None,
-                                                rectangle: Some(StreamRectangle {
-                                                    first: rectangle.first.to_vec(),
-                                                    last: rectangle.last.to_vec(),
-                                                    color: rectangle.color.to_vec(),
-                                                }),
-                                                id: id.to_string(),
-                                            },
-                                        };
-
-                                        vec![StreamActions {
-                                            actions: vec![stream_action],
-                                        }]
-                                    },
-                                    timestamp: Some(Timestamp::from(SystemTime::now())),
-                                    action_type: Some(StreamActionType::IsDeleted(true)),
-                                };
-                                let stream_service = self.stream_client.clone();
-                                tokio::spawn({
-                                    async move {
-                                        let client = stream_service.get_client().await;
-                                        if let Ok(mut client) = client {
-                                            let _ = client.window_paint(request).await;
-                                        }
-                                    }
-                                });
-                                match action {
-                                    Action {
-                                        action_type: ActionType::Stroke(_),
-                                        ..
-                                    } => {
-                                        self.strokes.pop();
-                                    }
-                                    Action {
-                                        action_type: ActionType::Text(_),
-                                        ..
-                                    } => {
-                                        self.texts.pop();
-                                    }
-                                    Action {
-                                        action_type: ActionType::Shapes(_),
-                                        ..
-                                    } => {
-                                        self.shapes.pop();
-                                    }
-                                }
-                            }
-                            window.request_redraw();
+                            self.undo();
+                            return true;
+                        } else if self.nudge_selection(&event.logical_key)
+                            || self.polygon_key(&event.logical_key)
+                        {
                             return true;
                         }
                     }
                     ElementState::Released => {
                         self.pressed_keys.remove(&event.logical_key);
-                        self.create_rect = false;
 
-                        if let (Some(first), Some(last)) =
-                            (self.shape_positions.first(), self.shape_positions.last())
-                        {
-                            let rectangle = Rectangle {
-                                first: first.position,
-                                last: last.position,
-                                color: self.current_color,
-                            };
-
-                            self.actions.push(Action {
-                                action_type: ActionType::Shapes(rectangle),
-                                id: Uuid::new_v4(),
-                            });
-                            self.shapes.push(rectangle);
+                        if event.logical_key == Key::Character("s") {
+                            self.finish_shape();
                         }
-
-                        self.shape_positions.clear();
                     }
                     _ => (),
                 }
                 true
             }
+            WindowEvent::Touch(touch) => {
+                let force = touch.force.map(|force| force.normalized() as f32);
+                let location = self.world_position(touch.location);
+                match touch.phase {
+                    TouchPhase::Started if self.eraser.is_some() => self.begin_erase(location),
+                    TouchPhase::Moved if self.eraser.is_some() => self.erase_to(location),
+                    TouchPhase::Started if self.selection.is_some() => self.begin_select(location),
+                    TouchPhase::Moved if self.selection.is_some() => self.drag_select(location),
+                    TouchPhase::Started => {
+                        self.begin_stroke();
+                        self.push_stroke_point(location, force);
+                    }
+                    TouchPhase::Moved => self.push_stroke_point(location, force),
+                    _ => {
+                        self.finish_stroke();
+                        self.end_select();
+                    }
+                }
+                window.request_redraw();
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll(*delta);
+                window.request_redraw();
+                true
+            }
             WindowEvent::Resized(physical_size) => {
                 self.size = *physical_size;
                 self.resize(*physical_size);
@@ -600,7 +366,7 @@ impl WindowState {
         }
     }
 
-    pub async fn new(window: Arc<Window>, url: String) -> Result<Self, ()> {
+    pub async fn new(window: Arc<Window>, url: String, session: Session) -> Result<Self, ()> {
         let physical_size = window.inner_size();
         let scale_factor = window.scale_factor();
 
@@ -631,7 +397,7 @@ impl WindowState {
             usage: TextureUsages::RENDER_ATTACHMENT,
             format: swapchain_format,
             width: physical_size.width,
-            height: (physical_size.height as f32 * 0.8) as u32,
+            height: physical_size.height,
             present_mode: PresentMode::Fifo,
             alpha_mode: CompositeAlphaMode::Opaque,
             view_formats: vec![],
@@ -643,138 +409,71 @@ impl WindowState {
         egui_extras::install_image_loaders(&egui_ctx);
         surface.configure(&device, &surface_config);
 
-        let mut font_system = FontSystem::new();
-        font_system
-            .db_mut()
-            .load_font_data(include_bytes!("assets/vazir.ttf").to_vec());
+        let font_system = render::font_system();
         let swash_cache = SwashCache::new();
         let cache = Cache::new(&device);
         let viewport = Viewport::new(&device, &cache);
-        let mut atlas = TextAtlas::new(&device, &queue, &cache, swapchain_format);
-        let text_renderer =
-            TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
-
-        let shader = device.create_shader_module(egui_wgpu::wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: egui_wgpu::wgpu::ShaderSource::Wgsl(include_str!("shaders/main.wgsl").into()),
-        });
-
-        let pipeline_layout =
-            device.create_pipeline_layout(&egui_wgpu::wgpu::PipelineLayoutDescriptor {
-                label: Some("Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
-
-        let shader_shape = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("rect shader"),
-            source: egui_wgpu::wgpu::ShaderSource::Wgsl(include_str!("shaders/shape.wgsl").into()),
-        });
+        let atlas = TextAtlas::new(&device, &queue, &cache, swapchain_format);
+
+        let camera = Camera::default();
+        let camera_layout = render::camera_bind_group_layout(&device);
+        let (camera_buffer, camera_bind_group) = render::camera_binding(
+            &device,
+            &camera_layout,
+            camera.matrix([physical_size.width as f32, physical_size.height as f32]),
+        );
         let rectangle_shader =
-            device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
-                label: Some("rect pipline"),
-                layout: Some(&pipeline_layout),
-                vertex: egui_wgpu::wgpu::VertexState {
-                    module: &shader_shape,
-                    entry_point: Some("rectangle_vs"),
-                    compilation_options: PipelineCompilationOptions::default(),
-                    buffers: &[VertexBufferLayout {
-                        array_stride: size_of::<Vertex>() as egui_wgpu::wgpu::BufferAddress,
-                        step_mode: egui_wgpu::wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            egui_wgpu::wgpu::VertexAttribute {
-                                format: egui_wgpu::wgpu::VertexFormat::Float32x2,
-                                offset: 0,
-                                shader_location: 0,
-                            },
-                            egui_wgpu::wgpu::VertexAttribute {
-                                format: egui_wgpu::wgpu::VertexFormat::Float32x4,
-                                offset: std::mem::size_of::<[f32; 2]>()
-                                    as egui_wgpu::wgpu::BufferAddress,
-                                shader_location: 1,
-                            },
-                        ],
-                    }],
-                },
-                primitive: PrimitiveState {
-                    topology: egui_wgpu::wgpu::PrimitiveTopology::LineList,
-                    strip_index_format: None,
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                multisample: MultisampleState::default(),
-                fragment: Some(FragmentState {
-                    module: &shader_shape,
-                    entry_point: Some("fs_main"),
-                    compilation_options: PipelineCompilationOptions::default(),
-                    targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(egui_wgpu::wgpu::BlendState::ALPHA_BLENDING),
-                        write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
-                    })],
-                }),
-                multiview: None,
-                cache: None,
-            });
-
+            render::create_rectangle_pipeline(&device, surface_config.format, &camera_layout);
+        let shape_shader =
+            render::create_shape_pipeline(&device, surface_config.format, &camera_layout);
         let render_pipeline =
-            device.create_render_pipeline(&egui_wgpu::wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(&pipeline_layout),
-                vertex: egui_wgpu::wgpu::VertexState {
-                    module: &shader,
-                    entry_point: Some("vs_main"),
-                    buffers: &[egui_wgpu::wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<Vertex>()
-                            as egui_wgpu::wgpu::BufferAddress,
-                        step_mode: egui_wgpu::wgpu::VertexStepMode::Vertex,
-                        attributes: &vertex_attr_array![
-                            0 => Float32x2,
-                            1 => Float32x4
-                        ],
-                    }],
-                    compilation_options: PipelineCompilationOptions::default(),
-                },
-                fragment: Some(egui_wgpu::wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: Some("fs_main"),
-                    targets: &[Some(egui_wgpu::wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(egui_wgpu::wgpu::BlendState::ALPHA_BLENDING),
-                        write_mask: egui_wgpu::wgpu::ColorWrites::ALL,
-                    })],
-                    compilation_options: PipelineCompilationOptions::default(),
-                }),
-                primitive: egui_wgpu::wgpu::PrimitiveState {
-                    topology: egui_wgpu::wgpu::PrimitiveTopology::LineList,
-                    strip_index_format: None,
-                    ..Default::default()
-                },
-                depth_stencil: None,
-                multisample: egui_wgpu::wgpu::MultisampleState::default(),
-                multiview: None,
-                cache: None,
-            });
-
-        let vertex_buffer =
-            device.create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: &[],
-                usage: egui_wgpu::wgpu::BufferUsages::VERTEX
-                    | egui_wgpu::wgpu::BufferUsages::COPY_DST,
-            });
+            render::create_stroke_pipeline(&device, surface_config.format, &camera_layout);
+        let paper_layout = render::paper_bind_group_layout(&device);
+        let (paper_buffer, paper_bind_group) = render::paper_binding(
+            &device,
+            &paper_layout,
+            PaperUniform::new(&Paper::default(), camera::BOARD_SIZE),
+        );
+        let paper_pipeline = render::create_paper_pipeline(
+            &device,
+            surface_config.format,
+            &camera_layout,
+            &paper_layout,
+        );
+        let image_layout = render::image_bind_group_layout(&device);
+        let image_pipeline = render::create_image_pipeline(
+            &device,
+            surface_config.format,
+            &camera_layout,
+            &image_layout,
+        );
 
+        let (incoming_sender, incoming_events) = tokio::sync::mpsc::unbounded_channel();
         let mut render_self = Self {
             stream_client: Arc::new(client),
+            room: session.room.clone(),
+            client_id: Uuid::new_v4(),
+            incoming_events,
+            incoming_sender,
+            subscription: None,
+            sync_answers: HashMap::new(),
             device,
             events_id: HashSet::new(),
-            // undo_button: false,
-            shapes: Vec::new(),
             last_cursor_position: PhysicalPosition::new(0.0, 0.0),
             queue,
             scale_factor,
             surface,
-            actions: Vec::new(),
+            scene: Scene::new(),
+            pages: vec![Page::first()],
+            current_page: 0,
+            follow_pages: session.follow_pages,
+            pending_page: None,
+            images: ImageStore::default(),
+            shared_images: HashSet::new(),
+            image_textures: HashMap::new(),
+            thumbnail_textures: HashMap::new(),
+            pending_image_file: false,
+            pending_slides: None,
             pressed_keys: HashSet::new(),
             surface_config,
             font_system,
@@ -782,91 +481,291 @@ impl WindowState {
             swash_cache,
             viewport,
             atlas,
-            text_renderer,
-            texts: Vec::new(),
+            text_renderers: Vec::new(),
             create_rect: false,
             window,
             actions_changed: false,
             size: physical_size,
             mouse_pressed: false,
             render_pipeline,
-            vertex_buffer,
-            strokes: Vec::new(),
-            current_stroke: Vec::new(),
+            paper_pipeline,
+            paper_buffer,
+            paper_bind_group,
+            image_pipeline,
+            image_layout,
+            camera,
+            camera_buffer,
+            camera_bind_group,
+            pan_anchor: None,
+            current_stroke: Polyline::default(),
+            last_stroke_point: Instant::now(),
+            stabilizer: Stabilizer::default(),
+            stroke_width: 3.0,
+            stroke_join: LineJoin::default(),
+            shape_recognition: RecognitionMode::default(),
+            pen_rest: None,
+            eraser: None,
+            last_erase_position: None,
+            selection: None,
             current_color: [0.0, 0.0, 0.0, 1.0],
             start_typing: false,
             cursor_visible: false,
             cursor_timer: Instant::now(),
             last_click_time: None,
             last_click_position: None,
-            editing_text_index: None,
+            editing_text: None,
+            text_caret: TextCaret::default(),
             rectangle_shader: Some(rectangle_shader),
+            shape_shader: Some(shape_shader),
             shape_positions: Vec::new(),
+            shape_style: ShapeStyle::default(),
+            shape_kind: ShapeKind::default(),
             egui_renderer,
             show_modal_fonts: false,
+            show_modal_widths: false,
+            show_modal_shapes: false,
+            show_modal_view: false,
+            show_modal_paper: false,
+            show_modal_slides: false,
             show_modal_colors: false,
+            show_modal_export: false,
+            png_export_options: PngExportOptions::default(),
+            pending_export: None,
+            metadata: BoardMetadata::new(session.title().to_owned()),
+            pending_board_file: None,
+            redo_stack: Vec::new(),
+            undo_history: Vec::new(),
+            undo_button: false,
+            redo_button: false,
 
             color: include_image!("assets/color.png"),
             font: include_image!("assets/font.png"),
             rect: include_image!("assets/rect.png"),
-            // prev: include_image!("assets/prev.png"),
+            prev: include_image!("assets/prev.png"),
             raw_input,
             egui_context: egui_ctx,
         };
 
         let _ = Self::render(&mut render_self);
+        render_self.subscribe();
         Ok(render_self)
     }
 
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.surface_config.width = self.size.width;
-            self.surface_config.height = self.size.height;
-            self.surface.configure(&self.device, &self.surface_config);
-
-            let _ = self.render();
+    pub(crate) fn clear_board(&mut self) {
+        self.scene.clear();
+        self.pages = vec![Page::first()];
+        self.current_page = 0;
+        self.events_id.clear();
+        self.shared_images.clear();
+        self.sync_answers.clear();
+        self.redo_stack.clear();
+        self.undo_history.clear();
+        self.current_stroke = Polyline::default();
+        self.shape_positions.clear();
+        self.start_typing = false;
+        self.editing_text = None;
+        self.text_caret = TextCaret::default();
+        self.actions_changed = false;
+        if let Some(selection) = &mut self.selection {
+            *selection = Selection::new(selection.mode);
         }
     }
 
-    pub fn update(&mut self) -> Result<(), egui_wgpu::wgpu::SurfaceError> {
-        let mut text_areas: Vec<TextArea> = Vec::new();
-        let mut all_vertices = Vec::new();
+    /// Puts a new local action on top of the board; it is shared with the room
+    /// on the next update.
+    pub(crate) fn add_action(&mut self, action_type: ActionType) {
+        let id = Uuid::new_v4();
+        self.scene.insert(Action { id, action_type });
+        self.undo_history.push(id);
+        self.redo_stack.clear();
+        self.actions_changed = true;
+    }
 
-        let physical_width = (self.size.width as f64 * self.scale_factor) as f32;
-        let physical_height = (self.size.height as f64 * self.scale_factor) as f32;
+    /// Adds a point under `position` to the stroke being drawn. Without a
+    /// measured `force`, pressure is simulated from the pointer speed.
+    fn push_stroke_point(&mut self, position: PhysicalPosition<f64>, force: Option<f32>) {
+        let point = [position.x as f32, position.y as f32];
+        self.pen_rest
+            .get_or_insert_with(|| PenRest::new(point))
+            .update(point);
 
-        for action in &self.actions {
-            if let Action {
-                action_type: ActionType::Stroke(stroke),
-                ..
-            } = action
-            {
-                if stroke.len() >= 2 {
-                    for i in 0..(stroke.len() - 1) {
-                        all_vertices.push(stroke[i]);
-                        all_vertices.push(stroke[i + 1]);
-                    }
-                }
+        let Some([px, py]) = self.stabilizer.push(point) else {
+            return;
+        };
+        let [x, y] = export::board_to_ndc([px, py], self.board_size());
+        let now = Instant::now();
+
+        let pressure = match (force, self.current_stroke.points.last()) {
+            (Some(force), _) => force.clamp(utils::MIN_PRESSURE, 1.0),
+            (None, Some(last)) => {
+                let [last_x, last_y] = export::ndc_to_board(*last, self.board_size());
+                let distance = (px - last_x).hypot(py - last_y);
+                let previous = self.current_stroke.pressure.last().copied();
+                utils::velocity_pressure(
+                    previous.unwrap_or(utils::INITIAL_PRESSURE),
+                    distance,
+                    now - self.last_stroke_point,
+                )
             }
+            (None, None) => utils::INITIAL_PRESSURE,
+        };
+
+        self.last_stroke_point = now;
+        self.current_stroke.push([x, y], pressure);
+    }
+
+    fn begin_stroke(&mut self) {
+        self.current_stroke = Polyline::default();
+        self.stabilizer.reset();
+        self.pen_rest = None;
+    }
+
+    /// Commits the stroke being drawn, or the shape it is recognized as.
+    fn finish_stroke(&mut self) {
+        let held = self.pen_rest.take().is_some_and(|rest| rest.is_held());
+        if let Some(point) = self.stabilizer.release() {
+            let pressure = self.current_stroke.pressure.last().copied();
+            self.current_stroke.push(
+                export::board_to_ndc(point, self.board_size()),
+                pressure.unwrap_or(utils::INITIAL_PRESSURE),
+            );
+        }
+        if !self.current_stroke.is_empty() {
+            let mut polyline = std::mem::take(&mut self.current_stroke);
+            if self.stabilizer.options.smooth_on_release {
+                smoothing::smooth_stroke(&mut polyline, self.stabilizer.options.strength);
+            }
+
+            let recognize = match self.shape_recognition {
+                RecognitionMode::Off => false,
+                RecognitionMode::Auto => true,
+                RecognitionMode::Hold => held,
+            };
+            let shape = recognize
+                .then(|| {
+                    recognition::recognize(
+                        &polyline,
+                        self.current_color,
+                        self.stroke_width,
+                        self.board_size(),
+                    )
+                })
+                .flatten();
+            let action_type = shape.unwrap_or_else(|| {
+                ActionType::Stroke(curve::fit_stroke(
+                    &polyline,
+                    self.current_color,
+                    self.stroke_width,
+                    self.stroke_join,
+                    self.board_size(),
+                ))
+            });
+            self.add_action(action_type);
         }
+    }
 
-        if self.current_stroke.len() >= 2 {
-            for i in 0..(self.current_stroke.len() - 1) {
-                all_vertices.push(self.current_stroke[i]);
-                all_vertices.push(self.current_stroke[i + 1]);
+    /// Finishes the text being typed. Empty text is dropped, edits of text the
+    /// room already has are sent as updates.
+    pub(crate) fn commit_text(&mut self) {
+        self.start_typing = false;
+        self.text_caret = TextCaret::default();
+        let Some(id) = self.editing_text.take() else {
+            return;
+        };
+        let Some(text_entry) = self.scene.text_mut(&id) else {
+            return;
+        };
+        text_entry.pending = false;
+
+        if text_entry.text.is_empty() {
+            if let Some(action) = self.scene.remove(&id) {
+                if self.events_id.remove(&id) {
+                    self.send_paint_event(sync::paint_event(
+                        &self.room,
+                        self.current_page_id(),
+                        [&action],
+                        StreamActionType::IsDeleted(true),
+                    ));
+                }
+            }
+        } else if self.events_id.contains(&id) {
+            if let Some(action) = self.scene.get(&id) {
+                self.send_action_update(action);
             }
+        } else {
+            self.undo_history.push(id);
+            self.redo_stack.clear();
+            self.actions_changed = true;
         }
+    }
 
-        let vertex_data = bytemuck::cast_slice(&all_vertices);
-        self.vertex_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: vertex_data,
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            });
+    /// The latest action on the current page drawn by this client. Actions
+    /// of other participants are never undone from here.
+    fn last_own_action(&self) -> Option<Uuid> {
+        self.undo_history
+            .iter()
+            .rev()
+            .find(|id| {
+                self.scene
+                    .get(id)
+                    .is_some_and(|action| !action.is_pending())
+            })
+            .copied()
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        self.last_own_action().is_some()
+    }
+
+    /// Removes the latest action this client drew on the current page, tells
+    /// the room to delete it and keeps it so `redo` can restore it.
+    pub fn undo(&mut self) {
+        self.actions_changed = false;
+        let last_id = self.last_own_action();
+        if let Some(id) = last_id {
+            self.undo_history.retain(|own| *own != id);
+        }
+        if let Some(action) = last_id.and_then(|id| self.scene.remove(&id)) {
+            self.send_paint_event(sync::paint_event(
+                &self.room,
+                self.current_page_id(),
+                [&action],
+                StreamActionType::IsDeleted(true),
+            ));
+            self.redo_stack.push(action);
+        }
+        self.window.request_redraw();
+    }
+
+    /// Puts the last undone action back under its original id and shares it
+    /// with the room again.
+    pub fn redo(&mut self) {
+        if let Some(action) = self.redo_stack.pop() {
+            self.send_paint_event(sync::paint_event(
+                &self.room,
+                self.current_page_id(),
+                [&action],
+                StreamActionType::ActionRequest(true),
+            ));
+            self.events_id.insert(action.id);
+            self.undo_history.push(action.id);
+            self.scene.insert(action);
+        }
+        self.window.request_redraw();
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.surface_config.width = self.size.width;
+            self.surface_config.height = self.size.height;
+            self.surface.configure(&self.device, &self.surface_config);
 
+            let _ = self.render();
+        }
+    }
+
+    pub fn update(&mut self) -> Result<(), egui_wgpu::wgpu::SurfaceError> {
         const CURSOR_BLINK_INTERVAL: f32 = 0.5;
         if self.start_typing {
             let elapsed = self.cursor_timer.elapsed().as_secs_f32();
@@ -877,178 +776,134 @@ impl WindowState {
             }
         }
 
-        let mut buffers = Vec::new();
-        for text_entry in &self.texts {
-            let mut text_buffer = Buffer::new(
-                &mut self.font_system,
-                Metrics::new(
-                    text_entry.font_size as f32,
-                    text_entry.font_size as f32 * 0.1,
-                ),
-            );
-
-            text_buffer.set_size(
-                &mut self.font_system,
-                Some(physical_width),
-                Some(physical_height),
-            );
-            text_buffer.shape_until_scroll(&mut self.font_system, false);
-
-            let mut text = text_entry.text.clone();
-            if text_entry.pending && self.cursor_visible {
-                text.push('|');
-            }
-
-            let text = format!("\u{200E}\u{200C}{}", text);
-            text_buffer.set_text(
-                &mut self.font_system,
-                &text,
-                Attrs::new().family(Family::Name("Vazir")),
-                Shaping::Advanced,
-            );
-            text_buffer.shape_until_scroll(&mut self.font_system, false);
-            buffers.push(text_buffer);
+        if self.actions_changed && !self.start_typing {
+            self.send_unsent_actions();
         }
 
-        for (text_entry, buffer) in self.texts.iter().zip(buffers.iter()) {
-            let x = text_entry.position[0];
-            let y = text_entry.position[1];
-
-            let text_bounds = TextBounds {
-                left: 0,
-                top: 0,
-                right: self.size.width as i32,
-                bottom: self.size.height as i32,
-            };
+        Ok(())
+    }
 
-            let default_color = Color::rgba(
-                text_entry.color[0],
-                text_entry.color[1],
-                text_entry.color[2],
-                text_entry.color[3],
-            );
+    /// Runs the file dialogs the menus asked for during the last frame.
+    /// They block until closed, so this runs after the frame is presented
+    /// rather than while it holds the surface texture.
+    pub fn run_pending_dialogs(&mut self) {
+        if let Some(format) = self.pending_export.take() {
+            self.run_export(format);
+        }
+        if let Some(command) = self.pending_board_file.take() {
+            self.run_board_file_command(command);
+        }
+        if std::mem::take(&mut self.pending_image_file) {
+            self.open_image();
+        }
+        if let Some(import) = self.pending_slides.take() {
+            self.run_slide_import(import);
+        }
+    }
 
-            text_areas.push(TextArea {
-                buffer,
-                left: x,
-                top: y,
-                scale: 1.0,
-                bounds: text_bounds,
-                default_color,
-                custom_glyphs: &[],
-            });
+    pub fn render(&mut self) -> Result<(), egui_wgpu::wgpu::SurfaceError> {
+        self.egui_context.begin_pass(self.raw_input.clone());
+        let mut overlay = self.selection_overlay();
+        overlay.extend(self.caret_overlay());
+        if self.eraser.is_some() {
+            let cursor = self.world_position(self.last_cursor_position);
+            overlay.extend(eraser::outline(
+                [cursor.x as f32, cursor.y as f32],
+                self.board_size(),
+            ));
         }
+        let target_size = [self.surface_config.width, self.surface_config.height];
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&self.camera.matrix(self.board_size())),
+        );
+        let page_paper = self.current_paper();
+        self.queue.write_buffer(
+            &self.paper_buffer,
+            0,
+            bytemuck::bytes_of(&PaperUniform::new(&page_paper, self.board_size())),
+        );
+        self.prepare_image_textures();
+
+        // Everything on the page in paint order, with the shape and stroke
+        // being drawn on top.
+        let board_size = self.board_size();
+        let preview = self.shape_preview();
+        let current_stroke = ActionType::Stroke(Stroke::from_polyline(
+            &self.current_stroke,
+            self.current_color,
+            self.stroke_width,
+            self.stroke_join,
+        ));
+        let board = BoardBatches::new(
+            self.pages[self.current_page].slide.as_ref(),
+            self.scene
+                .iter()
+                .map(|action| &action.action_type)
+                .chain(preview.as_ref())
+                .chain(std::iter::once(&current_stroke)),
+            |hash| self.image_textures.get(hash),
+            board_size,
+        );
 
-        if self.actions_changed && !self.start_typing {
-            let send_ids: HashSet<Uuid> = self.events_id.clone();
-            let unsent_actions: Vec<&Action> = self
-                .actions
+        // Glyphs can't be turned, so text follows the camera upright: its
+        // corner is placed through the camera and its size follows the zoom.
+        // Text is cut off at the edges of the board like everything else.
+        let (letterbox, camera) = (self.letterbox(), self.camera);
+        let [right, bottom] = letterbox.to_window(board_size);
+        let text_bounds = TextBounds {
+            left: letterbox.origin[0] as i32,
+            top: letterbox.origin[1] as i32,
+            right: right.ceil() as i32,
+            bottom: bottom.ceil() as i32,
+        };
+        render::reserve_text_renderers(
+            &mut self.text_renderers,
+            &board,
+            &mut self.atlas,
+            &self.device,
+        );
+        for (texts, text_renderer) in board.texts().zip(&mut self.text_renderers) {
+            let buffers: Vec<Buffer> = texts
                 .iter()
-                .filter(|action| !send_ids.contains(&action.id))
+                .map(|text_entry| {
+                    render::text_buffer(
+                        &mut self.font_system,
+                        text_entry,
+                        board_size[0],
+                        board_size[1],
+                    )
+                })
                 .collect();
-
-            let request = PaintEvent {
-                chat_room: "general".to_owned(),
-                actions: unsent_actions
-                    .iter()
-                    .map(|action| {
-                        let stream_action = match action {
-                            Action {
-                                action_type: ActionType::Stroke(vertices),
-                                id,
-                            } => StreamAction {
-                                vertices: vertices
-                                    .iter()
-                                    .map(|vertex| StreamVertex {
-                                        position: vertex.position.to_vec(),
-                                        color: vertex.color.to_vec(),
-                                    })
-                                    .collect(),
-                                text: None,
-                                rectangle: None,
-                                id: id.to_string(),
-                            },
-                            Action {
-                                action_type: ActionType::Text(text_entries),
-                                id,
-                            } => StreamAction {
-                                vertices: vec![],
-                                text: Some(StreamTextEntries {
-                                    position: text_entries.position.to_vec(),
-                                    color: text_entries
-                                        .color
-                                        .iter()
-                                        .map(|color| *color as f32)
-                                        .collect(),
-                                    text: text_entries.text.clone(),
-                                    pending: text_entries.pending,
-                                    bounds: Some(StreamRect {
-                                        x: text_entries.bounds.x,
-                                        y: text_entries.bounds.y,
-                                        width: text_entries.bounds.width,
-                                        height: text_entries.bounds.height,
-                                    }),
-                                    font_size: text_entries.font_size,
-                                }),
-                                rectangle: None,
-                                id: id.to_string(),
-                            },
-                            Action {
-                                action_type: ActionType::Shapes(rectangle),
-                                id,
-                            } => StreamAction {
-                                vertices: vec![],
-                                text: None,
-                                rectangle: Some(StreamRectangle {
-                                    first: rectangle.first.to_vec(),
-                                    last: rectangle.last.to_vec(),
-                                    color: rectangle.color.to_vec(),
-                                }),
-                                id: id.to_string(),
-                            },
-                        };
-
-                        StreamActions {
-                            actions: vec![stream_action],
-                        }
-                    })
-                    .collect(),
-                timestamp: Some(Timestamp::from(SystemTime::now())),
-                action_type: Some(StreamActionType::ActionRequest(true)),
-            };
-
-            let stream_service = self.stream_client.clone();
-            tokio::spawn({
-                async move {
-                    let client = stream_service.get_client().await;
-                    if let Ok(mut client) = client {
-                        let _ = client.window_paint(request).await;
-                    }
+            let text_areas = texts.iter().zip(&buffers).map(|(text_entry, buffer)| {
+                let [x, y] = letterbox.to_window(camera.to_screen(text_entry.position));
+                TextArea {
+                    buffer,
+                    left: x,
+                    top: y,
+                    scale: camera.zoom * letterbox.scale,
+                    bounds: text_bounds,
+                    default_color: Color::rgba(
+                        text_entry.color[0],
+                        text_entry.color[1],
+                        text_entry.color[2],
+                        text_entry.color[3],
+                    ),
+                    custom_glyphs: &[],
                 }
             });
-
-            for action in unsent_actions {
-                self.events_id.insert(action.id);
-            }
-
-            self.actions_changed = false;
+            let _ = text_renderer.prepare(
+                &self.device,
+                &self.queue,
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                text_areas,
+                &mut self.swash_cache,
+            );
         }
 
-        let _ = self.text_renderer.prepare(
-            &self.device,
-            &self.queue,
-            &mut self.font_system,
-            &mut self.atlas,
-            &self.viewport,
-            text_areas.clone(),
-            &mut self.swash_cache,
-        );
-
-        Ok(())
-    }
-
-    pub fn render(&mut self) -> Result<(), egui_wgpu::wgpu::SurfaceError> {
-        self.egui_context.begin_pass(self.raw_input.clone());
         let output = self.surface.get_current_texture()?;
         let view = output
             .texture
@@ -1071,7 +926,7 @@ impl WindowState {
                             view: &view,
                             resolve_target: None,
                             ops: egui_wgpu::wgpu::Operations {
-                                load: egui_wgpu::wgpu::LoadOp::Clear(egui_wgpu::wgpu::Color::WHITE),
+                                load: egui_wgpu::wgpu::LoadOp::Clear(camera::LETTERBOX_COLOR),
                                 store: egui_wgpu::wgpu::StoreOp::Store,
                             },
                         })],
@@ -1080,51 +935,45 @@ impl WindowState {
                         occlusion_query_set: None,
                     });
 
-            if let Some(rectangle_shader) = &self.rectangle_shader {
-                let mut temp_shapes = self.shapes.clone();
-
-                if self.create_rect {
-                    if let (Some(first), Some(last)) =
-                        (&self.shape_positions.first(), &self.shape_positions.last())
-                    {
-                        let rectangle = Rectangle {
-                            first: first.position,
-                            last: last.position,
-                            color: self.current_color,
-                        };
-
-                        temp_shapes.push(rectangle);
-                    }
-                }
-
-                let flattened_shapes: Vec<_> = temp_shapes
-                    .iter()
-                    .flat_map(|rect| rect.to_vertices())
-                    .collect();
-
-                let rectangle_vertex_buffer =
-                    self.device
-                        .create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
-                            label: Some("Rectangle Vertex Buffer"),
-                            contents: bytemuck::cast_slice(&flattened_shapes),
-                            usage: egui_wgpu::wgpu::BufferUsages::VERTEX,
-                        });
-
-                if rectangle_vertex_buffer.size() != 0 {
-                    render_pass.set_pipeline(rectangle_shader);
-                    render_pass.set_vertex_buffer(0, rectangle_vertex_buffer.slice(..));
-                    render_pass.draw(0..flattened_shapes.len() as u32, 0..1);
+            letterbox.clip_pass(&mut render_pass, target_size);
+
+            // The paper of the page, under everything on it and inside the
+            // bars cleared around the board.
+            let paper_vertices = self.camera.view_quad(self.board_size(), page_paper.color);
+            let paper_vertex_buffer =
+                self.device
+                    .create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
+                        label: Some("Paper Vertex Buffer"),
+                        contents: bytemuck::cast_slice(&paper_vertices),
+                        usage: egui_wgpu::wgpu::BufferUsages::VERTEX,
+                    });
+            render_pass.set_pipeline(&self.paper_pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.paper_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, paper_vertex_buffer.slice(..));
+            render_pass.draw(0..paper_vertices.len() as u32, 0..1);
+
+            if let Some(shape_shader) = &self.shape_shader {
+                let pipelines = BoardPipelines {
+                    images: &self.image_pipeline,
+                    shapes: shape_shader,
+                    strokes: &self.render_pipeline,
+                    camera: &self.camera_bind_group,
+                    letterbox: letterbox
+                        .board_area(target_size)
+                        .map(|area| (area, target_size)),
+                };
+                if let Err(e) = board.draw(
+                    &self.device,
+                    &mut render_pass,
+                    &pipelines,
+                    &self.text_renderers,
+                    &self.atlas,
+                    &self.viewport,
+                ) {
+                    eprintln!("{:?}", e);
                 }
             }
-
-            if self.vertex_buffer.size() > 0 {
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                render_pass.draw(
-                    0..(self.vertex_buffer.size() as u32 / std::mem::size_of::<Vertex>() as u32),
-                    0..1,
-                );
-            }
         }
 
         let screen_descriptor = ScreenDescriptor {
@@ -1137,6 +986,7 @@ impl WindowState {
         let menu_color = egui::Color32::from_hex("#5C5C5C").expect("unable to get color");
 
         let sized = vec![10, 12, 14, 16, 18, 20, 24, 28, 32];
+        let stroke_widths = [1.0, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0, 16.0, 24.0];
 
         if self.show_modal_colors {
             egui::Window::new("")
@@ -1195,6 +1045,338 @@ impl WindowState {
                 });
         }
 
+        if self.show_modal_widths {
+            egui::Window::new("")
+                .collapsible(false)
+                .order(egui::Order::Foreground)
+                .resizable(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+                .show(&self.egui_context, |ui| {
+                    ui.horizontal(|ui| {
+                        for width in stroke_widths {
+                            if ui.button(format!("{} px", width)).clicked() {
+                                self.stroke_width = width;
+                                self.show_modal_widths = false;
+                                self.window.request_redraw();
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let join = &mut self.stroke_join;
+                        ui.radio_value(join, LineJoin::Round, "Round joins");
+                        ui.radio_value(join, LineJoin::Miter, "Miter joins");
+                        ui.radio_value(join, LineJoin::Bevel, "Bevel joins");
+                    });
+                    let options = &mut self.stabilizer.options;
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut options.mode, StabilizerMode::Off, "Raw");
+                        ui.radio_value(&mut options.mode, StabilizerMode::LazyBrush, "Lazy brush");
+                        ui.radio_value(&mut options.mode, StabilizerMode::MovingAverage, "Average");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut options.strength, 0.0..=1.0).text("Strength"),
+                        );
+                        ui.checkbox(&mut options.smooth_on_release, "Smooth on release");
+                    });
+                });
+        }
+
+        if self.show_modal_shapes {
+            egui::Window::new("")
+                .collapsible(false)
+                .order(egui::Order::Foreground)
+                .resizable(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+                .show(&self.egui_context, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for kind in ShapeKind::ALL {
+                            if ui
+                                .selectable_label(
+                                    self.create_rect && self.shape_kind == kind,
+                                    kind.label(),
+                                )
+                                .clicked()
+                            {
+                                self.shape_kind = kind;
+                                self.shape_positions.clear();
+                                self.create_rect = true;
+                                self.eraser = None;
+                                self.selection = None;
+                            }
+                        }
+                    });
+                    let style = &mut self.shape_style;
+                    ui.horizontal_wrapped(|ui| {
+                        if ui
+                            .add(egui::Button::new("No fill").selected(style.fill[3] == 0.0))
+                            .clicked()
+                        {
+                            style.fill = [0.0; 4];
+                        }
+                        for color in [
+                            egui::Color32::from_rgb(255, 0, 0),
+                            egui::Color32::from_rgb(0, 255, 0),
+                            egui::Color32::from_rgb(0, 0, 255),
+                            egui::Color32::from_rgb(255, 255, 0),
+                            egui::Color32::from_rgb(255, 0, 255),
+                            egui::Color32::from_rgb(0, 255, 255),
+                            egui::Color32::from_rgb(0, 0, 0),
+                            egui::Color32::from_rgb(255, 255, 255),
+                        ] {
+                            let size = egui::Vec2::splat(30.0);
+                            if ui
+                                .add(egui::Button::new("").fill(color).min_size(size))
+                                .clicked()
+                            {
+                                let opacity = if style.fill[3] > 0.0 {
+                                    style.fill[3]
+                                } else {
+                                    1.0
+                                };
+                                style.fill = color.to_normalized_gamma_f32();
+                                style.fill[3] = opacity;
+                            }
+                        }
+                    });
+                    ui.add(egui::Slider::new(&mut style.fill[3], 0.0..=1.0).text("Fill opacity"));
+                    ui.add(egui::Slider::new(&mut style.outline_width, 0.0..=16.0).text("Outline"));
+                    ui.add(
+                        egui::Slider::new(&mut style.corner_radius, 0.0..=64.0)
+                            .text("Corner radius"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut style.sides, shapes::MIN_SIDES..=shapes::MAX_SIDES)
+                            .text("Polygon corners"),
+                    );
+                });
+        }
+
+        if self.show_modal_view {
+            let center = self.screen_center();
+            let mut fit = false;
+            egui::Window::new("")
+                .collapsible(false)
+                .order(egui::Order::Foreground)
+                .resizable(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+                .show(&self.egui_context, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("-").clicked() {
+                            self.camera.zoom_about(center, 1.0 / camera::ZOOM_STEP);
+                        }
+                        ui.label(format!("{:.0}%", self.camera.zoom * 100.0));
+                        if ui.button("+").clicked() {
+                            self.camera.zoom_about(center, camera::ZOOM_STEP);
+                        }
+                        fit = ui.button("Zoom to fit").clicked();
+                        if ui.button("Reset view").clicked() {
+                            self.camera = Camera::default();
+                        }
+                    });
+                    // Shown from -180° to 180° rather than the stored 0 to 360°.
+                    let rotation = self.camera.rotation.to_degrees();
+                    let mut degrees = if rotation > 180.0 {
+                        rotation - 360.0
+                    } else {
+                        rotation
+                    };
+                    if ui
+                        .add(egui::Slider::new(&mut degrees, -180.0..=180.0).text("Rotation"))
+                        .changed()
+                    {
+                        self.camera
+                            .rotate_about(center, (degrees - rotation).to_radians());
+                    }
+                });
+            if fit {
+                self.zoom_to_fit();
+            }
+        }
+
+        if self.show_modal_paper {
+            let mut paper = self.current_paper();
+            egui::Window::new("")
+                .collapsible(false)
+                .order(egui::Order::Foreground)
+                .resizable(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+                .show(&self.egui_context, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for kind in PaperKind::ALL {
+                            ui.selectable_value(&mut paper.kind, kind, kind.label());
+                        }
+                    });
+                    ui.horizontal_wrapped(|ui| {
+                        for color in paper::PAPER_COLORS {
+                            let [red, green, blue, _] = utils::normalized_to_rgba(color);
+                            let fill = egui::Color32::from_rgb(red, green, blue);
+                            let size = egui::Vec2::splat(30.0);
+                            if ui
+                                .add(
+                                    egui::Button::new("")
+                                        .fill(fill)
+                                        .min_size(size)
+                                        .selected(paper.color == color),
+                                )
+                                .clicked()
+                            {
+                                paper.color = color;
+                            }
+                        }
+                    });
+                });
+            self.set_paper(paper);
+        }
+
+        if self.show_modal_slides {
+            egui::Window::new("")
+                .collapsible(false)
+                .order(egui::Order::Foreground)
+                .resizable(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+                .show(&self.egui_context, |ui| {
+                    ui.label("One page per slide, in file name order");
+                    ui.horizontal(|ui| {
+                        if ui.button("Files").clicked() {
+                            self.pending_slides = Some(SlideImport::Files);
+                            self.show_modal_slides = false;
+                            self.window.request_redraw();
+                        }
+                        if ui.button("Folder").clicked() {
+                            self.pending_slides = Some(SlideImport::Folder);
+                            self.show_modal_slides = false;
+                            self.window.request_redraw();
+                        }
+                    });
+                });
+        }
+
+        if self.show_modal_export {
+            egui::Window::new("")
+                .collapsible(false)
+                .order(egui::Order::Foreground)
+                .resizable(false)
+                .anchor(Align2::CENTER_TOP, [0.0, 0.0])
+                .show(&self.egui_context, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(
+                            &mut self.png_export_options.background,
+                            Background::White,
+                            "Paper",
+                        );
+                        ui.radio_value(
+                            &mut self.png_export_options.background,
+                            Background::Transparent,
+                            "Transparent",
+                        );
+                        ui.checkbox(&mut self.png_export_options.crop_to_content, "Crop");
+                    });
+                    ui.horizontal(|ui| {
+                        for width in [1280, 1920, 3840] {
+                            if ui.button(format!("{} px", width)).clicked() {
+                                self.png_export_options.width = width;
+                                self.pending_export = Some(ExportFormat::Png);
+                                self.show_modal_export = false;
+                                self.window.request_redraw();
+                            }
+                        }
+                        if ui.button("SVG").clicked() {
+                            self.pending_export = Some(ExportFormat::Svg);
+                            self.show_modal_export = false;
+                            self.window.request_redraw();
+                        }
+                        if ui.button("PDF").clicked() {
+                            self.pending_export = Some(ExportFormat::Pdf);
+                            self.show_modal_export = false;
+                            self.window.request_redraw();
+                        }
+                    });
+                });
+        }
+
+        let pages = &self.pages;
+        let current_scene = &self.scene;
+        let current_page = self.current_page;
+        let images = &self.images;
+        let thumbnail_textures = &mut self.thumbnail_textures;
+        egui::Area::new(pages::PAGE_STRIP.into())
+            .anchor(Align2::CENTER_BOTTOM, [0.0, -8.0])
+            .movable(false)
+            .order(egui::Order::Foreground)
+            .show(&self.egui_context, |ui| {
+                egui::Frame::none()
+                    .fill(menu_color)
+                    .inner_margin(6.0)
+                    .rounding(4.0)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(current_page > 0, egui::Button::new("<"))
+                                .clicked()
+                            {
+                                self.pending_page = Some(PageCommand::Previous);
+                                self.window.request_redraw();
+                            }
+
+                            let thumbnail_size = egui::vec2(
+                                pages::THUMBNAIL_WIDTH,
+                                pages::THUMBNAIL_WIDTH * camera::BOARD_SIZE[1]
+                                    / camera::BOARD_SIZE[0],
+                            );
+                            for (index, page) in pages.iter().enumerate() {
+                                let (rect, response) =
+                                    ui.allocate_exact_size(thumbnail_size, egui::Sense::click());
+                                let scene = if index == current_page {
+                                    current_scene
+                                } else {
+                                    &page.scene
+                                };
+                                pages::paint_thumbnail(
+                                    ui.painter(),
+                                    rect,
+                                    page,
+                                    scene,
+                                    images,
+                                    thumbnail_textures,
+                                );
+                                let outline = if index == current_page {
+                                    egui::Stroke::new(2.0, egui::Color32::from_rgb(40, 120, 220))
+                                } else {
+                                    egui::Stroke::new(1.0, egui::Color32::GRAY)
+                                };
+                                ui.painter().rect_stroke(rect, 0.0, outline);
+                                if response.clicked() {
+                                    self.pending_page = Some(PageCommand::Show(index));
+                                    self.window.request_redraw();
+                                }
+                            }
+
+                            if ui
+                                .add_enabled(current_page + 1 < pages.len(), egui::Button::new(">"))
+                                .clicked()
+                            {
+                                self.pending_page = Some(PageCommand::Next);
+                                self.window.request_redraw();
+                            }
+                            ui.label(format!("{} / {}", current_page + 1, pages.len()));
+                            if ui.button("+").clicked() {
+                                self.pending_page = Some(PageCommand::Add);
+                                self.window.request_redraw();
+                            }
+                            if ui
+                                .add_enabled(pages.len() > 1, egui::Button::new("−"))
+                                .clicked()
+                            {
+                                self.pending_page = Some(PageCommand::Delete);
+                                self.window.request_redraw();
+                            }
+                            ui.checkbox(&mut self.follow_pages, "Follow");
+                        });
+                    });
+            });
+
+        let can_undo = self.can_undo();
         egui::Area::new("Header".into())
             .fixed_pos([0.0, 0.0])
             .movable(false)
@@ -1212,15 +1394,33 @@ impl WindowState {
                             ui.set_width(header_width);
 
                             ui.add_space(header_width * self.window.scale_factor() as f32 / 11.0);
-                            // let prev = ImageButton::new(
-                            //     Image::new(self.prev.clone())
-                            //         .fit_to_exact_size(egui::vec2(80.0, 30.0)),
-                            // )
-                            // .frame(false);
-                            // let prev_button: egui::Response = ui.add(prev);
-                            // if prev_button.clicked() {
-                            //     self.undo_button = true;
-                            // }
+                            let prev = ImageButton::new(
+                                Image::new(self.prev.clone())
+                                    .fit_to_exact_size(egui::vec2(80.0, 30.0)),
+                            )
+                            .frame(false);
+                            let prev_button: egui::Response = ui.add_enabled(can_undo, prev);
+                            if prev_button.clicked() {
+                                self.undo_button = true;
+                                self.window.request_redraw();
+                            }
+
+                            // The redo button is the undo arrow mirrored horizontally.
+                            let next = ImageButton::new(
+                                Image::new(self.prev.clone())
+                                    .uv(egui::Rect::from_min_max(
+                                        egui::pos2(1.0, 0.0),
+                                        egui::pos2(0.0, 1.0),
+                                    ))
+                                    .fit_to_exact_size(egui::vec2(80.0, 30.0)),
+                            )
+                            .frame(false);
+                            let next_button = ui.add_enabled(!self.redo_stack.is_empty(), next);
+                            if next_button.clicked() {
+                                self.redo_button = true;
+                                self.window.request_redraw();
+                            }
+                            ui.add_space(header_width * 0.03);
 
                             let sqaure = ImageButton::new(
                                 Image::new(self.rect.clone())
@@ -1229,7 +1429,78 @@ impl WindowState {
                             .frame(false);
                             let sqaure_button = ui.add(sqaure);
                             if sqaure_button.clicked() {
+                                self.shape_kind = ShapeKind::Rectangle;
+                                self.shape_positions.clear();
                                 self.create_rect = true;
+                                self.eraser = None;
+                                self.selection = None;
+                            }
+
+                            let shape_button =
+                                ui.add(egui::Button::new("Shape").min_size(egui::vec2(80.0, 30.0)));
+                            if shape_button.clicked() {
+                                self.show_modal_fonts = false;
+                                self.show_modal_colors = false;
+                                self.show_modal_widths = false;
+                                self.show_modal_export = false;
+                                self.show_modal_view = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = false;
+                                self.show_modal_shapes = !self.show_modal_shapes;
+                                self.egui_context.request_repaint();
+                                self.window.request_redraw();
+                            }
+                            ui.add_space(header_width * 0.03);
+
+                            // Cycles pen, object eraser and partial eraser.
+                            let eraser_label = match self.eraser {
+                                None => "Eraser",
+                                Some(EraserMode::Object) => "Erase objects",
+                                Some(EraserMode::Partial) => "Erase parts",
+                            };
+                            let eraser_button = ui.add(
+                                egui::Button::new(eraser_label)
+                                    .selected(self.eraser.is_some())
+                                    .min_size(egui::vec2(80.0, 30.0)),
+                            );
+                            if eraser_button.clicked() {
+                                self.eraser = match self.eraser {
+                                    None => Some(EraserMode::Object),
+                                    Some(EraserMode::Object) => Some(EraserMode::Partial),
+                                    Some(EraserMode::Partial) => None,
+                                };
+                                self.selection = None;
+                                self.create_rect = false;
+                                self.shape_positions.clear();
+                                self.window.request_redraw();
+                            }
+                            ui.add_space(header_width * 0.03);
+
+                            // Cycles pen, box selection and lasso selection.
+                            let select_label = match &self.selection {
+                                None => "Select",
+                                Some(selection) if selection.mode == SelectionMode::Box => {
+                                    "Select: box"
+                                }
+                                Some(_) => "Select: lasso",
+                            };
+                            let select_button = ui.add(
+                                egui::Button::new(select_label)
+                                    .selected(self.selection.is_some())
+                                    .min_size(egui::vec2(80.0, 30.0)),
+                            );
+                            if select_button.clicked() {
+                                self.selection = match &self.selection {
+                                    None => Some(Selection::new(SelectionMode::Box)),
+                                    Some(selection) if selection.mode == SelectionMode::Box => {
+                                        Some(Selection::new(SelectionMode::Lasso))
+                                    }
+                                    Some(_) => None,
+                                };
+                                self.eraser = None;
+                                self.create_rect = false;
+                                self.shape_positions.clear();
+                                self.window.request_redraw();
                             }
                             ui.add_space(header_width * 0.03);
 
@@ -1243,6 +1514,11 @@ impl WindowState {
                                 if self.show_modal_colors {
                                     self.show_modal_colors = false;
                                 }
+                                self.show_modal_widths = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_view = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = false;
                                 self.show_modal_fonts = true;
                                 self.egui_context.request_repaint();
                                 self.window.request_redraw();
@@ -1260,10 +1536,146 @@ impl WindowState {
                                 if self.show_modal_fonts {
                                     self.show_modal_fonts = false;
                                 }
+                                self.show_modal_widths = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_view = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = false;
                                 self.show_modal_colors = true;
                                 self.egui_context.request_repaint();
                                 self.window.request_redraw();
                             }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let width_button = ui.add(
+                                egui::Button::new(format!("{} px", self.stroke_width))
+                                    .min_size(egui::vec2(80.0, 30.0)),
+                            );
+                            if width_button.clicked() {
+                                self.show_modal_fonts = false;
+                                self.show_modal_colors = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_view = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = false;
+                                self.show_modal_widths = true;
+                                self.egui_context.request_repaint();
+                                self.window.request_redraw();
+                            }
+
+                            // Cycles off, automatic and hold-to-snap recognition.
+                            let recognition_label = match self.shape_recognition {
+                                RecognitionMode::Off => "Recognize: off",
+                                RecognitionMode::Auto => "Recognize: auto",
+                                RecognitionMode::Hold => "Recognize: hold",
+                            };
+                            let recognition_button = ui.add(
+                                egui::Button::new(recognition_label)
+                                    .selected(self.shape_recognition != RecognitionMode::Off)
+                                    .min_size(egui::vec2(80.0, 30.0)),
+                            );
+                            if recognition_button.clicked() {
+                                self.shape_recognition = match self.shape_recognition {
+                                    RecognitionMode::Off => RecognitionMode::Auto,
+                                    RecognitionMode::Auto => RecognitionMode::Hold,
+                                    RecognitionMode::Hold => RecognitionMode::Off,
+                                };
+                                self.window.request_redraw();
+                            }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let paper_button =
+                                ui.add(egui::Button::new("Paper").min_size(egui::vec2(80.0, 30.0)));
+                            if paper_button.clicked() {
+                                self.show_modal_fonts = false;
+                                self.show_modal_colors = false;
+                                self.show_modal_widths = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_export = false;
+                                self.show_modal_view = false;
+                                self.show_modal_slides = false;
+                                self.show_modal_paper = !self.show_modal_paper;
+                                self.egui_context.request_repaint();
+                                self.window.request_redraw();
+                            }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let image_button =
+                                ui.add(egui::Button::new("Image").min_size(egui::vec2(80.0, 30.0)));
+                            if image_button.clicked() {
+                                self.pending_image_file = true;
+                                self.window.request_redraw();
+                            }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let slides_button = ui
+                                .add(egui::Button::new("Slides").min_size(egui::vec2(80.0, 30.0)));
+                            if slides_button.clicked() {
+                                self.show_modal_fonts = false;
+                                self.show_modal_colors = false;
+                                self.show_modal_widths = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_export = false;
+                                self.show_modal_view = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = !self.show_modal_slides;
+                                self.egui_context.request_repaint();
+                                self.window.request_redraw();
+                            }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let view_button =
+                                ui.add(egui::Button::new("View").min_size(egui::vec2(80.0, 30.0)));
+                            if view_button.clicked() {
+                                self.show_modal_fonts = false;
+                                self.show_modal_colors = false;
+                                self.show_modal_widths = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_export = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = false;
+                                self.show_modal_view = !self.show_modal_view;
+                                self.egui_context.request_repaint();
+                                self.window.request_redraw();
+                            }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let export_button = ui
+                                .add(egui::Button::new("Export").min_size(egui::vec2(80.0, 30.0)));
+                            if export_button.clicked() {
+                                self.show_modal_fonts = false;
+                                self.show_modal_colors = false;
+                                self.show_modal_widths = false;
+                                self.show_modal_shapes = false;
+                                self.show_modal_view = false;
+                                self.show_modal_paper = false;
+                                self.show_modal_slides = false;
+                                self.show_modal_export = !self.show_modal_export;
+                                self.egui_context.request_repaint();
+                                self.window.request_redraw();
+                            }
+
+                            ui.add_space(header_width * 0.03);
+
+                            let open_button =
+                                ui.add(egui::Button::new("Open").min_size(egui::vec2(80.0, 30.0)));
+                            if open_button.clicked() {
+                                self.pending_board_file = Some(BoardFileCommand::Open);
+                                self.window.request_redraw();
+                            }
+
+                            let save_button =
+                                ui.add(egui::Button::new("Save").min_size(egui::vec2(80.0, 30.0)));
+                            if save_button.clicked() {
+                                self.pending_board_file = Some(BoardFileCommand::Save);
+                                self.window.request_redraw();
+                            }
                         });
 
                         ui.add_space(10.0 * self.window.scale_factor() as f32);
@@ -1273,6 +1685,17 @@ impl WindowState {
 
         let full_output = self.egui_context.end_pass();
 
+        if std::mem::take(&mut self.undo_button) {
+            self.undo();
+        }
+        if std::mem::take(&mut self.redo_button) {
+            self.redo();
+        }
+
+        if let Some(command) = self.pending_page.take() {
+            self.run_page_command(command);
+        }
+
         let tris = self
             .egui_context
             .tessellate(full_output.shapes, full_output.pixels_per_point);
@@ -1311,10 +1734,18 @@ impl WindowState {
             self.egui_renderer.free_texture(x);
         }
 
-        {
+        if let (Some(rectangle_shader), false) = (&self.rectangle_shader, overlay.is_empty()) {
+            let overlay_vertex_buffer =
+                self.device
+                    .create_buffer_init(&egui_wgpu::wgpu::util::BufferInitDescriptor {
+                        label: Some("Selection Overlay Vertex Buffer"),
+                        contents: bytemuck::cast_slice(&overlay),
+                        usage: egui_wgpu::wgpu::BufferUsages::VERTEX,
+                    });
+
             let mut render_pass =
                 encoder.begin_render_pass(&egui_wgpu::wgpu::RenderPassDescriptor {
-                    label: Some("Text Render Pass"),
+                    label: Some("Selection Overlay Render Pass"),
                     color_attachments: &[Some(egui_wgpu::wgpu::RenderPassColorAttachment {
                         view: &view,
                         resolve_target: None,
@@ -1328,9 +1759,11 @@ impl WindowState {
                     occlusion_query_set: None,
                 });
 
-            self.text_renderer
-                .render(&self.atlas, &self.viewport, &mut render_pass)
-                .unwrap();
+            letterbox.clip_pass(&mut render_pass, target_size);
+            render_pass.set_pipeline(rectangle_shader);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, overlay_vertex_buffer.slice(..));
+            render_pass.draw(0..overlay.len() as u32, 0..1);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));